@@ -0,0 +1,36 @@
+#![no_main]
+use dns_starter_rust::{message::Message, message_ref::MessageRef};
+use libfuzzer_sys::fuzz_target;
+
+// Reads the input every way the server might, none of which may panic. Whatever
+// validates has to survive being decoded and encoded again unchanged.
+fuzz_target!(|data: &[u8]| {
+  let _ = Message::format_error(data);
+  let Ok(view) = MessageRef::parse(data) else {
+    return;
+  };
+  let _ = view.edns();
+  let _ = view.to_string();
+  for record in view.records().flatten() {
+    let _ = record.to_record().map(|record| format!("{record} {record:#}"));
+  }
+  if view.validate().is_err() {
+    return;
+  }
+  let message = view.to_message();
+  let questions = message.questions().unwrap();
+  let (answers, authorities, additionals) = (message.answers().unwrap(), message.authorities().unwrap(), message.additionals().unwrap());
+  let mut encoded = Message::new();
+  encoded.set_compression(false);
+  encoded.set_id(message.id());
+  encoded.set_flags(message.flags());
+  questions.iter().try_for_each(|question| encoded.add_question(question)).unwrap();
+  answers.iter().try_for_each(|record| encoded.add_answer(record)).unwrap();
+  authorities.iter().try_for_each(|record| encoded.add_authority(record)).unwrap();
+  additionals.iter().try_for_each(|record| encoded.add_additional(record)).unwrap();
+  let decoded = Message::parse(&encoded).unwrap();
+  assert_eq!(decoded.questions().unwrap(), questions);
+  assert_eq!(decoded.answers().unwrap(), answers);
+  assert_eq!(decoded.authorities().unwrap(), authorities);
+  assert_eq!(decoded.additionals().unwrap(), additionals);
+});
@@ -0,0 +1,251 @@
+#![allow(dead_code)]
+use crate::{
+  edns::Edns,
+  error::DnsError,
+  header::{Flags, Opcode, Rcode},
+  message::Message,
+  message_ref::MessageRef,
+  name::Name,
+  record::{DnsClass, Question, RecordType, ResourceRecord},
+};
+
+/// Collects the parts of a message and writes them out in section order, so
+/// counts always match and compression pointers only ever point backwards.
+#[derive(Debug, Clone)]
+pub struct MessageBuilder {
+  id: u16,
+  flags: Flags,
+  questions: Vec<Question>,
+  answers: Vec<ResourceRecord>,
+  authorities: Vec<ResourceRecord>,
+  additionals: Vec<ResourceRecord>,
+  edns: Option<Edns>,
+  compression: bool,
+  max_size: Option<usize>,
+}
+
+impl Message {
+  /// A recursive query for `name` with a random ID.
+  pub fn query(name: Name, qtype: RecordType) -> MessageBuilder {
+    Self::query_for(Question::new(name, qtype, DnsClass::IN))
+  }
+
+  /// A recursive query for `question`, of any class, with a random ID.
+  pub fn query_for(question: Question) -> MessageBuilder {
+    MessageBuilder::new(rand::random()).rd(true).question(question)
+  }
+
+  /// A response echoing the ID, opcode, RD and CD bits and questions of `request`.
+  pub fn response_to<'a>(request: impl Into<MessageRef<'a>>) -> Result<MessageBuilder, DnsError> {
+    let request = request.into();
+    let request_flags = request.flags();
+    let flags = Flags {
+      qr: true,
+      opcode: request_flags.opcode,
+      rd: request_flags.rd,
+      cd: request_flags.cd,
+      ..Flags::default()
+    };
+    let mut builder = MessageBuilder::new(request.id()).flags(flags);
+    builder.questions = request.questions().map(|question| Ok(question?.to_question())).collect::<Result<_, DnsError>>()?;
+    Ok(builder)
+  }
+}
+
+impl MessageBuilder {
+  pub fn new(id: u16) -> Self {
+    Self {
+      id,
+      flags: Flags::default(),
+      questions: Vec::new(),
+      answers: Vec::new(),
+      authorities: Vec::new(),
+      additionals: Vec::new(),
+      edns: None,
+      compression: true,
+      max_size: None,
+    }
+  }
+
+  pub fn id(mut self, id: u16) -> Self {
+    self.id = id;
+    self
+  }
+  pub fn flags(mut self, flags: Flags) -> Self {
+    self.flags = flags;
+    self
+  }
+  pub fn opcode(mut self, opcode: Opcode) -> Self {
+    self.flags.opcode = opcode;
+    self
+  }
+  pub fn aa(mut self, aa: bool) -> Self {
+    self.flags.aa = aa;
+    self
+  }
+  pub fn rd(mut self, rd: bool) -> Self {
+    self.flags.rd = rd;
+    self
+  }
+  pub fn ra(mut self, ra: bool) -> Self {
+    self.flags.ra = ra;
+    self
+  }
+  pub fn ad(mut self, ad: bool) -> Self {
+    self.flags.ad = ad;
+    self
+  }
+  pub fn cd(mut self, cd: bool) -> Self {
+    self.flags.cd = cd;
+    self
+  }
+  /// Extended rcodes are split between the header and the OPT record on `build`,
+  /// which adds an OPT record for them when there is no `edns`.
+  pub fn rcode(mut self, rcode: Rcode) -> Self {
+    self.flags.rcode = rcode;
+    self
+  }
+  pub fn current_rcode(&self) -> Rcode {
+    self.flags.rcode
+  }
+  pub fn question(mut self, question: Question) -> Self {
+    self.questions.push(question);
+    self
+  }
+  pub fn answer(mut self, record: ResourceRecord) -> Self {
+    self.answers.push(record);
+    self
+  }
+  pub fn answers(mut self, records: impl IntoIterator<Item = ResourceRecord>) -> Self {
+    self.answers.extend(records);
+    self
+  }
+  pub fn authority(mut self, record: ResourceRecord) -> Self {
+    self.authorities.push(record);
+    self
+  }
+  pub fn authorities(mut self, records: impl IntoIterator<Item = ResourceRecord>) -> Self {
+    self.authorities.extend(records);
+    self
+  }
+  /// OPT records belong in `edns`, and are skipped here.
+  pub fn additional(mut self, record: ResourceRecord) -> Self {
+    if record.rtype() != RecordType::OPT {
+      self.additionals.push(record);
+    }
+    self
+  }
+  pub fn additionals(self, records: impl IntoIterator<Item = ResourceRecord>) -> Self {
+    records.into_iter().fold(self, Self::additional)
+  }
+  pub fn edns(mut self, edns: Option<Edns>) -> Self {
+    self.edns = edns;
+    self
+  }
+  pub fn compression(mut self, compression: bool) -> Self {
+    self.compression = compression;
+    self
+  }
+  /// Largest message `build` may produce. A message that does not fit keeps only
+  /// its questions and OPT record, with TC set so the client retries over TCP.
+  pub fn max_size(mut self, max_size: usize) -> Self {
+    self.max_size = Some(max_size);
+    self
+  }
+
+  pub fn build(self) -> Result<Message, DnsError> {
+    let message = self.write(true)?;
+    match self.max_size {
+      Some(max_size) if message.len() > max_size => {
+        let mut truncated = self.write(false)?;
+        truncated.set_tc(true);
+        Ok(truncated)
+      }
+      _ => Ok(message),
+    }
+  }
+
+  fn write(&self, with_records: bool) -> Result<Message, DnsError> {
+    let mut message = Message::new();
+    message.set_compression(self.compression);
+    message.set_id(self.id);
+    message.set_flags(self.flags);
+    for question in &self.questions {
+      message.add_question(question)?;
+    }
+    if with_records {
+      for answer in &self.answers {
+        message.add_answer(answer)?;
+      }
+      for authority in &self.authorities {
+        message.add_authority(authority)?;
+      }
+      for additional in &self.additionals {
+        message.add_additional(additional)?;
+      }
+    }
+    // Without an OPT record the header alone would turn BADCOOKIE into YXRRSET.
+    let edns = self.edns.clone().or_else(|| (self.flags.rcode.high() != 0).then(Edns::default));
+    if let Some(edns) = edns {
+      message.add_additional(&Edns { extended_rcode: self.flags.rcode.high(), ..edns }.to_record())?;
+    }
+    Ok(message)
+  }
+}
+
+#[test]
+fn test_query() {
+  let query = Message::query("example.com".parse().unwrap(), RecordType::AAAA).edns(Some(Edns::default())).build().unwrap();
+  assert!(query.rd() && !query.is_response());
+  assert_eq!(query.questions().unwrap(), vec![Question::new("example.com".parse().unwrap(), RecordType::AAAA, DnsClass::IN)]);
+  assert_eq!(query.edns().unwrap(), Some(Edns::default()));
+}
+
+#[test]
+fn test_response_to() {
+  let request = Message::query("example.com".parse().unwrap(), RecordType::A).cd(true).id(0x1234).build().unwrap();
+  let response = Message::response_to(&request)
+    .unwrap()
+    .additional("ns1.example.com. 3600 IN A 192.0.2.1".parse().unwrap())
+    .authority("example.com. 3600 IN NS ns1.example.com.".parse().unwrap())
+    .answer("example.com. 3600 IN A 192.0.2.2".parse().unwrap())
+    .additional(Edns::default().to_record())
+    .edns(Some(Edns::default()))
+    .rcode(Rcode::BadCookie)
+    .build().unwrap();
+  assert_eq!(response.id(), 0x1234);
+  assert!(response.is_response() && response.rd() && response.cd());
+  assert_eq!(response.header().answer_count, 1);
+  assert_eq!(response.header().authority_count, 1);
+  assert_eq!(response.header().additional_count, 2);
+  assert_eq!(response.authorities().unwrap()[0].rtype(), RecordType::NS);
+  assert_eq!(response.additionals().unwrap()[0].rtype(), RecordType::A);
+  assert_eq!(response.extended_rcode().unwrap(), Rcode::BadCookie);
+  assert!(response.validate().is_ok());
+}
+
+#[test]
+fn test_extended_rcode_without_edns() {
+  let request = Message::query("example.com".parse().unwrap(), RecordType::A).build().unwrap();
+  let response = Message::response_to(&request).unwrap().rcode(Rcode::BadCookie).build().unwrap();
+  assert_eq!(response.extended_rcode().unwrap(), Rcode::BadCookie);
+  assert_eq!(response.edns().unwrap(), Some(Edns { extended_rcode: Rcode::BadCookie.high(), ..Edns::default() }));
+  let response = Message::response_to(&request).unwrap().rcode(Rcode::Refused).build().unwrap();
+  assert_eq!(response.edns().unwrap(), None);
+}
+
+#[test]
+fn test_build_truncates_to_max_size() {
+  let request = Message::query("example.com".parse().unwrap(), RecordType::TXT).build().unwrap();
+  let text = format!("example.com. 60 IN TXT \"{}\"", "x".repeat(255));
+  let record: ResourceRecord = text.parse().unwrap();
+  let builder = Message::response_to(&request).unwrap().answers(vec![record; 3]).edns(Some(Edns::default()));
+  let response = builder.clone().max_size(4096).build().unwrap();
+  assert!(!response.tc());
+  assert_eq!(response.answers().unwrap().len(), 3);
+  let response = builder.max_size(512).build().unwrap();
+  assert!(response.tc());
+  assert!(response.answers().unwrap().is_empty());
+  assert_eq!(response.questions().unwrap().len(), 1);
+  assert!(response.edns().unwrap().is_some());
+}
@@ -0,0 +1,148 @@
+use std::net::IpAddr;
+
+use anyhow::{bail, Context, Result};
+use dns_starter_rust::tsig::Key;
+
+/// Server settings taken from the command line.
+#[derive(Debug, Clone)]
+pub struct Config {
+  /// Upstream resolver to forward questions to, e.g. `8.8.8.8:53`.
+  pub resolver: Option<String>,
+  /// Whether responses use name compression; `--no-compression` turns it off for debugging.
+  pub compression: bool,
+  /// Whether logs show internationalized names in Unicode rather than as A-labels.
+  pub unicode_names: bool,
+  /// Whether logs show messages as RFC 8427 JSON, one per line, instead of `dig` style.
+  pub json: bool,
+  /// Master files of local zones, answered before anything is forwarded.
+  pub zone_files: Vec<String>,
+  /// Master files of DS or DNSKEY records that forwarded answers are validated against.
+  pub trust_anchor_files: Vec<String>,
+  /// TSIG keys that requests may be signed with; their responses are signed too.
+  pub tsig_keys: Vec<Key>,
+  /// Name of the TSIG key that signs questions forwarded to the resolver.
+  pub resolver_key: Option<String>,
+  /// Resolvers trusted with the client's subnet (RFC 7871); others never see it.
+  pub client_subnet_resolvers: Vec<IpAddr>,
+  /// How many bits of an IPv4 client address go upstream.
+  pub client_subnet_ipv4_prefix: u8,
+  /// How many bits of an IPv6 client address go upstream.
+  pub client_subnet_ipv6_prefix: u8,
+}
+
+impl Default for Config {
+  fn default() -> Self {
+    Self {
+      resolver: None,
+      compression: true,
+      unicode_names: false,
+      json: false,
+      zone_files: Vec::new(),
+      trust_anchor_files: Vec::new(),
+      tsig_keys: Vec::new(),
+      resolver_key: None,
+      client_subnet_resolvers: Vec::new(),
+      // What RFC 7871 section 11.1 recommends.
+      client_subnet_ipv4_prefix: 24,
+      client_subnet_ipv6_prefix: 56,
+    }
+  }
+}
+
+impl Config {
+  pub fn from_args(mut args: impl Iterator<Item = String>) -> Result<Self> {
+    let mut config = Self::default();
+    while let Some(arg) = args.next() {
+      match arg.as_str() {
+        "--resolver" => config.resolver = Some(args.next().context("--resolver needs an address")?),
+        "--zone" => config.zone_files.push(args.next().context("--zone needs a file")?),
+        "--trust-anchor" => config.trust_anchor_files.push(args.next().context("--trust-anchor needs a file")?),
+        "--tsig-key" => {
+          let key = args.next().context("--tsig-key needs [algorithm:]name:secret")?;
+          config.tsig_keys.push(key.parse().context("Failed to parse --tsig-key")?);
+        }
+        "--resolver-key" => config.resolver_key = Some(args.next().context("--resolver-key needs a key name")?),
+        "--client-subnet-resolver" => {
+          let address = args.next().context("--client-subnet-resolver needs an IP address")?;
+          config.client_subnet_resolvers.push(address.parse().with_context(|| format!("{address:?} is not an IP address"))?);
+        }
+        "--client-subnet-ipv4-prefix" => config.client_subnet_ipv4_prefix = prefix_length(args.next(), "--client-subnet-ipv4-prefix", 32)?,
+        "--client-subnet-ipv6-prefix" => config.client_subnet_ipv6_prefix = prefix_length(args.next(), "--client-subnet-ipv6-prefix", 128)?,
+        "--no-compression" => config.compression = false,
+        "--unicode-names" => config.unicode_names = true,
+        "--json" if cfg!(feature = "json") => config.json = true,
+        "--json" => bail!("--json needs the server built with the json feature"),
+        other => bail!("unknown argument: {other}"),
+      }
+    }
+    if !config.trust_anchor_files.is_empty() && config.resolver.is_none() {
+      bail!("--trust-anchor needs --resolver");
+    }
+    if !config.client_subnet_resolvers.is_empty() && config.resolver.is_none() {
+      bail!("--client-subnet-resolver needs --resolver");
+    }
+    if let Some(name) = &config.resolver_key {
+      if config.resolver.is_none() {
+        bail!("--resolver-key needs --resolver");
+      }
+      if config.resolver_key().is_none() {
+        bail!("--resolver-key {name} is not one of the --tsig-key keys");
+      }
+    }
+    Ok(config)
+  }
+
+  /// How many bits of `address` go upstream as its client subnet.
+  pub fn client_subnet_prefix(&self, address: IpAddr) -> u8 {
+    match address {
+      IpAddr::V4(_) => self.client_subnet_ipv4_prefix,
+      IpAddr::V6(_) => self.client_subnet_ipv6_prefix,
+    }
+  }
+
+  /// The key named by `--resolver-key`.
+  pub fn resolver_key(&self) -> Option<&Key> {
+    let name = self.resolver_key.as_ref()?.parse().ok()?;
+    self.tsig_keys.iter().find(|key| key.name == name)
+  }
+}
+
+fn prefix_length(arg: Option<String>, flag: &str, max: u8) -> Result<u8> {
+  let bits = arg.with_context(|| format!("{flag} needs a number of bits"))?;
+  match bits.parse() {
+    Ok(bits) if bits <= max => Ok(bits),
+    _ => bail!("{flag} takes 0 to {max} bits, not {bits:?}"),
+  }
+}
+
+#[test]
+fn test_config_from_args() {
+  let args = ["--resolver", "8.8.8.8:53", "--no-compression"].map(String::from);
+  let config = Config::from_args(args.into_iter()).unwrap();
+  assert_eq!(config.resolver.as_deref(), Some("8.8.8.8:53"));
+  assert!(!config.compression);
+  assert!(!config.unicode_names);
+  assert!(Config::from_args(["--unicode-names".to_string()].into_iter()).unwrap().unicode_names);
+  assert!(Config::from_args(["--resolver".to_string()].into_iter()).is_err());
+  let args = ["--zone", "a.zone", "--zone", "b.zone"].map(String::from);
+  assert_eq!(Config::from_args(args.into_iter()).unwrap().zone_files, ["a.zone", "b.zone"]);
+  assert!(Config::from_args(["--zone".to_string()].into_iter()).is_err());
+  let args = ["--resolver", "8.8.8.8:53", "--trust-anchor", "root.key"].map(String::from);
+  assert_eq!(Config::from_args(args.into_iter()).unwrap().trust_anchor_files, ["root.key"]);
+  assert!(Config::from_args(["--trust-anchor", "root.key"].map(String::from).into_iter()).is_err());
+  let args = ["--tsig-key", "hmac-sha512:xfr.example.:c2VjcmV0", "--resolver", "8.8.8.8:53", "--resolver-key", "xfr.example"];
+  let config = Config::from_args(args.map(String::from).into_iter()).unwrap();
+  assert_eq!(config.tsig_keys.len(), 1);
+  assert_eq!(config.resolver_key().unwrap().name, "xfr.example.".parse().unwrap());
+  assert!(Config::from_args(["--tsig-key", "xfr.example.:not base64"].map(String::from).into_iter()).is_err());
+  assert!(Config::from_args(["--resolver", "8.8.8.8:53", "--resolver-key", "xfr.example."].map(String::from).into_iter()).is_err());
+  let args = ["--resolver", "192.0.2.53:53", "--client-subnet-resolver", "192.0.2.53", "--client-subnet-ipv4-prefix", "20"];
+  let config = Config::from_args(args.map(String::from).into_iter()).unwrap();
+  assert_eq!(config.client_subnet_resolvers, ["192.0.2.53".parse::<IpAddr>().unwrap()]);
+  assert_eq!(config.client_subnet_prefix("198.51.100.1".parse().unwrap()), 20);
+  assert_eq!(config.client_subnet_prefix("2001:db8::1".parse().unwrap()), 56);
+  assert!(Config::from_args(["--client-subnet-ipv4-prefix", "33"].map(String::from).into_iter()).is_err());
+  assert!(Config::from_args(["--client-subnet-resolver", "192.0.2.53"].map(String::from).into_iter()).is_err());
+  assert!(Config::from_args(["--bogus".to_string()].into_iter()).is_err());
+  assert_eq!(Config::from_args(["--json".to_string()].into_iter()).is_ok(), cfg!(feature = "json"));
+}
@@ -0,0 +1,194 @@
+#![allow(dead_code)]
+//! DNS cookies (RFC 7873): a client cookie that the server echoes, and a server
+//! cookie, made as RFC 9018 describes, that shows a client really is at its address.
+use std::{cell::RefCell, collections::HashMap, hash::Hasher, net::IpAddr};
+
+use siphasher::sip::SipHasher24;
+
+use crate::{
+  edns::{Edns, EdnsOption},
+  error::DnsError,
+};
+
+/// How long a server cookie is accepted for, and how often the secret changes.
+pub const COOKIE_LIFETIME: u32 = 3600;
+/// How far ahead of our clock a server cookie's timestamp may be.
+const CLOCK_SKEW: u32 = 300;
+/// The only server cookie version (RFC 9018 section 4.2).
+const VERSION: u8 = 1;
+
+fn siphash(secret: &[u8; 16], data: &[u8]) -> [u8; 8] {
+  let mut hasher = SipHasher24::new_with_key(secret);
+  hasher.write(data);
+  hasher.finish().to_le_bytes()
+}
+
+fn ip_bytes(ip: IpAddr) -> Vec<u8> {
+  match ip {
+    IpAddr::V4(ip) => ip.octets().to_vec(),
+    IpAddr::V6(ip) => ip.octets().to_vec(),
+  }
+}
+
+/// Version, three reserved bytes, timestamp and SipHash-2-4 of them all with the
+/// client cookie and address.
+fn server_cookie(secret: &[u8; 16], client: &[u8; 8], client_ip: IpAddr, timestamp: u32) -> Vec<u8> {
+  let mut cookie = vec![VERSION, 0, 0, 0];
+  cookie.extend(timestamp.to_be_bytes());
+  let hash = siphash(secret, &[&client[..], &cookie, &ip_bytes(client_ip)].concat());
+  cookie.extend(hash);
+  cookie
+}
+
+/// Compares without giving away, by how long it takes, how much matched.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+  a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// The server side: makes server cookies, and checks the ones clients send back.
+/// The secret changes every `COOKIE_LIFETIME`, and cookies made with the one
+/// before still pass, so none go bad early.
+pub struct ServerCookies {
+  secret: [u8; 16],
+  previous: Option<[u8; 16]>,
+  rotated_at: u64,
+}
+
+impl ServerCookies {
+  /// With a random secret, at `now` in seconds since the epoch.
+  pub fn new(now: u64) -> Self {
+    Self::with_secret(rand::random(), now)
+  }
+  pub fn with_secret(secret: [u8; 16], now: u64) -> Self {
+    Self { secret, previous: None, rotated_at: now }
+  }
+  /// Moves on to a new random secret once the current one is `COOKIE_LIFETIME` old.
+  pub fn rotate(&mut self, now: u64) {
+    if now.saturating_sub(self.rotated_at) >= u64::from(COOKIE_LIFETIME) {
+      self.previous = Some(std::mem::replace(&mut self.secret, rand::random()));
+      self.rotated_at = now;
+    }
+  }
+  /// A server cookie for the client at `client_ip`, good for `COOKIE_LIFETIME`.
+  pub fn make(&self, client: &[u8; 8], client_ip: IpAddr, now: u64) -> Vec<u8> {
+    server_cookie(&self.secret, client, client_ip, now as u32)
+  }
+  /// Whether `server` is a cookie we made for this client and address, recently.
+  pub fn check(&self, client: &[u8; 8], server: &[u8], client_ip: IpAddr, now: u64) -> bool {
+    if server.len() != 16 || server[0] != VERSION {
+      return false;
+    }
+    let timestamp = u32::from_be_bytes([server[4], server[5], server[6], server[7]]);
+    // Serial number arithmetic, as the 32 bit timestamp wraps in 2106.
+    let age = (now as u32).wrapping_sub(timestamp);
+    if age > COOKIE_LIFETIME && age.wrapping_neg() > CLOCK_SKEW {
+      return false;
+    }
+    [Some(&self.secret), self.previous.as_ref()]
+      .into_iter()
+      .flatten()
+      .any(|secret| constant_time_eq(&server_cookie(secret, client, client_ip, timestamp), server))
+  }
+}
+
+/// The client side: a client cookie for each server, from a secret of our own,
+/// and the server cookie each one last sent back.
+pub struct ClientCookies {
+  secret: [u8; 16],
+  servers: RefCell<HashMap<IpAddr, Vec<u8>>>,
+}
+
+impl ClientCookies {
+  pub fn new() -> Self {
+    Self { secret: rand::random(), servers: RefCell::default() }
+  }
+  fn client_cookie(&self, server_ip: IpAddr) -> [u8; 8] {
+    siphash(&self.secret, &ip_bytes(server_ip))
+  }
+  /// The COOKIE option for a query to `server_ip`.
+  pub fn option(&self, server_ip: IpAddr) -> EdnsOption {
+    let server = self.servers.borrow().get(&server_ip).cloned().unwrap_or_default();
+    EdnsOption::Cookie { client: self.client_cookie(server_ip), server }
+  }
+  /// Checks that a response from `server_ip` echoes our client cookie, and keeps
+  /// its server cookie for next time. Servers that do not do cookies send none,
+  /// but one that has given us a cookie before must keep sending it (RFC 7873 §5.3).
+  pub fn learn(&self, server_ip: IpAddr, edns: Option<&Edns>) -> Result<(), DnsError> {
+    let Some((client, server)) = edns.and_then(Edns::cookie) else {
+      if self.servers.borrow().contains_key(&server_ip) {
+        return Err(DnsError::CookieMismatch);
+      }
+      return Ok(());
+    };
+    if *client != self.client_cookie(server_ip) {
+      return Err(DnsError::CookieMismatch);
+    }
+    if !server.is_empty() {
+      self.servers.borrow_mut().insert(server_ip, server.to_vec());
+    }
+    Ok(())
+  }
+}
+
+impl Default for ClientCookies {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+#[cfg(test)]
+fn from_hex<const N: usize>(text: &str) -> [u8; N] {
+  data_encoding::HEXLOWER.decode(text.as_bytes()).unwrap().try_into().unwrap()
+}
+
+#[test]
+fn test_server_cookie() {
+  // RFC 9018 appendix A.1.
+  let client = from_hex("2464c4abcf10c957");
+  let client_ip = "198.51.100.100".parse().unwrap();
+  let cookies = ServerCookies::with_secret(from_hex("e5e973e5a6b2a43f48e7dc849e37bfcf"), 1559731985);
+  let server = cookies.make(&client, client_ip, 1559731985);
+  assert_eq!(server, from_hex::<16>("010000005cf79f111f8130c3eee29480"));
+  assert!(cookies.check(&client, &server, client_ip, 1559731985 + 10));
+  assert!(cookies.check(&client, &server, client_ip, 1559731985 - 200));
+  assert!(!cookies.check(&client, &server, client_ip, 1559731985 + 3601));
+  assert!(!cookies.check(&client, &server, client_ip, 1559731985 - 301));
+  assert!(!cookies.check(&client, &server, "198.51.100.101".parse().unwrap(), 1559731985));
+  assert!(!cookies.check(&from_hex("2464c4abcf10c958"), &server, client_ip, 1559731985));
+  assert!(!cookies.check(&client, &server[..8], client_ip, 1559731985));
+}
+
+#[test]
+fn test_server_cookie_rotation() {
+  let now = 1_700_000_000;
+  let mut cookies = ServerCookies::new(now);
+  let (client, client_ip) = ([1; 8], "2001:db8::1".parse().unwrap());
+  let server = cookies.make(&client, client_ip, now);
+  cookies.rotate(now + 60);
+  assert_eq!(cookies.make(&client, client_ip, now), server);
+  // One rotation later the old cookie still passes, until it is too old anyway.
+  cookies.rotate(now + 3600);
+  assert_ne!(cookies.make(&client, client_ip, now), server);
+  assert!(cookies.check(&client, &server, client_ip, now + 3600));
+  cookies.rotate(now + 7200);
+  assert!(!cookies.check(&client, &server, client_ip, now + 3600));
+}
+
+#[test]
+fn test_client_cookies() {
+  let cookies = ClientCookies::new();
+  let (server_ip, other_ip) = ("192.0.2.53".parse().unwrap(), "192.0.2.54".parse().unwrap());
+  let EdnsOption::Cookie { client, server } = cookies.option(server_ip) else {
+    unreachable!()
+  };
+  assert!(server.is_empty());
+  assert_ne!(cookies.option(other_ip), cookies.option(server_ip));
+  let response = Edns { options: vec![EdnsOption::Cookie { client, server: vec![7; 16] }], ..Edns::default() };
+  cookies.learn(server_ip, Some(&response)).unwrap();
+  assert_eq!(cookies.option(server_ip), EdnsOption::Cookie { client, server: vec![7; 16] });
+  assert!(matches!(cookies.learn(other_ip, Some(&response)), Err(DnsError::CookieMismatch)));
+  cookies.learn(other_ip, Some(&Edns::default())).unwrap();
+  cookies.learn(other_ip, None).unwrap();
+  assert!(matches!(cookies.learn(server_ip, Some(&Edns::default())), Err(DnsError::CookieMismatch)));
+  assert!(matches!(cookies.learn(server_ip, None), Err(DnsError::CookieMismatch)));
+}
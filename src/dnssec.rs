@@ -0,0 +1,223 @@
+#![allow(dead_code)]
+//! DNSSEC helpers (RFC 4034): key tags, type bitmaps, and the canonical form
+//! records are signed and verified in.
+use ring::digest;
+
+use crate::{
+  error::DnsError,
+  message::encode_rdata,
+  name::Name,
+  record::{RData, RecordType, ResourceRecord},
+};
+
+/// DNSKEY flag for keys that may sign zone data.
+pub const ZONE_KEY: u16 = 0x0100;
+/// DNSKEY flag marking a key signing key, the one DS records point at.
+pub const SECURE_ENTRY_POINT: u16 = 0x0001;
+/// The only DNSKEY protocol there is.
+pub const PROTOCOL: u8 = 3;
+
+pub const RSASHA1: u8 = 5;
+pub const RSASHA256: u8 = 8;
+pub const RSASHA512: u8 = 10;
+pub const ECDSAP256SHA256: u8 = 13;
+pub const ECDSAP384SHA384: u8 = 14;
+pub const ED25519: u8 = 15;
+
+pub const DIGEST_SHA1: u8 = 1;
+pub const DIGEST_SHA256: u8 = 2;
+pub const DIGEST_SHA384: u8 = 4;
+
+/// NSEC3 flag for a span that may hold insecure delegations.
+pub const OPT_OUT: u8 = 0x01;
+pub const NSEC3_SHA1: u8 = 1;
+
+impl RData {
+  /// The key tag of a DNSKEY (RFC 4034 appendix B), which RRSIG and DS records use
+  /// to point at it. `None` for anything else.
+  pub fn key_tag(&self) -> Option<u16> {
+    let RData::DNSKEY { public_key, algorithm, .. } = self else {
+      return None;
+    };
+    // Algorithm 1 (RSA/MD5) takes the tag from the modulus instead.
+    if *algorithm == 1 {
+      let tail = public_key.len().checked_sub(3)?;
+      return Some(u16::from_be_bytes([public_key[tail], public_key[tail + 1]]));
+    }
+    let sum = encode_rdata(self)
+      .ok()?
+      .iter()
+      .enumerate()
+      .fold(0u32, |sum, (i, &byte)| sum + if i % 2 == 0 { (byte as u32) << 8 } else { byte as u32 });
+    Some((sum + (sum >> 16)) as u16)
+  }
+
+  /// The canonical form of RFC 4034 section 6.2: names in the rdata types listed
+  /// there are lowercased. The next name of NSEC is left alone (RFC 6840 section 5.1).
+  pub fn to_canonical(&self) -> RData {
+    match self {
+      RData::NS(name) => RData::NS(name.to_lowercase()),
+      RData::CNAME(name) => RData::CNAME(name.to_lowercase()),
+      RData::PTR(name) => RData::PTR(name.to_lowercase()),
+      RData::MX { preference, exchange } => RData::MX { preference: *preference, exchange: exchange.to_lowercase() },
+      RData::SOA { mname, rname, serial, refresh, retry, expire, minimum } => RData::SOA {
+        mname: mname.to_lowercase(),
+        rname: rname.to_lowercase(),
+        serial: *serial,
+        refresh: *refresh,
+        retry: *retry,
+        expire: *expire,
+        minimum: *minimum,
+      },
+      RData::SRV { priority, weight, port, target } => RData::SRV { priority: *priority, weight: *weight, port: *port, target: target.to_lowercase() },
+      RData::RRSIG { type_covered, algorithm, labels, original_ttl, expiration, inception, key_tag, signer_name, signature } => RData::RRSIG {
+        type_covered: *type_covered,
+        algorithm: *algorithm,
+        labels: *labels,
+        original_ttl: *original_ttl,
+        expiration: *expiration,
+        inception: *inception,
+        key_tag: *key_tag,
+        signer_name: signer_name.to_lowercase(),
+        signature: signature.clone(),
+      },
+      rdata => rdata.clone(),
+    }
+  }
+}
+
+impl ResourceRecord {
+  /// The owner name lowercased, and the rdata in canonical form.
+  pub fn to_canonical(&self) -> ResourceRecord {
+    ResourceRecord::new(self.name.to_lowercase(), self.class, self.ttl, self.rdata.to_canonical())
+  }
+}
+
+impl ResourceRecord {
+  /// The DS record pointing at this DNSKEY (RFC 4034 section 5.1.4), or `None`
+  /// for other records and unsupported digest types.
+  pub fn to_ds(&self, digest_type: u8) -> Option<RData> {
+    let RData::DNSKEY { algorithm, .. } = &self.rdata else {
+      return None;
+    };
+    let algorithm_digest = match digest_type {
+      DIGEST_SHA1 => &digest::SHA1_FOR_LEGACY_USE_ONLY,
+      DIGEST_SHA256 => &digest::SHA256,
+      DIGEST_SHA384 => &digest::SHA384,
+      _ => return None,
+    };
+    let data = [self.name.to_lowercase().to_wire(), encode_rdata(&self.rdata).ok()?].concat();
+    Some(RData::DS {
+      key_tag: self.rdata.key_tag()?,
+      algorithm: *algorithm,
+      digest_type,
+      digest: digest::digest(algorithm_digest, &data).as_ref().to_vec(),
+    })
+  }
+}
+
+/// The hashed owner name NSEC3 records use for `name` (RFC 5155 section 5), or
+/// `None` for hash algorithms other than SHA-1.
+pub fn nsec3_hash(name: &Name, hash_algorithm: u8, iterations: u16, salt: &[u8]) -> Option<Vec<u8>> {
+  if hash_algorithm != NSEC3_SHA1 {
+    return None;
+  }
+  let mut hash = name.to_lowercase().to_wire();
+  for _ in 0..=iterations {
+    hash = digest::digest(&digest::SHA1_FOR_LEGACY_USE_ONLY, &[&hash[..], salt].concat()).as_ref().to_vec();
+  }
+  Some(hash)
+}
+
+/// `records` in canonical form and order (RFC 4034 section 6.3): sorted by their
+/// uncompressed canonical rdata, with duplicates removed.
+pub fn canonical_rrset(records: &[ResourceRecord]) -> Result<Vec<ResourceRecord>, DnsError> {
+  let mut records =
+    records.iter().map(|record| Ok((encode_rdata(&record.rdata.to_canonical())?, record.to_canonical()))).collect::<Result<Vec<_>, DnsError>>()?;
+  records.sort_by(|(a, _), (b, _)| a.cmp(b));
+  records.dedup_by(|(a, _), (b, _)| a == b);
+  Ok(records.into_iter().map(|(_, record)| record).collect())
+}
+
+/// The type bitmap of NSEC and NSEC3 records (RFC 4034 section 4.1.2): a window
+/// for each block of 256 types in use, holding a bit for each type in it.
+pub fn encode_type_bitmap(types: &[RecordType]) -> Vec<u8> {
+  let mut types: Vec<u16> = types.iter().map(|&rtype| rtype.into()).collect();
+  types.sort_unstable();
+  types.dedup();
+  let mut bitmap = Vec::new();
+  let mut rest = &types[..];
+  while let Some(&first) = rest.first() {
+    let (window, next) = rest.split_at(rest.iter().position(|rtype| rtype >> 8 != first >> 8).unwrap_or(rest.len()));
+    rest = next;
+    let mut bits = [0u8; 32];
+    for &rtype in window {
+      bits[(rtype & 0xff) as usize / 8] |= 0x80 >> (rtype % 8);
+    }
+    let length = (window[window.len() - 1] & 0xff) as usize / 8 + 1;
+    bitmap.push((window[0] >> 8) as u8);
+    bitmap.push(length as u8);
+    bitmap.extend_from_slice(&bits[..length]);
+  }
+  bitmap
+}
+
+#[test]
+fn test_key_tag() {
+  use data_encoding::BASE64;
+  // The KSK of the root zone, tag 20326.
+  let public_key = BASE64
+    .decode(
+      b"AwEAAaz/tAm8yTn4Mfeh5eyI96WSVexTBAvkMgJzkKTOiW1vkIbzxeF3+/4RgWOq7HrxRixHlFlExOLAJr5emLvN7SWXgnLh4+B5xQlNVz8Og8kvArMtNROxVQuCaSnIDdD5LKyWbRd2n9WGe2R8PzgCmr3EgVLrjyBxWezF0jLHwVN8efS3rCj/EWgvIWgb9tarpVUDK/b58Da+sqqls3eNbuv7pr+eoZG+SrDK6nWeL3c6H5Apxz7LjVc1uTIdsIXxuOLYA4/ilBmSVIzuDWfdRUfhHdY6+cn8HFRm+2hM8AnXGXws9555KrUB5qihylGa8subX2Nn6UwNR1AkUTV74bU=",
+    )
+    .unwrap();
+  let dnskey = RData::DNSKEY { flags: ZONE_KEY | SECURE_ENTRY_POINT, protocol: PROTOCOL, algorithm: RSASHA256, public_key };
+  assert_eq!(dnskey.key_tag(), Some(20326));
+  assert_eq!(RData::NS(crate::name::Name::root()).key_tag(), None);
+}
+
+#[test]
+fn test_to_ds() {
+  // The example of RFC 4034 section 5.4.
+  let dnskey: ResourceRecord = "dskey.example.com. 86400 IN DNSKEY 256 3 5 AQOeiiR0GOMYkDshWoSKz9XzfwJr1AYtsmx3TGkJaNXVbfi/2pHm822aJ5iI9BMzNXxeYCmZDRD99WYwYqUSdjMmmAphXdvxegXd/M5+X7OrzKBaMbCVdFLUUh6DhweJBjEVv5f2wwjM9XzcnOf+EPbtG9DMBmADjFDc2w/rljwvFw=="
+    .parse()
+    .unwrap();
+  let ds = RData::from_text(RecordType::DS, "60485 5 1 2BB183AF5F22588179A53B0A98631FAD1A292118").unwrap();
+  assert_eq!(dnskey.to_ds(DIGEST_SHA1), Some(ds));
+  assert!(matches!(dnskey.to_ds(DIGEST_SHA384), Some(RData::DS { digest, .. }) if digest.len() == 48));
+  assert_eq!(dnskey.to_ds(3), None);
+}
+
+#[test]
+fn test_nsec3_hash() {
+  // From the example zone of RFC 5155 appendix A.
+  use data_encoding::BASE32HEX_NOPAD;
+  let hash = |name: &str| BASE32HEX_NOPAD.encode(&nsec3_hash(&name.parse().unwrap(), NSEC3_SHA1, 12, b"\xaa\xbb\xcc\xdd").unwrap()).to_ascii_lowercase();
+  assert_eq!(hash("example"), "0p9mhaveqvm6t7vbl5lop2u3t2rp3tom");
+  assert_eq!(hash("a.example"), "35mthgpgcu1qg68fab165klnsnk3dpvl");
+  assert_eq!(nsec3_hash(&Name::root(), 2, 0, &[]), None);
+}
+
+#[test]
+fn test_type_bitmap() {
+  // The example of RFC 4034 section 4.3.
+  let types = [RecordType::A, RecordType::MX, RecordType::RRSIG, RecordType::NSEC, RecordType::Unknown(1234)];
+  let expected = [&b"\x00\x06\x40\x01\x00\x00\x00\x03\x04\x1b"[..], &[0; 26], b"\x20"].concat();
+  assert_eq!(encode_type_bitmap(&types), expected);
+  assert!(encode_type_bitmap(&[]).is_empty());
+}
+
+#[test]
+fn test_canonical_rrset() {
+  let records: Vec<ResourceRecord> = ["Example.COM. 60 IN MX 20 Mail.Example.com.", "example.com. 60 IN MX 10 b.example.com.", "example.com. 60 IN MX 10 B.EXAMPLE.COM."]
+    .iter()
+    .map(|text| text.parse().unwrap())
+    .collect();
+  let canonical = canonical_rrset(&records).unwrap();
+  assert_eq!(canonical.len(), 2);
+  assert_eq!(canonical[0].to_string(), "example.com.\t60\tIN\tMX\t10 b.example.com.");
+  assert_eq!(canonical[1].to_string(), "example.com.\t60\tIN\tMX\t20 mail.example.com.");
+  // The next name of NSEC keeps its case.
+  let nsec = RData::NSEC { next_domain: "B.example.".parse().unwrap(), types: vec![RecordType::A] };
+  assert_eq!(nsec.to_canonical().to_string(), "B.example. A");
+}
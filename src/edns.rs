@@ -0,0 +1,234 @@
+#![allow(dead_code)]
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use crate::{
+  header::Rcode,
+  name::Name,
+  record::{DnsClass, RData, ResourceRecord},
+};
+
+/// The only EDNS version there is (RFC 6891).
+pub const EDNS_VERSION: u8 = 0;
+/// What we advertise, and so the size of our receive buffers.
+pub const MAX_UDP_PAYLOAD_SIZE: u16 = 4096;
+/// Responses never need to be cut below the pre-EDNS limit, whatever the client advertises.
+pub const MIN_UDP_PAYLOAD_SIZE: u16 = 512;
+
+const DNSSEC_OK: u32 = 0b1000_0000_0000_0000;
+
+/// Option code of EDNS Client Subnet (RFC 7871).
+pub const CLIENT_SUBNET: u16 = 8;
+/// Option code of DNS cookies (RFC 7873).
+pub const COOKIE: u16 = 10;
+/// Option code of Extended DNS Errors (RFC 8914).
+pub const EXTENDED_ERROR: u16 = 15;
+
+/// The Extended DNS Error info codes (RFC 8914 section 4) this server sends, by
+/// the names in `EDE_NAMES`. With no answer cache and no blocklist, it never
+/// has a Stale Answer or a Blocked query to report.
+pub const EDE_OTHER: u16 = 0;
+pub const EDE_DNSSEC_BOGUS: u16 = 6;
+pub const EDE_NOT_SUPPORTED: u16 = 21;
+pub const EDE_NETWORK_ERROR: u16 = 23;
+pub const EDE_NAMES: [&str; 25] = [
+  "Other Error",
+  "Unsupported DNSKEY Algorithm",
+  "Unsupported DS Digest Type",
+  "Stale Answer",
+  "Forged Answer",
+  "DNSSEC Indeterminate",
+  "DNSSEC Bogus",
+  "Signature Expired",
+  "Signature Not Yet Valid",
+  "DNSKEY Missing",
+  "RRSIGs Missing",
+  "No Zone Key Bit Set",
+  "NSEC Missing",
+  "Cached Error",
+  "Not Ready",
+  "Blocked",
+  "Censored",
+  "Filtered",
+  "Prohibited",
+  "Stale NXDOMAIN Answer",
+  "Not Authoritative",
+  "Not Supported",
+  "No Reachable Authority",
+  "Network Error",
+  "Invalid Data",
+];
+
+/// The network a query comes from, as a resolver tells an authority that answers
+/// differently by location (RFC 7871). The scope is how much of it the answer
+/// depended on, and is 0 in queries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientSubnet {
+  pub address: IpAddr,
+  pub source_prefix: u8,
+  pub scope_prefix: u8,
+}
+
+impl ClientSubnet {
+  /// The subnet of `address` with `source_prefix` bits, the rest zeroed.
+  pub fn new(address: IpAddr, source_prefix: u8) -> Self {
+    let source_prefix = source_prefix.min(Self::max_prefix(address));
+    let address = match address {
+      IpAddr::V4(ip) => IpAddr::V4(Ipv4Addr::from(u32::from(ip) & u32::MAX.checked_shl(32 - u32::from(source_prefix)).unwrap_or(0))),
+      IpAddr::V6(ip) => IpAddr::V6(Ipv6Addr::from(u128::from(ip) & u128::MAX.checked_shl(128 - u32::from(source_prefix)).unwrap_or(0))),
+    };
+    Self { address, source_prefix, scope_prefix: 0 }
+  }
+  fn max_prefix(address: IpAddr) -> u8 {
+    if address.is_ipv4() { 32 } else { 128 }
+  }
+  /// The address family, as IANA numbers them.
+  pub fn family(&self) -> u16 {
+    if self.address.is_ipv4() { 1 } else { 2 }
+  }
+  /// The address cut to the bytes the source prefix covers, as it goes on the wire.
+  pub fn address_bytes(&self) -> Vec<u8> {
+    let octets = match self.address {
+      IpAddr::V4(ip) => ip.octets().to_vec(),
+      IpAddr::V6(ip) => ip.octets().to_vec(),
+    };
+    octets[..(usize::from(self.source_prefix) + 7) / 8].to_vec()
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EdnsOption {
+  ClientSubnet(ClientSubnet),
+  /// The client's cookie, and the server's from an earlier response, which is
+  /// empty or 8 to 32 bytes.
+  Cookie { client: [u8; 8], server: Vec<u8> },
+  /// Why a response failed, or what else went wrong on the way to it.
+  ExtendedError { info_code: u16, extra_text: String },
+  Unknown { code: u16, data: Vec<u8> },
+}
+
+impl EdnsOption {
+  pub fn code(&self) -> u16 {
+    match self {
+      EdnsOption::ClientSubnet(_) => CLIENT_SUBNET,
+      EdnsOption::Cookie { .. } => COOKIE,
+      EdnsOption::ExtendedError { .. } => EXTENDED_ERROR,
+      EdnsOption::Unknown { code, .. } => *code,
+    }
+  }
+  /// The option's payload as it goes on the wire, after the code and length.
+  pub fn data(&self) -> Vec<u8> {
+    match self {
+      EdnsOption::ClientSubnet(subnet) => {
+        [&subnet.family().to_be_bytes()[..], &[subnet.source_prefix, subnet.scope_prefix], &subnet.address_bytes()].concat()
+      }
+      EdnsOption::Cookie { client, server } => [&client[..], server].concat(),
+      EdnsOption::ExtendedError { info_code, extra_text } => [&info_code.to_be_bytes()[..], extra_text.as_bytes()].concat(),
+      EdnsOption::Unknown { data, .. } => data.clone(),
+    }
+  }
+}
+
+/// The contents of an OPT pseudo-record, whose class and TTL fields carry EDNS
+/// parameters instead of their usual meaning.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edns {
+  pub udp_payload_size: u16,
+  /// Upper 8 bits of the 12 bit rcode.
+  pub extended_rcode: u8,
+  pub version: u8,
+  pub dnssec_ok: bool,
+  pub options: Vec<EdnsOption>,
+}
+
+impl Default for Edns {
+  fn default() -> Self {
+    Self {
+      udp_payload_size: MAX_UDP_PAYLOAD_SIZE,
+      extended_rcode: 0,
+      version: EDNS_VERSION,
+      dnssec_ok: false,
+      options: Vec::new(),
+    }
+  }
+}
+
+impl Edns {
+  /// `None` unless `record` is an OPT record.
+  pub fn from_record(record: &ResourceRecord) -> Option<Self> {
+    let RData::OPT(options) = &record.rdata else {
+      return None;
+    };
+    Some(Self {
+      udp_payload_size: record.class.into(),
+      extended_rcode: (record.ttl >> 24) as u8,
+      version: (record.ttl >> 16) as u8,
+      dnssec_ok: record.ttl & DNSSEC_OK != 0,
+      options: options.clone(),
+    })
+  }
+
+  pub fn to_record(&self) -> ResourceRecord {
+    let mut ttl = (self.extended_rcode as u32) << 24 | (self.version as u32) << 16;
+    if self.dnssec_ok {
+      ttl |= DNSSEC_OK;
+    }
+    ResourceRecord::new(Name::root(), DnsClass::from(self.udp_payload_size), ttl, RData::OPT(self.options.clone()))
+  }
+
+  /// The full rcode, given the low 4 bits from the header.
+  pub fn rcode(&self, header_rcode: Rcode) -> Rcode {
+    Rcode::from_parts(header_rcode.low(), self.extended_rcode)
+  }
+
+  /// The CLIENT-SUBNET option, if there is one.
+  pub fn client_subnet(&self) -> Option<ClientSubnet> {
+    self.options.iter().find_map(|option| match option {
+      EdnsOption::ClientSubnet(subnet) => Some(*subnet),
+      _ => None,
+    })
+  }
+
+  /// The COOKIE option, if there is one.
+  pub fn cookie(&self) -> Option<(&[u8; 8], &[u8])> {
+    self.options.iter().find_map(|option| match option {
+      EdnsOption::Cookie { client, server } => Some((client, &server[..])),
+      _ => None,
+    })
+  }
+
+  /// Largest response the sender of this OPT record can take over UDP.
+  pub fn max_response_size(&self) -> usize {
+    self.udp_payload_size.max(MIN_UDP_PAYLOAD_SIZE) as usize
+  }
+}
+
+#[test]
+fn test_edns_record_round_trip() {
+  let edns = Edns {
+    udp_payload_size: 1232,
+    extended_rcode: Rcode::BadVers.high(),
+    version: 0,
+    dnssec_ok: true,
+    options: vec![EdnsOption::Unknown { code: 65001, data: vec![1, 2] }],
+  };
+  let record = edns.to_record();
+  assert!(record.name.is_root());
+  assert_eq!(u16::from(record.class), 1232);
+  assert_eq!(record.ttl, 0x0100_8000);
+  assert_eq!(Edns::from_record(&record), Some(edns.clone()));
+  assert_eq!(edns.rcode(Rcode::NoError), Rcode::BadVers);
+  assert_eq!(Edns { udp_payload_size: 100, ..edns }.max_response_size(), 512);
+}
+
+#[test]
+fn test_client_subnet() {
+  let subnet = ClientSubnet::new("192.0.2.129".parse().unwrap(), 25);
+  assert_eq!(subnet.address, "192.0.2.128".parse::<IpAddr>().unwrap());
+  assert_eq!(EdnsOption::ClientSubnet(subnet).data(), [0, 1, 25, 0, 192, 0, 2, 128]);
+  let subnet = ClientSubnet::new("2001:db8:1234:5678::1".parse().unwrap(), 56);
+  assert_eq!(subnet.address, "2001:db8:1234:5600::".parse::<IpAddr>().unwrap());
+  assert_eq!(EdnsOption::ClientSubnet(subnet).data(), [0, 2, 56, 0, 0x20, 0x01, 0x0d, 0xb8, 0x12, 0x34, 0x56]);
+  let subnet = ClientSubnet::new("192.0.2.1".parse().unwrap(), 0);
+  assert_eq!((subnet.address, subnet.address_bytes()), ("0.0.0.0".parse().unwrap(), vec![]));
+  assert_eq!(ClientSubnet::new("192.0.2.1".parse().unwrap(), 40).source_prefix, 32);
+}
@@ -0,0 +1,85 @@
+use nom::error::{ErrorKind, ParseError};
+use thiserror::Error;
+
+use crate::{header::Rcode, record::RecordType};
+
+#[derive(Debug, Error)]
+pub enum DnsError {
+  #[error("message is shorter than the 12 byte header")]
+  MalformedHeader,
+  #[error("message ends in the middle of a field")]
+  Truncated,
+  #[error("label has a reserved type or runs past the end of the message")]
+  BadLabel,
+  #[error("compression pointer at {at} points forward to {target}")]
+  ForwardPointer { at: usize, target: usize },
+  #[error("name chains too many compression pointers")]
+  TooManyPointers,
+  #[error("name is longer than 255 bytes")]
+  NameTooLong,
+  #[error("label is {0} bytes, longer than 63")]
+  LabelTooLong(usize),
+  #[error("empty label inside a name")]
+  EmptyLabel,
+  #[error("{0:?} is not a valid internationalized label")]
+  Idna(String),
+  #[error("rdata length runs past the end of the message")]
+  TruncatedRdata,
+  #[error("rdata does not match its {0:?} record type")]
+  BadRdata(RecordType),
+  #[error("{field} of {rtype} rdata is {len} bytes, more than {max}")]
+  FieldTooLong { rtype: RecordType, field: &'static str, len: usize, max: usize },
+  #[error("invalid SvcParams: {0}")]
+  BadSvcParams(String),
+  #[error("query has no questions")]
+  NoQuestions,
+  #[error("{0} bytes left over after the last record")]
+  TrailingData(usize),
+  #[error("records added out of section order")]
+  SectionOrder,
+  #[error("more than one OPT record")]
+  DuplicateOpt,
+  #[error("EDNS option {0} is malformed")]
+  BadEdnsOption(u16),
+  #[error("TSIG record is not the last in the message")]
+  MisplacedTsig,
+  #[error("syntax error: {0}")]
+  Syntax(String),
+  #[error("malformed message: {0:?}")]
+  Malformed(ErrorKind),
+  #[error("upstream resolver did not answer in time")]
+  UpstreamTimeout,
+  #[error("upstream resolver answered {0}")]
+  UpstreamRcode(Rcode),
+  #[error("DNSSEC validation failed: {0}")]
+  Bogus(String),
+  #[error("response does not echo our client cookie")]
+  CookieMismatch,
+  #[error("response is for another client subnet")]
+  ClientSubnetMismatch,
+  #[error("response TSIG did not verify: {0}")]
+  BadTsig(&'static str),
+  #[error("i/o error: {0}")]
+  Io(#[from] std::io::Error),
+}
+
+impl<I> ParseError<I> for DnsError {
+  fn from_error_kind(_input: I, kind: ErrorKind) -> Self {
+    match kind {
+      ErrorKind::Eof => Self::Truncated,
+      kind => Self::Malformed(kind),
+    }
+  }
+  fn append(_input: I, _kind: ErrorKind, other: Self) -> Self {
+    other
+  }
+}
+
+impl From<nom::Err<DnsError>> for DnsError {
+  fn from(value: nom::Err<DnsError>) -> Self {
+    match value {
+      nom::Err::Incomplete(_) => Self::Truncated,
+      nom::Err::Error(e) | nom::Err::Failure(e) => e,
+    }
+  }
+}
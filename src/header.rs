@@ -0,0 +1,226 @@
+#![allow(dead_code)]
+
+pub const QR: u16 = 0b1000_0000_0000_0000;
+pub const AA: u16 = 0b0000_0100_0000_0000;
+pub const TC: u16 = 0b0000_0010_0000_0000;
+pub const RD: u16 = 0b0000_0001_0000_0000;
+pub const RA: u16 = 0b0000_0000_1000_0000;
+pub const Z: u16 = 0b0000_0000_0100_0000;
+pub const AD: u16 = 0b0000_0000_0010_0000;
+pub const CD: u16 = 0b0000_0000_0001_0000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Opcode {
+  Query,
+  IQuery,
+  Status,
+  Notify,
+  Update,
+  Unknown(u8),
+}
+
+impl From<u8> for Opcode {
+  fn from(value: u8) -> Self {
+    match value {
+      0 => Self::Query,
+      1 => Self::IQuery,
+      2 => Self::Status,
+      4 => Self::Notify,
+      5 => Self::Update,
+      other => Self::Unknown(other & 0b1111),
+    }
+  }
+}
+
+impl From<Opcode> for u8 {
+  fn from(value: Opcode) -> Self {
+    match value {
+      Opcode::Query => 0,
+      Opcode::IQuery => 1,
+      Opcode::Status => 2,
+      Opcode::Notify => 4,
+      Opcode::Update => 5,
+      Opcode::Unknown(other) => other & 0b1111,
+    }
+  }
+}
+
+/// Response codes. Only the low 4 bits fit in the header; the values from 16 up
+/// need the upper 8 bits carried in an OPT record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Rcode {
+  #[default]
+  NoError,
+  FormErr,
+  ServFail,
+  NXDomain,
+  NotImp,
+  Refused,
+  YXDomain,
+  YXRRSet,
+  NXRRSet,
+  NotAuth,
+  NotZone,
+  BadVers,
+  BadKey,
+  BadTime,
+  BadMode,
+  BadName,
+  BadAlg,
+  BadTrunc,
+  BadCookie,
+  Unknown(u16),
+}
+
+impl Rcode {
+  /// The 4 bits that go in the header.
+  pub fn low(self) -> u8 {
+    (u16::from(self) & 0b1111) as u8
+  }
+  /// The 8 bits that go in the OPT record's extended rcode field.
+  pub fn high(self) -> u8 {
+    (u16::from(self) >> 4) as u8
+  }
+  pub fn from_parts(low: u8, high: u8) -> Self {
+    Self::from((high as u16) << 4 | (low & 0b1111) as u16)
+  }
+}
+
+impl From<u16> for Rcode {
+  fn from(value: u16) -> Self {
+    match value {
+      0 => Self::NoError,
+      1 => Self::FormErr,
+      2 => Self::ServFail,
+      3 => Self::NXDomain,
+      4 => Self::NotImp,
+      5 => Self::Refused,
+      6 => Self::YXDomain,
+      7 => Self::YXRRSet,
+      8 => Self::NXRRSet,
+      9 => Self::NotAuth,
+      10 => Self::NotZone,
+      16 => Self::BadVers,
+      17 => Self::BadKey,
+      18 => Self::BadTime,
+      19 => Self::BadMode,
+      20 => Self::BadName,
+      21 => Self::BadAlg,
+      22 => Self::BadTrunc,
+      23 => Self::BadCookie,
+      other => Self::Unknown(other),
+    }
+  }
+}
+
+impl From<Rcode> for u16 {
+  fn from(value: Rcode) -> Self {
+    match value {
+      Rcode::NoError => 0,
+      Rcode::FormErr => 1,
+      Rcode::ServFail => 2,
+      Rcode::NXDomain => 3,
+      Rcode::NotImp => 4,
+      Rcode::Refused => 5,
+      Rcode::YXDomain => 6,
+      Rcode::YXRRSet => 7,
+      Rcode::NXRRSet => 8,
+      Rcode::NotAuth => 9,
+      Rcode::NotZone => 10,
+      Rcode::BadVers => 16,
+      Rcode::BadKey => 17,
+      Rcode::BadTime => 18,
+      Rcode::BadMode => 19,
+      Rcode::BadName => 20,
+      Rcode::BadAlg => 21,
+      Rcode::BadTrunc => 22,
+      Rcode::BadCookie => 23,
+      Rcode::Unknown(other) => other,
+    }
+  }
+}
+
+/// The second 16 bits of the header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Flags {
+  pub qr: bool,
+  pub opcode: Opcode,
+  pub aa: bool,
+  pub tc: bool,
+  pub rd: bool,
+  pub ra: bool,
+  pub z: bool,
+  pub ad: bool,
+  pub cd: bool,
+  /// Only the low 4 bits survive in the header.
+  pub rcode: Rcode,
+}
+
+impl Default for Flags {
+  fn default() -> Self {
+    Self::from(0)
+  }
+}
+
+impl From<u16> for Flags {
+  fn from(value: u16) -> Self {
+    Self {
+      qr: value & QR != 0,
+      opcode: Opcode::from((value >> 11) as u8 & 0b1111),
+      aa: value & AA != 0,
+      tc: value & TC != 0,
+      rd: value & RD != 0,
+      ra: value & RA != 0,
+      z: value & Z != 0,
+      ad: value & AD != 0,
+      cd: value & CD != 0,
+      rcode: Rcode::from(value & 0b1111),
+    }
+  }
+}
+
+impl From<Flags> for u16 {
+  fn from(value: Flags) -> Self {
+    let bits = [(value.qr, QR), (value.aa, AA), (value.tc, TC), (value.rd, RD), (value.ra, RA), (value.z, Z), (value.ad, AD), (value.cd, CD)];
+    bits.iter().filter(|(set, _)| *set).fold(0, |acc, (_, mask)| acc | mask)
+      | (u8::from(value.opcode) as u16) << 11
+      | value.rcode.low() as u16
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Header {
+  pub id: u16,
+  pub flags: Flags,
+  pub question_count: u16,
+  pub answer_count: u16,
+  pub authority_count: u16,
+  pub additional_count: u16,
+}
+
+#[test]
+fn test_flags_round_trip() {
+  let flags = Flags::from(0x8180);
+  assert!(flags.qr && flags.rd && flags.ra);
+  assert!(!flags.aa && !flags.tc && !flags.ad && !flags.cd);
+  assert_eq!(flags.opcode, Opcode::Query);
+  assert_eq!(flags.rcode, Rcode::NoError);
+  assert_eq!(u16::from(flags), 0x8180);
+  let flags = Flags {
+    opcode: Opcode::Update,
+    aa: true,
+    cd: true,
+    rcode: Rcode::NXDomain,
+    ..Flags::default()
+  };
+  assert_eq!(u16::from(flags), 0x2c13);
+  assert_eq!(Flags::from(0x2c13), flags);
+}
+
+#[test]
+fn test_extended_rcode() {
+  assert_eq!(Rcode::BadVers.low(), 0);
+  assert_eq!(Rcode::BadVers.high(), 1);
+  assert_eq!(Rcode::from_parts(7, 1), Rcode::BadCookie);
+  assert_eq!(Rcode::from_parts(Rcode::Refused.low(), Rcode::Refused.high()), Rcode::Refused);
+}
@@ -0,0 +1,396 @@
+//! The JSON representation of DNS messages from RFC 8427, for handing queries and
+//! responses to other tools. Names and rdata are written in presentation format,
+//! alongside the uncompressed rdata in hex that reading a message back relies on.
+use std::collections::BTreeMap;
+
+use serde::{de, ser, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+use crate::{
+  builder::MessageBuilder,
+  edns::Edns,
+  error::DnsError,
+  header::{Flags, Opcode, Rcode},
+  message::{encode_rdata, Message},
+  message_ref::{MessageRef, RecordRef},
+  parser::decode_rdata,
+  presentation::{from_hex, hex},
+  record::{DnsClass, Question, RData, RecordType, ResourceRecord},
+};
+
+/// RFC 8427 flags are booleans, but its own examples write them as 0 and 1.
+fn flag<'de, D: Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+  #[derive(Deserialize)]
+  #[serde(untagged)]
+  enum Flag {
+    Bool(bool),
+    Int(u8),
+  }
+  Ok(match Flag::deserialize(deserializer)? {
+    Flag::Bool(flag) => flag,
+    Flag::Int(flag) => flag != 0,
+  })
+}
+
+fn type_name(rtype: RecordType) -> Option<String> {
+  (!matches!(rtype, RecordType::Unknown(_))).then(|| rtype.to_string())
+}
+
+fn class_name(class: DnsClass) -> Option<String> {
+  (!matches!(class, DnsClass::Unknown(_))).then(|| class.to_string())
+}
+
+/// The numeric field if there is one, otherwise the mnemonic.
+fn number_or_name<T: std::str::FromStr<Err = DnsError> + From<u16>>(number: Option<u16>, name: Option<&str>, field: &str) -> Result<T, DnsError> {
+  match (number, name) {
+    (Some(number), _) => Ok(T::from(number)),
+    (None, Some(name)) => name.parse(),
+    (None, None) => Err(DnsError::Syntax(format!("missing {field}"))),
+  }
+}
+
+/// The class is the one member that may be left out, and is then IN.
+fn class(number: Option<u16>, name: Option<&str>) -> Result<DnsClass, DnsError> {
+  match (number, name) {
+    (None, None) => Ok(DnsClass::IN),
+    _ => number_or_name(number, name, "CLASS"),
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct JsonQuestion {
+  #[serde(rename = "NAME")]
+  name: String,
+  #[serde(rename = "TYPE", default, skip_serializing_if = "Option::is_none")]
+  rtype: Option<u16>,
+  #[serde(rename = "TYPEname", default, skip_serializing_if = "Option::is_none")]
+  type_name: Option<String>,
+  #[serde(rename = "CLASS", default, skip_serializing_if = "Option::is_none")]
+  class: Option<u16>,
+  #[serde(rename = "CLASSname", default, skip_serializing_if = "Option::is_none")]
+  class_name: Option<String>,
+}
+
+impl From<&Question> for JsonQuestion {
+  fn from(question: &Question) -> Self {
+    Self {
+      name: question.name.to_string(),
+      rtype: Some(question.qtype.into()),
+      type_name: type_name(question.qtype),
+      class: Some(question.qclass.into()),
+      class_name: class_name(question.qclass),
+    }
+  }
+}
+
+impl TryFrom<JsonQuestion> for Question {
+  type Error = DnsError;
+  fn try_from(question: JsonQuestion) -> Result<Self, Self::Error> {
+    Ok(Question::new(
+      question.name.parse()?,
+      number_or_name(question.rtype, question.type_name.as_deref(), "TYPE")?,
+      class(question.class, question.class_name.as_deref())?,
+    ))
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct JsonRecord {
+  #[serde(rename = "NAME")]
+  name: String,
+  #[serde(rename = "TYPE", default, skip_serializing_if = "Option::is_none")]
+  rtype: Option<u16>,
+  #[serde(rename = "TYPEname", default, skip_serializing_if = "Option::is_none")]
+  type_name: Option<String>,
+  #[serde(rename = "CLASS", default, skip_serializing_if = "Option::is_none")]
+  class: Option<u16>,
+  #[serde(rename = "CLASSname", default, skip_serializing_if = "Option::is_none")]
+  class_name: Option<String>,
+  #[serde(rename = "TTL")]
+  ttl: u32,
+  #[serde(rename = "RDLENGTH", default, skip_serializing_if = "Option::is_none")]
+  rdlength: Option<u16>,
+  #[serde(rename = "RDATAHEX", default, skip_serializing_if = "Option::is_none")]
+  rdata_hex: Option<String>,
+  /// `rdata` followed by the type mnemonic, e.g. `rdataMX`, holding the rdata in presentation format.
+  #[serde(flatten)]
+  rdata_text: BTreeMap<String, Value>,
+}
+
+impl TryFrom<&ResourceRecord> for JsonRecord {
+  type Error = DnsError;
+  fn try_from(record: &ResourceRecord) -> Result<Self, Self::Error> {
+    let rtype = record.rtype();
+    let data = encode_rdata(&record.rdata)?;
+    let rdlength = u16::try_from(data.len()).map_err(|_| DnsError::FieldTooLong { rtype, field: "rdata", len: data.len(), max: u16::MAX as usize })?;
+    let mut rdata_text = BTreeMap::new();
+    if !matches!(record.rdata, RData::OPT(_) | RData::Unknown { .. }) {
+      rdata_text.insert(format!("rdata{rtype}"), Value::String(record.rdata.to_string()));
+    }
+    Ok(Self {
+      name: record.name.to_string(),
+      rtype: Some(rtype.into()),
+      type_name: type_name(rtype),
+      class: Some(record.class.into()),
+      class_name: class_name(record.class),
+      ttl: record.ttl,
+      rdlength: Some(rdlength),
+      rdata_hex: Some(hex(&data)),
+      rdata_text,
+    })
+  }
+}
+
+impl TryFrom<JsonRecord> for ResourceRecord {
+  type Error = DnsError;
+  fn try_from(record: JsonRecord) -> Result<Self, Self::Error> {
+    let rtype: RecordType = number_or_name(record.rtype, record.type_name.as_deref(), "TYPE")?;
+    let class = class(record.class, record.class_name.as_deref())?;
+    let rdata = match (&record.rdata_hex, record.rdata_text.get(&format!("rdata{rtype}"))) {
+      (Some(rdata_hex), _) => {
+        let data = from_hex(rdata_hex)?;
+        if record.rdlength.is_some_and(|rdlength| rdlength as usize != data.len()) {
+          return Err(DnsError::Syntax(format!("RDLENGTH does not match RDATAHEX in {rdata_hex:?}")));
+        }
+        decode_rdata(&data, &data, rtype)?
+      }
+      (None, Some(Value::String(text))) => RData::from_text(rtype, text)?,
+      _ => return Err(DnsError::Syntax(format!("no rdata for {}", record.name))),
+    };
+    Ok(ResourceRecord::new(record.name.parse()?, class, record.ttl, rdata))
+  }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct JsonMessage {
+  #[serde(rename = "ID", default)]
+  id: u16,
+  #[serde(rename = "QR", default, deserialize_with = "flag")]
+  qr: bool,
+  #[serde(rename = "Opcode", default)]
+  opcode: u8,
+  #[serde(rename = "AA", default, deserialize_with = "flag")]
+  aa: bool,
+  #[serde(rename = "TC", default, deserialize_with = "flag")]
+  tc: bool,
+  #[serde(rename = "RD", default, deserialize_with = "flag")]
+  rd: bool,
+  #[serde(rename = "RA", default, deserialize_with = "flag")]
+  ra: bool,
+  #[serde(rename = "AD", default, deserialize_with = "flag")]
+  ad: bool,
+  #[serde(rename = "CD", default, deserialize_with = "flag")]
+  cd: bool,
+  /// Just the low 4 bits from the header; the rest is in the OPT record.
+  #[serde(rename = "RCODE", default)]
+  rcode: u8,
+  #[serde(rename = "QDCOUNT", default)]
+  question_count: u16,
+  #[serde(rename = "ANCOUNT", default)]
+  answer_count: u16,
+  #[serde(rename = "NSCOUNT", default)]
+  authority_count: u16,
+  #[serde(rename = "ARCOUNT", default)]
+  additional_count: u16,
+  /// The lone question of most messages, in place of `questionRRs`.
+  #[serde(rename = "QNAME", default, skip_serializing_if = "Option::is_none")]
+  qname: Option<String>,
+  #[serde(rename = "QTYPE", default, skip_serializing_if = "Option::is_none")]
+  qtype: Option<u16>,
+  #[serde(rename = "QCLASS", default, skip_serializing_if = "Option::is_none")]
+  qclass: Option<u16>,
+  #[serde(rename = "questionRRs", default, skip_serializing_if = "Vec::is_empty")]
+  questions: Vec<JsonQuestion>,
+  #[serde(rename = "answerRRs", default, skip_serializing_if = "Vec::is_empty")]
+  answers: Vec<JsonRecord>,
+  #[serde(rename = "authorityRRs", default, skip_serializing_if = "Vec::is_empty")]
+  authorities: Vec<JsonRecord>,
+  #[serde(rename = "additionalRRs", default, skip_serializing_if = "Vec::is_empty")]
+  additionals: Vec<JsonRecord>,
+  /// The whole message in wire format, which takes precedence over every other member when reading.
+  #[serde(rename = "messageOctetsHEX", default, skip_serializing_if = "Option::is_none")]
+  message_hex: Option<String>,
+}
+
+fn json_records<'a>(records: impl Iterator<Item = Result<RecordRef<'a>, DnsError>>) -> Result<Vec<JsonRecord>, DnsError> {
+  records.map(|record| JsonRecord::try_from(&record?.to_record()?)).collect()
+}
+
+impl TryFrom<MessageRef<'_>> for JsonMessage {
+  type Error = DnsError;
+  fn try_from(message: MessageRef<'_>) -> Result<Self, Self::Error> {
+    let header = message.header();
+    let questions = message.questions().map(|question| Ok(question?.to_question())).collect::<Result<Vec<_>, DnsError>>()?;
+    let mut json = Self {
+      id: header.id,
+      qr: header.flags.qr,
+      opcode: header.flags.opcode.into(),
+      aa: header.flags.aa,
+      tc: header.flags.tc,
+      rd: header.flags.rd,
+      ra: header.flags.ra,
+      ad: header.flags.ad,
+      cd: header.flags.cd,
+      rcode: header.flags.rcode.low(),
+      question_count: header.question_count,
+      answer_count: header.answer_count,
+      authority_count: header.authority_count,
+      additional_count: header.additional_count,
+      answers: json_records(message.answers())?,
+      authorities: json_records(message.authorities())?,
+      additionals: json_records(message.additionals())?,
+      ..Self::default()
+    };
+    match &questions[..] {
+      [question] => {
+        json.qname = Some(question.name.to_string());
+        json.qtype = Some(question.qtype.into());
+        json.qclass = Some(question.qclass.into());
+      }
+      questions => json.questions = questions.iter().map(JsonQuestion::from).collect(),
+    }
+    Ok(json)
+  }
+}
+
+impl TryFrom<JsonMessage> for Message {
+  type Error = DnsError;
+  fn try_from(json: JsonMessage) -> Result<Self, Self::Error> {
+    if let Some(message_hex) = &json.message_hex {
+      let message = Message::parse(&from_hex(message_hex)?)?;
+      message.validate()?;
+      return Ok(message);
+    }
+    let flags = Flags {
+      qr: json.qr,
+      opcode: Opcode::from(json.opcode),
+      aa: json.aa,
+      tc: json.tc,
+      rd: json.rd,
+      ra: json.ra,
+      z: false,
+      ad: json.ad,
+      cd: json.cd,
+      rcode: Rcode::from_parts(json.rcode, 0),
+    };
+    let mut builder = MessageBuilder::new(json.id).flags(flags);
+    if let Some(qname) = json.qname {
+      let question = JsonQuestion { name: qname, rtype: json.qtype, type_name: None, class: json.qclass, class_name: None };
+      builder = builder.question(question.try_into()?);
+    }
+    for question in json.questions {
+      builder = builder.question(question.try_into()?);
+    }
+    for answer in json.answers {
+      builder = builder.answer(answer.try_into()?);
+    }
+    for authority in json.authorities {
+      builder = builder.authority(authority.try_into()?);
+    }
+    for additional in json.additionals {
+      let record: ResourceRecord = additional.try_into()?;
+      match Edns::from_record(&record) {
+        Some(edns) => {
+          builder = builder.rcode(edns.rcode(flags.rcode)).edns(Some(edns));
+        }
+        None => builder = builder.additional(record),
+      }
+    }
+    builder.build()
+  }
+}
+
+impl Serialize for MessageRef<'_> {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    JsonMessage::try_from(*self).map_err(ser::Error::custom)?.serialize(serializer)
+  }
+}
+
+impl Serialize for Message {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    self.view().serialize(serializer)
+  }
+}
+
+impl<'de> Deserialize<'de> for Message {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    JsonMessage::deserialize(deserializer)?.try_into().map_err(de::Error::custom)
+  }
+}
+
+impl Serialize for Question {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    JsonQuestion::from(self).serialize(serializer)
+  }
+}
+
+impl<'de> Deserialize<'de> for Question {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    JsonQuestion::deserialize(deserializer)?.try_into().map_err(de::Error::custom)
+  }
+}
+
+impl Serialize for ResourceRecord {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    JsonRecord::try_from(self).map_err(ser::Error::custom)?.serialize(serializer)
+  }
+}
+
+impl<'de> Deserialize<'de> for ResourceRecord {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    JsonRecord::deserialize(deserializer)?.try_into().map_err(de::Error::custom)
+  }
+}
+
+#[test]
+fn test_message_json_round_trip() {
+  let request = Message::query("example.com".parse().unwrap(), RecordType::MX).id(0x1234).edns(Some(Edns::default())).build().unwrap();
+  let response = Message::response_to(&request)
+    .unwrap()
+    .ra(true)
+    .answer("example.com. 300 IN MX 10 mail.example.com.".parse().unwrap())
+    .answer(ResourceRecord::new("example.com".parse().unwrap(), DnsClass::IN, 300, RData::Unknown { rtype: RecordType::Unknown(65534), data: vec![1, 2] }))
+    .authority("example.com. 300 IN NS ns1.example.com.".parse().unwrap())
+    .edns(Some(Edns { dnssec_ok: true, ..Edns::default() }))
+    .rcode(Rcode::BadCookie)
+    .build().unwrap();
+  let json = serde_json::to_value(&response).unwrap();
+  assert_eq!(json["ID"], 0x1234);
+  assert_eq!(json["QR"], true);
+  assert_eq!(json["RCODE"], 7);
+  assert_eq!(json["QNAME"], "example.com.");
+  assert_eq!(json["QTYPE"], 15);
+  assert_eq!(json["ANCOUNT"], 2);
+  assert_eq!(json["answerRRs"][0]["rdataMX"], "10 mail.example.com.");
+  assert_eq!(json["answerRRs"][0]["TYPEname"], "MX");
+  assert_eq!(json["answerRRs"][1]["RDATAHEX"], "0102");
+  assert!(json["answerRRs"][1].get("TYPEname").is_none());
+  assert_eq!(json["additionalRRs"][0]["TYPE"], 41);
+  let decoded: Message = serde_json::from_value(json).unwrap();
+  assert_eq!(&decoded[..], &response[..]);
+}
+
+#[test]
+fn test_message_from_rfc_8427_json() {
+  // Flags as integers and rdata only in presentation format, as in the RFC's examples.
+  let json = r#"{
+    "ID": 32784, "QR": 1, "Opcode": 0, "AA": 1, "TC": 0, "RD": 0, "RA": 0, "AD": 0, "CD": 0, "RCODE": 0,
+    "QDCOUNT": 1, "ANCOUNT": 1, "NSCOUNT": 0, "ARCOUNT": 0,
+    "QNAME": "example.com.", "QTYPE": 1, "QCLASS": 1,
+    "answerRRs": [{ "NAME": "example.com.", "TYPEname": "A", "CLASS": 1, "TTL": 3600, "rdataA": "192.0.2.1" }]
+  }"#;
+  let message: Message = serde_json::from_str(json).unwrap();
+  assert_eq!(message.id(), 32784);
+  assert!(message.is_response() && message.aa() && !message.rd());
+  assert_eq!(message.answers().unwrap(), vec!["example.com. 3600 IN A 192.0.2.1".parse().unwrap()]);
+  let hex = format!(r#"{{ "messageOctetsHEX": "{}" }}"#, hex(&message));
+  let from_hex: Message = serde_json::from_str(&hex).unwrap();
+  assert_eq!(&from_hex[..], &message[..]);
+  assert!(serde_json::from_str::<Message>(r#"{ "answerRRs": [{ "NAME": "a.", "TYPE": 1, "TTL": 1 }] }"#).is_err());
+  assert!(serde_json::from_str::<Message>(r#"{ "answerRRs": [{ "NAME": "a.", "TYPE": 1, "TTL": 1, "RDATAHEX": "01" }] }"#).is_err());
+  let record: ResourceRecord = serde_json::from_str(r#"{ "NAME": "a.", "TYPE": 1, "TTL": 1, "rdataA": "192.0.2.1" }"#).unwrap();
+  assert_eq!(record.class, DnsClass::IN);
+  assert!(serde_json::from_str::<ResourceRecord>(r#"{ "NAME": "a.", "TYPE": 1, "CLASSname": "BOGUS", "TTL": 1, "rdataA": "192.0.2.1" }"#).is_err());
+  assert!(serde_json::from_str::<Question>(r#"{ "NAME": "a.", "TYPE": 1, "CLASSname": "BOGUS" }"#).is_err());
+  let long = ResourceRecord::new("a.".parse().unwrap(), DnsClass::IN, 1, RData::Unknown { rtype: RecordType::Unknown(65534), data: vec![0; 70000] });
+  assert!(serde_json::to_value(&long).is_err());
+}
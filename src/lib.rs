@@ -0,0 +1,23 @@
+//! DNS wire format, presentation format and message building, shared by the
+//! server binary and the fuzz targets.
+pub mod builder;
+pub mod cookie;
+pub mod dnssec;
+pub mod edns;
+pub mod error;
+pub mod header;
+#[cfg(feature = "json")]
+pub mod json;
+pub mod message;
+pub mod message_ref;
+pub mod name;
+pub mod parser;
+pub mod presentation;
+pub mod record;
+pub mod svcb;
+pub mod tsig;
+pub mod validator;
+pub mod zone;
+
+#[cfg(test)]
+mod strategy;
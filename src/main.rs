@@ -1,69 +1,650 @@
 use std::{
-  env,
-  net::{Ipv4Addr, SocketAddr, UdpSocket},
+  env, fs,
+  io::ErrorKind,
+  net::{Ipv4Addr, SocketAddr, ToSocketAddrs, UdpSocket},
+  rc::Rc,
+  time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
-use bytes::{Bytes, BytesMut};
-use message::Message;
-use nom::Offset;
-use parser::{expand_answer, expand_question};
-mod message;
-mod parser;
-
-fn forward_question(message: Message, addr: &str) -> BytesMut {
-  let socket = UdpSocket::bind("127.0.0.1:0").expect("failed to bind to a new system assigned port");
-  socket.connect(addr).unwrap_or_else(|e| panic!("error connecting to resolver: {e}"));
-  socket.send(&message).unwrap_or_else(|e| panic!("error sending message: {e}"));
-  let mut buf = [0u8; 512];
-  match socket.recv(&mut buf) {
-    Ok(size) => {
-      let response = &buf[..size];
-      let (r, _) = expand_question(response, 12).unwrap_or_else(|e| panic!("expand question error: {e}"));
-      let (_, answer) = expand_answer(response, response.offset(r)).unwrap_or_else(|e| panic!("expand answer error: {e}"));
-      answer
-    }
-    Err(e) => panic!("receive from resolver: {e}"),
-  }
-}
-
-fn handle_data_graph(received_data: Bytes, source: SocketAddr, udp_socket: &UdpSocket) {
-  eprintln!("received data: {:02X?}", received_data);
-  let mut message = Message::from(&received_data[..]);
-  let questions = message.expanded_questions();
-  if let Some(addr) = env::args().nth(2) {
-    for question in questions {
-      let mut forward_message = Message::from(&received_data[..12]);
-      forward_message.set_question_count(0);
-      forward_message.add_question(&question);
-      message.add_answer(&forward_question(forward_message, &addr));
+use anyhow::{bail, Context, Result};
+use config::Config;
+use dns_starter_rust::{
+  builder::MessageBuilder,
+  cookie::{ClientCookies, ServerCookies},
+  edns::{
+    ClientSubnet, Edns, EdnsOption, EDE_DNSSEC_BOGUS, EDE_NETWORK_ERROR, EDE_NOT_SUPPORTED, EDE_OTHER, EDNS_VERSION, MAX_UDP_PAYLOAD_SIZE,
+    MIN_UDP_PAYLOAD_SIZE,
+  },
+  error::DnsError,
+  header::{Opcode, Rcode},
+  message::Message,
+  message_ref::MessageRef,
+  record::{Question, RData, RecordType, ResourceRecord},
+  tsig::{self, Key, Verification, BADKEY, BADTIME},
+  validator::{Resolver, Security, Validator},
+  zone::Zone,
+};
+mod config;
+
+const UPSTREAM_TIMEOUT: Duration = Duration::from_secs(2);
+
+fn now() -> u64 {
+  SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs())
+}
+
+/// The resolver questions are forwarded to, with the TSIG key that signs them,
+/// our cookies for it, and whether it may see client subnets.
+struct Upstream {
+  addr: SocketAddr,
+  key: Option<Key>,
+  cookies: ClientCookies,
+  client_subnet: bool,
+}
+
+/// Sends `query` upstream with our cookie, and retries once if the upstream
+/// answers BADCOOKIE, which brings a fresh server cookie (RFC 7873 section 5.3).
+fn forward_question(query: MessageBuilder, dnssec_ok: bool, upstream: &Upstream, subnet: Option<ClientSubnet>) -> Result<Message, DnsError> {
+  let response = exchange(query.clone(), dnssec_ok, upstream, subnet)?;
+  if response.extended_rcode()? == Rcode::BadCookie {
+    return exchange(query, dnssec_ok, upstream, subnet);
+  }
+  Ok(response)
+}
+
+/// Signs the query if the upstream has a key, in which case the response has to
+/// be signed with it too. The subnet only goes to upstreams allowed to see it.
+fn exchange(query: MessageBuilder, dnssec_ok: bool, upstream: &Upstream, subnet: Option<ClientSubnet>) -> Result<Message, DnsError> {
+  let subnet = subnet.filter(|_| upstream.client_subnet);
+  let mut options = vec![upstream.cookies.option(upstream.addr.ip())];
+  options.extend(subnet.map(EdnsOption::ClientSubnet));
+  let edns = Edns { dnssec_ok, options, ..Edns::default() };
+  let mut message = query.edns(Some(edns)).build()?;
+  let request_mac = upstream.key.as_ref().map(|key| tsig::sign(&mut message, key, now(), None)).transpose()?;
+  let questions = message.questions()?;
+  let socket = UdpSocket::bind("127.0.0.1:0")?;
+  socket.connect(upstream.addr)?;
+  socket.send(&message)?;
+  let deadline = Instant::now() + UPSTREAM_TIMEOUT;
+  let mut buf = [0u8; MAX_UDP_PAYLOAD_SIZE as usize];
+  // Datagrams that do not answer our query are dropped as if they never came,
+  // and we wait on for the one that does (RFC 5452 section 9.1).
+  let size = loop {
+    let timeout = deadline.saturating_duration_since(Instant::now());
+    if timeout.is_zero() {
+      return Err(DnsError::UpstreamTimeout);
     }
+    socket.set_read_timeout(Some(timeout))?;
+    match socket.recv(&mut buf) {
+      Ok(size) if answers(message.id(), &questions, &buf[..size]) => break size,
+      Ok(_) => eprintln!("dropped a response from {} that does not match the query", upstream.addr),
+      Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => return Err(DnsError::UpstreamTimeout),
+      Err(e) => return Err(e.into()),
+    }
+  };
+  if let (Some(key), Some(request_mac)) = (&upstream.key, &request_mac) {
+    verify_upstream(&buf[..size], key, request_mac)?;
+  }
+  let response = Message::parse(&buf[..size])?;
+  let response_edns = response.edns()?;
+  upstream.cookies.learn(upstream.addr.ip(), response_edns.as_ref())?;
+  // An answer for another subnet is not for us (RFC 7871 section 7.3).
+  if let (Some(sent), Some(echoed)) = (subnet, response_edns.as_ref().and_then(Edns::client_subnet)) {
+    if (echoed.address, echoed.source_prefix) != (sent.address, sent.source_prefix) {
+      return Err(DnsError::ClientSubnetMismatch);
+    }
+  }
+  Ok(response)
+}
+
+/// Whether `data` is a response with our query's ID and questions.
+fn answers(id: u16, questions: &[Question], data: &[u8]) -> bool {
+  Message::parse(data).is_ok_and(|response| response.id() == id && response.is_response() && response.questions().is_ok_and(|echoed| echoed == questions))
+}
+
+fn verify_upstream(response: &[u8], key: &Key, request_mac: &[u8]) -> Result<(), DnsError> {
+  match tsig::verify(response, std::slice::from_ref(key), now(), Some(request_mac))? {
+    Verification::Verified { .. } => Ok(()),
+    Verification::Unsigned => Err(DnsError::BadTsig("unsigned")),
+    Verification::Rejected { error: BADKEY, .. } => Err(DnsError::BadTsig("BADKEY")),
+    Verification::Rejected { error: BADTIME, .. } => Err(DnsError::BadTsig("BADTIME")),
+    Verification::Rejected { .. } => Err(DnsError::BadTsig("BADSIG")),
+  }
+}
+
+/// The upstream, as the validator sees it: asked for DNSSEC records, with CD set
+/// so that it hands over bogus answers too.
+impl Resolver for Upstream {
+  fn resolve(&self, question: &Question) -> Result<Message, DnsError> {
+    forward_question(Message::query(question.name.clone(), question.qtype).cd(true), true, self, None)
+  }
+}
+
+/// The records the upstream returned for every forwarded question, and whether they
+/// were all vouched for, by the upstream's AD bit or by our own validation. The
+/// rcode is the first failure, and the extended errors are all the upstream gave.
+/// The scope is the most bits of the client subnet any of the answers depend on.
+#[derive(Debug, Default)]
+struct Forwarded {
+  sections: [Vec<ResourceRecord>; 3],
+  authenticated: bool,
+  rcode: Rcode,
+  extended_errors: Vec<EdnsOption>,
+  scope_prefix: u8,
+}
+
+/// Forwards each question on its own, passing on the request's header bits,
+/// AD and CD among them, and its DO bit. With a validator, DO and CD are always
+/// set upstream, and answers are checked unless the client set CD itself.
+fn forward_questions(
+  request: &MessageRef<'_>,
+  questions: &[Question],
+  dnssec_ok: bool,
+  upstream: &Upstream,
+  subnet: Option<ClientSubnet>,
+  validator: Option<&Validator<Rc<Upstream>>>,
+) -> Result<Forwarded, DnsError> {
+  let mut forwarded = Forwarded { authenticated: true, ..Forwarded::default() };
+  let validating = validator.is_some();
+  for question in questions {
+    // A query of our own, with a fresh ID, that only passes on the bits asking
+    // something of the upstream.
+    let flags = request.flags();
+    let query = Message::query_for(question.clone()).rd(flags.rd).cd(flags.cd || validating).ad(flags.ad);
+    let response = forward_question(query, dnssec_ok || validating, upstream, subnet)?;
+    match response.extended_rcode()? {
+      Rcode::NoError => {}
+      rcode @ (Rcode::NXDomain | Rcode::ServFail | Rcode::Refused) => {
+        if forwarded.rcode == Rcode::NoError {
+          forwarded.rcode = rcode;
+        }
+      }
+      // Anything else is about our query to the upstream, not the client's.
+      rcode => return Err(DnsError::UpstreamRcode(rcode)),
+    }
+    if let Some(edns) = response.edns()? {
+      // A scope past the source prefix only means the answer is good for all of the subnet sent.
+      if let Some(echoed) = edns.client_subnet().filter(|_| subnet.is_some() && upstream.client_subnet) {
+        forwarded.scope_prefix = forwarded.scope_prefix.max(echoed.scope_prefix.min(echoed.source_prefix));
+      }
+      forwarded.extended_errors.extend(edns.options.into_iter().filter(|option| matches!(option, EdnsOption::ExtendedError { .. })));
+    }
+    forwarded.authenticated &= match validator {
+      Some(validator) if !request.flags().cd => validator.validate(question, &response)? == Security::Secure,
+      Some(_) => false,
+      None => response.ad(),
+    };
+    let sections = [response.answers()?, response.authorities()?, response.additionals()?];
+    for (forwarded, records) in forwarded.sections.iter_mut().zip(sections) {
+      // Clients that did not set DO only get the DNSSEC records they asked for (RFC 4035 section 3.2.1).
+      // The upstream's TSIG was for us.
+      let wanted = |record: &ResourceRecord| dnssec_ok || record.rtype() == question.qtype || !is_dnssec(record.rtype());
+      forwarded.extend(records.into_iter().filter(|record| record.rtype() != RecordType::TSIG && wanted(record)));
+    }
+  }
+  Ok(forwarded)
+}
+
+fn is_dnssec(rtype: RecordType) -> bool {
+  matches!(rtype, RecordType::RRSIG | RecordType::NSEC | RecordType::NSEC3)
+}
+
+/// The extended error (RFC 8914) for a question we could not forward.
+fn extended_error(error: &DnsError) -> EdnsOption {
+  let (info_code, extra_text) = match error {
+    DnsError::Bogus(reason) => (EDE_DNSSEC_BOGUS, reason.clone()),
+    DnsError::UpstreamRcode(_) => (EDE_OTHER, error.to_string()),
+    error => (EDE_NETWORK_ERROR, error.to_string()),
+  };
+  EdnsOption::ExtendedError { info_code, extra_text }
+}
+
+/// Parses a query, rejecting anything the server should answer with FORMERR.
+/// The questions are left in the request, to be read as they are answered.
+fn parse_request(received_data: &[u8]) -> Result<(MessageRef<'_>, Option<Edns>), DnsError> {
+  let request = MessageRef::parse(received_data)?;
+  request.validate()?;
+  if request.header().question_count == 0 {
+    return Err(DnsError::NoQuestions);
+  }
+  let edns = request.edns()?;
+  Ok((request, edns))
+}
+
+/// A message in presentation format, or JSON, for the logs.
+fn present(message: MessageRef<'_>, config: &Config) -> String {
+  #[cfg(feature = "json")]
+  if config.json {
+    return serde_json::to_string(&message).unwrap_or_else(|e| format!("unserializable message ({e})"));
+  }
+  if config.unicode_names {
+    format!("{message:#}")
   } else {
-    for question in questions {
-      message.answer_question(&question, 60, &Ipv4Addr::new(8, 8, 8, 8).octets())
+    message.to_string()
+  }
+}
+
+/// The DS and DNSKEY records in the `--trust-anchor` files.
+fn load_trust_anchors(config: &Config) -> Result<Vec<ResourceRecord>> {
+  let mut anchors = Vec::new();
+  for path in &config.trust_anchor_files {
+    let text = fs::read_to_string(path).with_context(|| format!("Failed to read trust anchor file {path}"))?;
+    let records: Zone = text.parse().with_context(|| format!("Failed to parse trust anchor file {path}"))?;
+    if let Some(record) = records.records().iter().find(|record| !matches!(record.rtype(), RecordType::DS | RecordType::DNSKEY)) {
+      bail!("{path}: trust anchors are DS or DNSKEY records, not {}", record.rtype());
     }
+    anchors.extend(records.records().iter().cloned());
+  }
+  Ok(anchors)
+}
+
+/// Every record in the `--zone` files.
+fn load_zone(config: &Config) -> Result<Zone> {
+  let mut zone = Zone::new();
+  for path in &config.zone_files {
+    let text = fs::read_to_string(path).with_context(|| format!("Failed to read zone file {path}"))?;
+    let records: Zone = text.parse().with_context(|| format!("Failed to parse zone file {path}"))?;
+    zone.extend(records.records().iter().cloned());
+  }
+  Ok(zone)
+}
+
+/// What queries are answered from.
+struct Server {
+  config: Config,
+  zone: Zone,
+  upstream: Option<Rc<Upstream>>,
+  validator: Option<Validator<Rc<Upstream>>>,
+  cookies: ServerCookies,
+}
+
+impl Server {
+  /// Loads the zones and trust anchors `config` names, and finds its upstream.
+  fn new(config: Config) -> Result<Self> {
+    let zone = load_zone(&config)?;
+    let trust_anchors = load_trust_anchors(&config)?;
+    let upstream = match &config.resolver {
+      Some(resolver) => {
+        let addr = resolver.to_socket_addrs().ok().and_then(|mut addrs| addrs.next()).with_context(|| format!("Failed to resolve --resolver {resolver}"))?;
+        let client_subnet = config.client_subnet_resolvers.contains(&addr.ip());
+        Some(Rc::new(Upstream { addr, key: config.resolver_key().cloned(), cookies: ClientCookies::new(), client_subnet }))
+      }
+      None => None,
+    };
+    let validator = upstream.clone().filter(|_| !trust_anchors.is_empty()).map(|upstream| Validator::new(upstream, trust_anchors));
+    Ok(Self { config, zone, upstream, validator, cookies: ServerCookies::new(now()) })
   }
-  message.set_response();
-  if message.opcode() == 0 {
-    message.set_rcode(0);
+}
+
+fn handle_data_graph(received_data: &[u8], source: SocketAddr, udp_socket: &UdpSocket, server: &Server) -> Result<(), DnsError> {
+  let Server { config, zone, upstream, validator, cookies } = server;
+  let (request, request_edns) = match parse_request(received_data) {
+    Ok(parsed) => parsed,
+    Err(e) => {
+      eprintln!("malformed query from {source} ({e}): {:02X?}", received_data);
+      if let Some(response) = Message::format_error(received_data) {
+        udp_socket.send_to(&response, source)?;
+      }
+      return Ok(());
+    }
+  };
+  eprintln!("query from {source}:\n{}", present(request, config));
+  let now = now();
+  // Signed requests get signed responses, covering the request's MAC (RFC 8945 section 5.3).
+  let signed_by = match tsig::verify(received_data, &config.tsig_keys, now, None)? {
+    Verification::Unsigned => None,
+    Verification::Verified { key, mac } => Some((key, mac)),
+    Verification::Rejected { error, tsig, key } => {
+      let mut message = Message::response_to(request)?.rcode(Rcode::NotAuth).build()?;
+      tsig::reject(&mut message, error, &tsig, key.as_ref(), now)?;
+      eprintln!("response:\n{}", present(message.view(), config));
+      udp_socket.send_to(&message, source)?;
+      return Ok(());
+    }
+  };
+  let mut response = Message::response_to(request)?.compression(config.compression);
+  // The DO bit is echoed (RFC 3225), and asks for DNSSEC records from upstream.
+  let dnssec_ok = request_edns.as_ref().is_some_and(|edns| edns.dnssec_ok);
+  // Clients that send a cookie get a fresh server cookie back (RFC 7873 section 5.2).
+  let cookie = request_edns.as_ref().and_then(Edns::cookie);
+  let cookie_ok = cookie.is_some_and(|(client, server)| cookies.check(client, server, source.ip(), now));
+  let response_cookie = cookie.map(|(client, _)| EdnsOption::Cookie { client: *client, server: cookies.make(client, source.ip(), now) });
+  let mut response_edns = request_edns.as_ref().map(|_| Edns { dnssec_ok, options: response_cookie.into_iter().collect(), ..Edns::default() });
+  if let Some(request_edns) = &request_edns {
+    if request_edns.version > EDNS_VERSION {
+      let mut message = response.rcode(Rcode::BadVers).edns(response_edns).build()?;
+      if let Some((key, mac)) = &signed_by {
+        tsig::sign(&mut message, key, now, Some(mac))?;
+      }
+      udp_socket.send_to(&message, source)?;
+      return Ok(());
+    }
+  }
+  // Only standard queries are answered. Anything else is neither looked up nor
+  // forwarded, but gets NOTIMP straight away (RFC 1035 section 4.1.1).
+  if request.opcode() != Opcode::Query {
+    if let Some(edns) = &mut response_edns {
+      edns.options.push(EdnsOption::ExtendedError { info_code: EDE_NOT_SUPPORTED, extra_text: format!("opcode {} is not supported", request.opcode()) });
+    }
+    let mut message = response.rcode(Rcode::NotImp).edns(response_edns).build()?;
+    if let Some((key, mac)) = &signed_by {
+      tsig::sign(&mut message, key, now, Some(mac))?;
+    }
+    eprintln!("response:\n{}", present(message.view(), config));
+    udp_socket.send_to(&message, source)?;
+    return Ok(());
+  }
+  // The client's own subnet goes upstream, or its address, but cut to the prefix
+  // we are configured to reveal either way (RFC 7871 section 7.1.1). A client
+  // subnet of 0 bits keeps all of its address to itself (section 7.1.2).
+  let client_subnet = request_edns.as_ref().and_then(Edns::client_subnet);
+  let forward_subnet = upstream.as_ref().filter(|upstream| upstream.client_subnet).map(|_| match client_subnet {
+    Some(subnet) => ClientSubnet::new(subnet.address, subnet.source_prefix.min(config.client_subnet_prefix(subnet.address))),
+    None => ClientSubnet::new(source.ip(), config.client_subnet_prefix(source.ip())),
+  });
+  let mut scope_prefix = 0;
+  let mut extended_errors = Vec::new();
+  let (mut remote, question_count) = (Vec::new(), usize::from(request.header().question_count));
+  for question in request.questions() {
+    let question = question?.to_question();
+    match zone.lookup(&question) {
+      Some(answer) => {
+        response = response.aa(true).answers(answer.answers).authorities(answer.authorities).additionals(answer.additionals);
+        if answer.rcode != Rcode::NoError {
+          response = response.rcode(answer.rcode);
+        }
+      }
+      None => remote.push(question),
+    }
+  }
+  if let Some(upstream) = upstream.as_ref().filter(|_| !remote.is_empty()) {
+    match forward_questions(&request, &remote, dnssec_ok, upstream, forward_subnet, validator.as_ref()) {
+      // The upstream's OPT record describes its own limits, not ours; `additionals` drops it.
+      // AD only goes to clients that show they understand it (RFC 6840 section 5.8).
+      // The upstream's rcode and extended errors are passed on too.
+      Ok(Forwarded { sections: [answers, authorities, additionals], authenticated, rcode, extended_errors: errors, scope_prefix: scope }) => {
+        if rcode != Rcode::NoError {
+          response = response.rcode(rcode);
+        }
+        extended_errors.extend(errors);
+        scope_prefix = scope;
+        response = response
+          .answers(answers)
+          .authorities(authorities)
+          .additionals(additionals)
+          .ad(authenticated && remote.len() == question_count && (dnssec_ok || request.flags().ad));
+      }
+      Err(e) => {
+        eprintln!("error forwarding to {}: {e}", upstream.addr);
+        response = response.rcode(Rcode::ServFail);
+        extended_errors.push(extended_error(&e));
+      }
+    }
   } else {
-    message.set_rcode(4);
+    for question in &remote {
+      let answer = ResourceRecord::new(question.name.clone(), question.qclass, 60, RData::A(Ipv4Addr::new(8, 8, 8, 8)));
+      response = response.answer(answer);
+    }
+  }
+  if let Some(edns) = &mut response_edns {
+    // A client that sent its subnet learns how much of it the answer depends on.
+    edns.options.extend(client_subnet.map(|subnet| EdnsOption::ClientSubnet(ClientSubnet { scope_prefix, ..subnet })));
+    edns.options.extend(extended_errors);
+  }
+  // The source address of a request without a valid server cookie or TSIG may be
+  // forged, so it only gets a small response, which makes a poor amplifier.
+  let verified = cookie_ok || signed_by.is_some();
+  let max_size = request_edns.as_ref().map_or(MIN_UDP_PAYLOAD_SIZE as usize, Edns::max_response_size);
+  let max_size = if verified { max_size } else { max_size.min(MIN_UDP_PAYLOAD_SIZE as usize) };
+  let tsig_len = signed_by.as_ref().map_or(0, |(key, _)| key.record_len());
+  let mut message = response.edns(response_edns.clone()).max_size(max_size - tsig_len).build()?;
+  if message.tc() && !verified && cookie.is_some() {
+    // Rather than truncate, ask the client to come back with the server cookie (RFC 7873 section 5.2.3).
+    message = Message::response_to(request)?.rcode(Rcode::BadCookie).edns(response_edns).build()?;
   }
-  eprintln!("response: {:02X?}", message);
-  udp_socket.send_to(&message, source).expect("Failed to send response");
+  if let Some((key, mac)) = &signed_by {
+    tsig::sign(&mut message, key, now, Some(mac))?;
+  }
+  eprintln!("response:\n{}", present(message.view(), config));
+  udp_socket.send_to(&message, source)?;
+  Ok(())
 }
 
-fn main() {
-  let udp_socket = UdpSocket::bind("127.0.0.1:2053").expect("Failed to bind to address");
-  let mut buf = [0; 512];
+fn main() -> Result<()> {
+  let mut server = Server::new(Config::from_args(env::args().skip(1))?)?;
+  let udp_socket = UdpSocket::bind("127.0.0.1:2053").context("Failed to bind to address")?;
+  let mut buf = [0; MAX_UDP_PAYLOAD_SIZE as usize];
   loop {
     match udp_socket.recv_from(&mut buf) {
       Ok((size, source)) => {
         println!("Received {} bytes from {}", size, source);
-        handle_data_graph(Bytes::copy_from_slice(&buf[..size]), source, &udp_socket);
+        server.cookies.rotate(now());
+        if let Err(e) = handle_data_graph(&buf[..size], source, &udp_socket, &server) {
+          eprintln!("Error handling data from {}: {}", source, e);
+        }
       }
       Err(e) => {
         eprintln!("Error receiving data: {}", e);
-        break;
       }
     }
   }
-}
\ No newline at end of file
+}
+
+/// A fake upstream on loopback that answers every query with `respond`, and
+/// passes on the queries it gets.
+#[cfg(test)]
+fn fake_upstream(respond: impl Fn(&Message) -> Message + Send + 'static) -> (SocketAddr, std::sync::mpsc::Receiver<Message>) {
+  let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+  socket.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+  let addr = socket.local_addr().unwrap();
+  let (queries, received) = std::sync::mpsc::channel();
+  std::thread::spawn(move || {
+    let mut buf = [0; MAX_UDP_PAYLOAD_SIZE as usize];
+    while let Ok((size, source)) = socket.recv_from(&mut buf) {
+      let query = Message::parse(&buf[..size]).unwrap();
+      socket.send_to(&respond(&query), source).unwrap();
+      // Tests that do not look at the queries have dropped the receiver.
+      let _ = queries.send(query);
+    }
+  });
+  (addr, received)
+}
+
+/// The response of a server started with `args` to `request` from 127.0.0.1.
+#[cfg(test)]
+fn test_exchange(args: &[&str], request: &Message) -> Message {
+  let server = Server::new(Config::from_args(args.iter().map(|arg| arg.to_string())).unwrap()).unwrap();
+  let (socket, client) = (UdpSocket::bind("127.0.0.1:0").unwrap(), UdpSocket::bind("127.0.0.1:0").unwrap());
+  client.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+  handle_data_graph(request, client.local_addr().unwrap(), &socket, &server).unwrap();
+  let mut buf = [0; MAX_UDP_PAYLOAD_SIZE as usize];
+  let size = client.recv(&mut buf).unwrap();
+  Message::parse(&buf[..size]).unwrap()
+}
+
+#[cfg(test)]
+fn test_query(edns: Option<Edns>) -> Message {
+  Message::query("example.com".parse().unwrap(), RecordType::A).edns(edns).build().unwrap()
+}
+
+#[test]
+fn test_extended_error() {
+  let bogus = extended_error(&DnsError::Bogus("the RRSIG has expired".to_string()));
+  assert_eq!(bogus, EdnsOption::ExtendedError { info_code: EDE_DNSSEC_BOGUS, extra_text: "the RRSIG has expired".to_string() });
+  let info_code = |error| match extended_error(&error) {
+    EdnsOption::ExtendedError { info_code, .. } => info_code,
+    option => panic!("{option:?}"),
+  };
+  assert_eq!(info_code(DnsError::UpstreamTimeout), EDE_NETWORK_ERROR);
+  assert_eq!(info_code(DnsError::Io(ErrorKind::ConnectionRefused.into())), EDE_NETWORK_ERROR);
+  assert_eq!(info_code(DnsError::UpstreamRcode(Rcode::FormErr)), EDE_OTHER);
+}
+
+#[test]
+fn test_not_supported() {
+  let (upstream, queries) = fake_upstream(|query| Message::response_to(query).unwrap().rcode(Rcode::Refused).build().unwrap());
+  let args = ["--resolver", &upstream.to_string()];
+  let notify = |edns| Message::query("example.com".parse().unwrap(), RecordType::SOA).opcode(Opcode::Notify).edns(edns).build().unwrap();
+  let response = test_exchange(&args, &notify(Some(Edns::default())));
+  assert_eq!(response.rcode(), Rcode::NotImp);
+  let options = response.edns().unwrap().unwrap().options;
+  assert!(matches!(&options[..], [EdnsOption::ExtendedError { info_code: EDE_NOT_SUPPORTED, .. }]), "{options:?}");
+  // Without EDNS there is nowhere to put an extended error.
+  let response = test_exchange(&args, &notify(None));
+  assert_eq!(response.rcode(), Rcode::NotImp);
+  assert_eq!(response.edns().unwrap(), None);
+  assert!(queries.recv_timeout(Duration::from_secs(1)).is_err());
+}
+
+#[test]
+fn test_unreachable_upstream() {
+  // Nothing listens on a port just let go of, so the upstream refuses the query.
+  let closed = UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+  let response = test_exchange(&["--resolver", &closed], &test_query(Some(Edns::default())));
+  assert_eq!(response.rcode(), Rcode::ServFail);
+  let options = response.edns().unwrap().unwrap().options;
+  assert!(matches!(&options[..], [EdnsOption::ExtendedError { info_code: EDE_NETWORK_ERROR, .. }]), "{options:?}");
+  let response = test_exchange(&["--resolver", &closed], &test_query(None));
+  assert_eq!(response.rcode(), Rcode::ServFail);
+  assert_eq!(response.edns().unwrap(), None);
+}
+
+#[test]
+fn test_upstream_extended_errors() {
+  let prohibited = EdnsOption::ExtendedError { info_code: 18, extra_text: "not for you".to_string() };
+  let ede = prohibited.clone();
+  let (upstream, _) = fake_upstream(move |query| {
+    let edns = Edns { options: vec![ede.clone()], ..Edns::default() };
+    Message::response_to(query).unwrap().rcode(Rcode::NXDomain).edns(Some(edns)).build().unwrap()
+  });
+  let args = ["--resolver", &upstream.to_string()];
+  let response = test_exchange(&args, &test_query(Some(Edns::default())));
+  assert_eq!(response.rcode(), Rcode::NXDomain);
+  assert_eq!(response.edns().unwrap().unwrap().options, [prohibited]);
+  let response = test_exchange(&args, &test_query(None));
+  assert_eq!(response.rcode(), Rcode::NXDomain);
+  assert_eq!(response.edns().unwrap(), None);
+}
+
+#[test]
+fn test_mismatched_upstream_response() {
+  let answer = |query: &Message, ip: &str| {
+    let record = format!("{} 60 IN A {ip}", query.questions().unwrap()[0].name).parse().unwrap();
+    Message::response_to(query).unwrap().answer(record).build().unwrap()
+  };
+  // A reply with the wrong ID is no answer at all.
+  let (upstream, _) = fake_upstream(move |query| {
+    let mut response = answer(query, "192.0.2.66");
+    response.set_id(query.id().wrapping_add(1));
+    response
+  });
+  let response = test_exchange(&["--resolver", &upstream.to_string()], &test_query(Some(Edns::default())));
+  assert_eq!(response.rcode(), Rcode::ServFail);
+  assert!(response.answers().unwrap().is_empty());
+  let options = response.edns().unwrap().unwrap().options;
+  assert!(matches!(&options[..], [EdnsOption::ExtendedError { info_code: EDE_NETWORK_ERROR, .. }]), "{options:?}");
+  // Nor is one for another question, or the query bounced back, and the real answer after them still counts.
+  let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+  let upstream = socket.local_addr().unwrap();
+  std::thread::spawn(move || {
+    let mut buf = [0; MAX_UDP_PAYLOAD_SIZE as usize];
+    let (size, source) = socket.recv_from(&mut buf).unwrap();
+    let query = Message::parse(&buf[..size]).unwrap();
+    let other = Message::query("example.net".parse().unwrap(), RecordType::A).id(query.id()).build().unwrap();
+    for reply in [answer(&other, "192.0.2.66"), Message::from(&query[..]), answer(&query, "192.0.2.1")] {
+      socket.send_to(&reply, source).unwrap();
+    }
+  });
+  let response = test_exchange(&["--resolver", &upstream.to_string()], &test_query(None));
+  assert_eq!(response.rcode(), Rcode::NoError);
+  assert_eq!(response.answers().unwrap(), ["example.com. 60 IN A 192.0.2.1".parse().unwrap()]);
+}
+
+#[test]
+fn test_upstream_query() {
+  let (upstream, queries) = fake_upstream(|query| Message::response_to(query).unwrap().build().unwrap());
+  let args = ["--resolver", &upstream.to_string()];
+  let request = Message::query("example.com".parse().unwrap(), RecordType::A).id(0x1234).aa(true).rd(false).ad(true).cd(true).build().unwrap();
+  let mut ids = Vec::new();
+  for _ in 0..2 {
+    test_exchange(&args, &request);
+    let query = queries.recv_timeout(Duration::from_secs(10)).unwrap();
+    assert_eq!(query.opcode(), Opcode::Query);
+    assert!(!query.is_response() && !query.aa() && !query.rd() && query.ad() && query.cd());
+    assert_eq!(query.questions().unwrap(), request.questions().unwrap());
+    ids.push(query.id());
+  }
+  // Each query gets an ID of its own, not the client's.
+  assert_ne!(ids, [0x1234, 0x1234]);
+}
+
+/// An upstream that answers with an A record, and echoes the client subnet it
+/// is sent with a scope of `scope_prefix`.
+#[cfg(test)]
+fn subnet_upstream(scope_prefix: u8) -> (SocketAddr, std::sync::mpsc::Receiver<Message>) {
+  fake_upstream(move |query| {
+    let subnet = query.edns().unwrap().and_then(|edns| edns.client_subnet());
+    let options = subnet.map(|subnet| EdnsOption::ClientSubnet(ClientSubnet { scope_prefix, ..subnet })).into_iter().collect();
+    let answer = "example.com. 60 IN A 192.0.2.1".parse().unwrap();
+    Message::response_to(query).unwrap().answer(answer).edns(Some(Edns { options, ..Edns::default() })).build().unwrap()
+  })
+}
+
+#[cfg(test)]
+fn subnet_query(address: &str, source_prefix: u8) -> Message {
+  let subnet = ClientSubnet::new(address.parse().unwrap(), source_prefix);
+  test_query(Some(Edns { options: vec![EdnsOption::ClientSubnet(subnet)], ..Edns::default() }))
+}
+
+/// The client subnet of the next query the upstream got.
+#[cfg(test)]
+fn sent_subnet(queries: &std::sync::mpsc::Receiver<Message>) -> Option<ClientSubnet> {
+  queries.recv_timeout(Duration::from_secs(10)).unwrap().edns().unwrap().and_then(|edns| edns.client_subnet())
+}
+
+#[cfg(test)]
+fn response_subnet(response: &Message) -> Option<ClientSubnet> {
+  response.edns().unwrap().and_then(|edns| edns.client_subnet())
+}
+
+#[test]
+fn test_client_subnet_allow_list() {
+  let (upstream, queries) = subnet_upstream(0);
+  let resolver = upstream.to_string();
+  // An upstream that is not allowed never sees a subnet, the client's or its address.
+  let response = test_exchange(&["--resolver", &resolver], &subnet_query("192.0.2.77", 32));
+  assert_eq!(sent_subnet(&queries), None);
+  assert_eq!(response_subnet(&response), Some(ClientSubnet::new("192.0.2.77".parse().unwrap(), 32)));
+  test_exchange(&["--resolver", &resolver], &test_query(None));
+  assert_eq!(sent_subnet(&queries), None);
+  // An allowed one gets the client's subnet, or else its address, cut to the configured prefix.
+  let args = ["--resolver", &resolver, "--client-subnet-resolver", "127.0.0.1", "--client-subnet-ipv4-prefix", "16"];
+  test_exchange(&args, &subnet_query("192.0.2.77", 32));
+  assert_eq!(sent_subnet(&queries), Some(ClientSubnet::new("192.0.0.0".parse().unwrap(), 16)));
+  test_exchange(&args, &subnet_query("192.0.2.77", 8));
+  assert_eq!(sent_subnet(&queries), Some(ClientSubnet::new("192.0.0.0".parse().unwrap(), 8)));
+  test_exchange(&args, &test_query(None));
+  assert_eq!(sent_subnet(&queries), Some(ClientSubnet::new("127.0.0.0".parse().unwrap(), 16)));
+}
+
+#[test]
+fn test_client_subnet_scope() {
+  let client = ClientSubnet::new("192.0.2.77".parse().unwrap(), 32);
+  for (upstream_scope, scope_prefix) in [(20, 20), (28, 24)] {
+    let (upstream, _) = subnet_upstream(upstream_scope);
+    let args = ["--resolver", &upstream.to_string(), "--client-subnet-resolver", "127.0.0.1"];
+    let response = test_exchange(&args, &subnet_query("192.0.2.77", 32));
+    // The client's own subnet comes back, with no more scope than the 24 bits that went upstream.
+    assert_eq!(response_subnet(&response), Some(ClientSubnet { scope_prefix, ..client }));
+  }
+}
+
+#[test]
+fn test_client_subnet_opt_out() {
+  let (upstream, queries) = subnet_upstream(0);
+  let args = ["--resolver", &upstream.to_string(), "--client-subnet-resolver", "127.0.0.1"];
+  // A source prefix of 0 asks that nothing of the client's address goes upstream (RFC 7871 section 7.1.2).
+  let response = test_exchange(&args, &subnet_query("192.0.2.77", 0));
+  let none = ClientSubnet::new(Ipv4Addr::UNSPECIFIED.into(), 0);
+  assert_eq!(sent_subnet(&queries), Some(none));
+  assert_eq!(response_subnet(&response), Some(none));
+  assert_eq!(response.answers().unwrap().len(), 1);
+}
@@ -1,21 +1,50 @@
 #![allow(dead_code)]
 use std::{
+  collections::HashMap,
   fmt::Debug,
-  iter::once,
   ops::{Deref, DerefMut},
 };
 
 use bytes::{BufMut, BytesMut};
-use nom::Offset;
-use crate::parser::{expand_question, parse_domains};
+use crate::{
+  dnssec::encode_type_bitmap,
+  edns::{Edns, EdnsOption},
+  error::DnsError,
+  header::{self, Flags, Header, Opcode, Rcode},
+  name::Name,
+  message_ref::MessageRef,
+  record::{Question, RData, RecordType, ResourceRecord},
+};
+
+pub const HEADER_LENGTH: usize = 12;
 
-const HEADER_LENGTH: usize = 12;
+/// Pointers can only address the first 16 KiB of a message.
+const MAX_POINTER_OFFSET: usize = 0x3fff;
 
-pub struct Message(BytesMut);
+pub struct Message {
+  buf: BytesMut,
+  /// Offsets of the name suffixes written so far, for RFC 1035 compression.
+  names: HashMap<Vec<Vec<u8>>, u16>,
+  compress: bool,
+}
 
 impl Message {
   pub fn new() -> Self {
-    Self(BytesMut::zeroed(HEADER_LENGTH))
+    Self::from(&[0u8; HEADER_LENGTH][..])
+  }
+  /// Wraps a received datagram, checking it is at least long enough to hold a header.
+  pub fn parse(data: &[u8]) -> Result<Self, DnsError> {
+    if data.len() < HEADER_LENGTH {
+      return Err(DnsError::MalformedHeader);
+    }
+    Ok(Self::from(data))
+  }
+  pub fn compression(&self) -> bool {
+    self.compress
+  }
+  /// Turns name compression on or off for names written from now on.
+  pub fn set_compression(&mut self, compress: bool) {
+    self.compress = compress;
   }
   pub fn id(&self) -> u16 {
     u16::from_be_bytes([self[0], self[1]])
@@ -23,23 +52,93 @@ impl Message {
   pub fn set_id(&mut self, id: u16) {
     self[..2].copy_from_slice(&id.to_be_bytes());
   }
+  pub fn header(&self) -> Header {
+    Header {
+      id: self.id(),
+      flags: self.flags(),
+      question_count: self.question_count(),
+      answer_count: self.answer_count(),
+      authority_count: self.authority_count(),
+      additional_count: self.additional_count(),
+    }
+  }
+  pub fn flags(&self) -> Flags {
+    Flags::from(u16::from_be_bytes([self[2], self[3]]))
+  }
+  pub fn set_flags(&mut self, flags: Flags) {
+    self[2..4].copy_from_slice(&u16::from(flags).to_be_bytes());
+  }
+  fn flag(&self, mask: u16) -> bool {
+    u16::from_be_bytes([self[2], self[3]]) & mask != 0
+  }
+  fn set_flag(&mut self, mask: u16, value: bool) {
+    let bits = u16::from_be_bytes([self[2], self[3]]);
+    let bits = if value { bits | mask } else { bits & !mask };
+    self[2..4].copy_from_slice(&bits.to_be_bytes());
+  }
+  pub fn is_response(&self) -> bool {
+    self.flag(header::QR)
+  }
   pub fn set_query(&mut self) {
-    self[2] &= 0b0111_1111;
+    self.set_flag(header::QR, false);
   }
   pub fn set_response(&mut self) {
-    self[2] |= 0b1000_0000;
+    self.set_flag(header::QR, true);
+  }
+  pub fn opcode(&self) -> Opcode {
+    Opcode::from(self[2] << 1 >> 4)
+  }
+  pub fn set_opcode(&mut self, opcode: Opcode) {
+    self[2] = (self[2] & 0b1000_0111) | (u8::from(opcode) << 3);
+  }
+  pub fn aa(&self) -> bool {
+    self.flag(header::AA)
   }
-  pub fn opcode(&self) -> u8 {
-    self[2] << 1 >> 4
+  pub fn set_aa(&mut self, aa: bool) {
+    self.set_flag(header::AA, aa);
   }
-  pub fn rd(&self) -> u8 {
-    self[2] & 0b0000_0001
+  pub fn tc(&self) -> bool {
+    self.flag(header::TC)
   }
-  pub fn rcode(&self) -> u8 {
-    self[3] & 0b0000_1111
+  pub fn set_tc(&mut self, tc: bool) {
+    self.set_flag(header::TC, tc);
   }
-  pub fn set_rcode(&mut self, rcode: u8) {
-    self[3] = (self[3] & 0b1111_0000) | (0b0000_1111 & rcode)
+  pub fn rd(&self) -> bool {
+    self.flag(header::RD)
+  }
+  pub fn set_rd(&mut self, rd: bool) {
+    self.set_flag(header::RD, rd);
+  }
+  pub fn ra(&self) -> bool {
+    self.flag(header::RA)
+  }
+  pub fn set_ra(&mut self, ra: bool) {
+    self.set_flag(header::RA, ra);
+  }
+  pub fn z(&self) -> bool {
+    self.flag(header::Z)
+  }
+  pub fn set_z(&mut self, z: bool) {
+    self.set_flag(header::Z, z);
+  }
+  pub fn ad(&self) -> bool {
+    self.flag(header::AD)
+  }
+  pub fn set_ad(&mut self, ad: bool) {
+    self.set_flag(header::AD, ad);
+  }
+  pub fn cd(&self) -> bool {
+    self.flag(header::CD)
+  }
+  pub fn set_cd(&mut self, cd: bool) {
+    self.set_flag(header::CD, cd);
+  }
+  /// The rcode as carried in the header, without any extension from an OPT record.
+  pub fn rcode(&self) -> Rcode {
+    Rcode::from((self[3] & 0b0000_1111) as u16)
+  }
+  pub fn set_rcode(&mut self, rcode: Rcode) {
+    self[3] = (self[3] & 0b1111_0000) | rcode.low()
   }
   pub fn question_count(&self) -> u16 {
     u16::from_be_bytes([self[4], self[5]])
@@ -53,73 +152,596 @@ impl Message {
   pub fn set_answer_count(&mut self, count: u16) {
     self[6..8].copy_from_slice(&count.to_be_bytes());
   }
-  pub fn original_questions(&self) -> Vec<&[u8]> {
-    let (_, questions) = parse_domains(&self[HEADER_LENGTH..], self.question_count() as usize).unwrap();
-    questions
-  }
-  
-  pub fn expanded_questions(&self) -> Vec<BytesMut> {
-    let mut res = Vec::new();
-    let mut qc = self.question_count();
-    let mut offset = HEADER_LENGTH;
-    loop {
-      let (r, question) = expand_question(self, offset).unwrap();
-      res.push(question);
-      qc -= 1;
-      if qc == 0 {
-        break;
-      }
-      offset = (self).offset(r);
+  pub fn authority_count(&self) -> u16 {
+    u16::from_be_bytes([self[8], self[9]])
+  }
+  pub fn set_authority_count(&mut self, count: u16) {
+    self[8..10].copy_from_slice(&count.to_be_bytes());
+  }
+  pub fn additional_count(&self) -> u16 {
+    u16::from_be_bytes([self[10], self[11]])
+  }
+  pub fn set_additional_count(&mut self, count: u16) {
+    self[10..12].copy_from_slice(&count.to_be_bytes());
+  }
+  /// A message with the same header but no questions or records, ready to be filled in.
+  pub fn header_only(&self) -> Self {
+    let mut message = Self::from(&self[..HEADER_LENGTH]);
+    message.set_compression(self.compress);
+    message.set_question_count(0);
+    message.set_answer_count(0);
+    message.set_authority_count(0);
+    message.set_additional_count(0);
+    message
+  }
+
+  /// A borrowed view for reading the message without decoding all of it.
+  pub fn view(&self) -> MessageRef<'_> {
+    MessageRef::from(self)
+  }
+
+  pub fn questions(&self) -> Result<Vec<Question>, DnsError> {
+    self.view().questions().map(|question| Ok(question?.to_question())).collect()
+  }
+
+  pub fn answers(&self) -> Result<Vec<ResourceRecord>, DnsError> {
+    self.view().answers().map(|record| record?.to_record()).collect()
+  }
+
+  pub fn authorities(&self) -> Result<Vec<ResourceRecord>, DnsError> {
+    self.view().authorities().map(|record| record?.to_record()).collect()
+  }
+
+  pub fn additionals(&self) -> Result<Vec<ResourceRecord>, DnsError> {
+    self.view().additionals().map(|record| record?.to_record()).collect()
+  }
+
+  /// The OPT record of the additional section, if there is one.
+  pub fn edns(&self) -> Result<Option<Edns>, DnsError> {
+    self.view().edns()
+  }
+
+  /// The full 12 bit rcode, combining the header with the OPT record.
+  pub fn extended_rcode(&self) -> Result<Rcode, DnsError> {
+    self.view().extended_rcode()
+  }
+
+  /// Checks that the sections hold exactly the records the header counts, with nothing after them.
+  pub fn validate(&self) -> Result<(), DnsError> {
+    self.view().validate()
+  }
+
+  /// A header-only FORMERR reply to a datagram that could not be parsed,
+  /// or `None` when it is too short to even carry an ID.
+  pub fn format_error(data: &[u8]) -> Option<Self> {
+    if data.len() < 2 {
+      return None;
+    }
+    let mut message = Self::new();
+    message.set_id(u16::from_be_bytes([data[0], data[1]]));
+    if let Some(flags) = data.get(2) {
+      let flags = Flags::from(u16::from_be_bytes([*flags, 0]));
+      message.set_opcode(flags.opcode);
+      message.set_rd(flags.rd);
+    }
+    message.set_response();
+    message.set_rcode(Rcode::FormErr);
+    Some(message)
+  }
+
+  /// Sections are written one after another, so nothing more can go into one
+  /// once a later section has records.
+  fn check_section_order(later_counts: &[u16]) -> Result<(), DnsError> {
+    if later_counts.iter().any(|&count| count != 0) {
+      return Err(DnsError::SectionOrder);
     }
-    res
+    Ok(())
   }
 
-  pub fn add_question(&mut self, question: &[u8]) {
-    assert_eq!(self.answer_count(), 0);
-    self.put(question);
+  pub fn add_question(&mut self, question: &Question) -> Result<(), DnsError> {
+    Self::check_section_order(&[self.answer_count(), self.authority_count(), self.additional_count()])?;
+    self.put_name(&question.name, true);
+    self.put_u16(question.qtype.into());
+    self.put_u16(question.qclass.into());
     self.set_question_count(self.question_count() + 1);
+    Ok(())
   }
 
-  pub fn add_answer(&mut self, answer: &[u8]) {
-    self.put(answer);
+  pub fn add_answer(&mut self, answer: &ResourceRecord) -> Result<(), DnsError> {
+    Self::check_section_order(&[self.authority_count(), self.additional_count()])?;
+    self.put_record(answer)?;
     self.set_answer_count(self.answer_count() + 1);
+    Ok(())
+  }
+
+  pub fn add_authority(&mut self, authority: &ResourceRecord) -> Result<(), DnsError> {
+    Self::check_section_order(&[self.additional_count()])?;
+    self.put_record(authority)?;
+    self.set_authority_count(self.authority_count() + 1);
+    Ok(())
+  }
+
+  pub fn add_additional(&mut self, additional: &ResourceRecord) -> Result<(), DnsError> {
+    self.put_record(additional)?;
+    self.set_additional_count(self.additional_count() + 1);
+    Ok(())
+  }
+
+  pub fn answer_question(&mut self, question: &Question, ttl: u32, rdata: RData) -> Result<(), DnsError> {
+    self.add_answer(&ResourceRecord::new(question.name.clone(), question.qclass, ttl, rdata))
+  }
+
+  /// Writes `name`, pointing at an earlier copy of its longest possible suffix when
+  /// both the message and the field allow compression.
+  fn put_name(&mut self, name: &Name, compressible: bool) {
+    let labels = name.labels();
+    for (i, label) in labels.iter().enumerate() {
+      if self.compress && compressible {
+        if let Some(&offset) = self.names.get(&labels[i..]) {
+          self.put_u16(0b1100_0000_0000_0000 | offset);
+          return;
+        }
+        if self.len() <= MAX_POINTER_OFFSET {
+          let offset = self.len() as u16;
+          self.names.insert(labels[i..].to_vec(), offset);
+        }
+      }
+      self.put_u8(label.len() as u8);
+      self.put(&label[..]);
+    }
+    self.put_u8(0);
   }
-  
-  pub fn answer_question(&mut self, question: &[u8], ttl: u32, data: &[u8]) {
-    self.put(question);
-    self.put_u32(ttl);
-    self.put_u16(data.len() as u16);
+
+  /// Writes `data` after its length in a byte, the `field` of an `rtype` record.
+  fn put_u8_sized(&mut self, rtype: RecordType, field: &'static str, data: &[u8]) -> Result<(), DnsError> {
+    let length = u8::try_from(data.len()).map_err(|_| DnsError::FieldTooLong { rtype, field, len: data.len(), max: usize::from(u8::MAX) })?;
+    self.put_u8(length);
     self.put(data);
-    let answer_count = u16::from_be_bytes([self[6], self[7]]) + 1;
-    self[6..8].copy_from_slice(&answer_count.to_be_bytes());
+    Ok(())
+  }
+
+  /// Writes `data` after its length in two bytes, like `put_u8_sized`.
+  fn put_u16_sized(&mut self, rtype: RecordType, field: &'static str, data: &[u8]) -> Result<(), DnsError> {
+    let length = u16::try_from(data.len()).map_err(|_| DnsError::FieldTooLong { rtype, field, len: data.len(), max: usize::from(u16::MAX) })?;
+    self.put_u16(length);
+    self.put(data);
+    Ok(())
+  }
+
+  fn put_edns_option(&mut self, option: &EdnsOption) -> Result<(), DnsError> {
+    self.put_u16(option.code());
+    self.put_u16_sized(RecordType::OPT, "option", &option.data())
+  }
+
+  /// Writes `record`, or leaves the message as it was if a field does not fit.
+  fn put_record(&mut self, record: &ResourceRecord) -> Result<(), DnsError> {
+    let start = self.len();
+    let written = self.put_record_fields(record);
+    if written.is_err() {
+      self.truncate(start);
+      self.names.retain(|_, offset| usize::from(*offset) < start);
+    }
+    written
+  }
+
+  fn put_record_fields(&mut self, record: &ResourceRecord) -> Result<(), DnsError> {
+    self.put_name(&record.name, true);
+    self.put_u16(record.rtype().into());
+    self.put_u16(record.class.into());
+    self.put_u32(record.ttl);
+    let length_offset = self.len();
+    self.put_u16(0);
+    self.put_rdata(&record.rdata)?;
+    let length = rdata_length(record.rtype(), self.len() - length_offset - 2)?;
+    self[length_offset..length_offset + 2].copy_from_slice(&length.to_be_bytes());
+    Ok(())
+  }
+
+  fn put_rdata(&mut self, rdata: &RData) -> Result<(), DnsError> {
+    let rtype = rdata.record_type();
+    match rdata {
+      RData::A(ip) => self.put(&ip.octets()[..]),
+      RData::AAAA(ip) => self.put(&ip.octets()[..]),
+      RData::NS(name) | RData::CNAME(name) | RData::PTR(name) => self.put_name(name, true),
+      RData::MX { preference, exchange } => {
+        self.put_u16(*preference);
+        self.put_name(exchange, true);
+      }
+      RData::TXT(strings) => {
+        for string in strings {
+          self.put_u8_sized(rtype, "string", string)?;
+        }
+      }
+      RData::SOA { mname, rname, serial, refresh, retry, expire, minimum } => {
+        self.put_name(mname, true);
+        self.put_name(rname, true);
+        for value in [serial, refresh, retry, expire, minimum] {
+          self.put_u32(*value);
+        }
+      }
+      // RFC 2782 forbids compressing the target.
+      RData::SRV { priority, weight, port, target } => {
+        self.put_u16(*priority);
+        self.put_u16(*weight);
+        self.put_u16(*port);
+        self.put_name(target, false);
+      }
+      RData::CAA { flags, tag, value } => {
+        self.put_u8(*flags);
+        self.put_u8_sized(rtype, "tag", tag)?;
+        self.put(&value[..]);
+      }
+      RData::OPT(options) => {
+        for option in options {
+          self.put_edns_option(option)?;
+        }
+      }
+      RData::DS { key_tag, algorithm, digest_type, digest } => {
+        self.put_u16(*key_tag);
+        self.put_u8(*algorithm);
+        self.put_u8(*digest_type);
+        self.put(&digest[..]);
+      }
+      // RFC 4034 forbids compressing the names in DNSSEC records.
+      RData::RRSIG { type_covered, algorithm, labels, original_ttl, expiration, inception, key_tag, signer_name, signature } => {
+        self.put_u16((*type_covered).into());
+        self.put_u8(*algorithm);
+        self.put_u8(*labels);
+        for value in [original_ttl, expiration, inception] {
+          self.put_u32(*value);
+        }
+        self.put_u16(*key_tag);
+        self.put_name(signer_name, false);
+        self.put(&signature[..]);
+      }
+      RData::NSEC { next_domain, types } => {
+        self.put_name(next_domain, false);
+        self.put(&encode_type_bitmap(types)[..]);
+      }
+      RData::DNSKEY { flags, protocol, algorithm, public_key } => {
+        self.put_u16(*flags);
+        self.put_u8(*protocol);
+        self.put_u8(*algorithm);
+        self.put(&public_key[..]);
+      }
+      RData::NSEC3 { hash_algorithm, flags, iterations, salt, next_hashed_owner, types } => {
+        self.put_u8(*hash_algorithm);
+        self.put_u8(*flags);
+        self.put_u16(*iterations);
+        self.put_u8_sized(rtype, "salt", salt)?;
+        self.put_u8_sized(rtype, "next hashed owner", next_hashed_owner)?;
+        self.put(&encode_type_bitmap(types)[..]);
+      }
+      RData::NSEC3PARAM { hash_algorithm, flags, iterations, salt } => {
+        self.put_u8(*hash_algorithm);
+        self.put_u8(*flags);
+        self.put_u16(*iterations);
+        self.put_u8_sized(rtype, "salt", salt)?;
+      }
+      // RFC 9460 forbids compressing the target.
+      RData::SVCB { priority, target, params } | RData::HTTPS { priority, target, params } => {
+        self.put_u16(*priority);
+        self.put_name(target, false);
+        for param in params {
+          self.put_u16(param.key());
+          self.put_u16_sized(rtype, "SvcParam value", &param.value()?)?;
+        }
+      }
+      // RFC 8945 forbids compressing the algorithm name.
+      RData::TSIG { algorithm, time_signed, fudge, mac, original_id, error, other } => {
+        self.put_name(algorithm, false);
+        self.put_u16((time_signed >> 32) as u16);
+        self.put_u32(*time_signed as u32);
+        self.put_u16(*fudge);
+        self.put_u16_sized(rtype, "MAC", mac)?;
+        self.put_u16(*original_id);
+        self.put_u16(*error);
+        self.put_u16_sized(rtype, "other data", other)?;
+      }
+      RData::Unknown { data, .. } => self.put(&data[..]),
+    }
+    Ok(())
+  }
+}
+
+impl Default for Message {
+  fn default() -> Self {
+    Self::new()
   }
 }
 
 impl From<&[u8]> for Message {
   fn from(value: &[u8]) -> Self {
-    Self(BytesMut::from(value))
+    Self {
+      buf: BytesMut::from(value),
+      names: HashMap::new(),
+      compress: true,
+    }
   }
 }
 
 impl Deref for Message {
   type Target = BytesMut;
   fn deref(&self) -> &Self::Target {
-    &self.0
+    &self.buf
   }
 }
 
 impl DerefMut for Message {
   fn deref_mut(&mut self) -> &mut Self::Target {
-    &mut self.0
+    &mut self.buf
   }
 }
 
 impl Debug for Message {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-    self.0.fmt(f)
+    self.buf.fmt(f)
   }
 }
 
-pub fn encode_domain(name: &str) -> BytesMut {
-  name.split('.').flat_map(|label| once(label.len() as u8).chain(label.bytes())).chain(once(0u8)).collect()
-}
\ No newline at end of file
+/// The length of the rdata of an `rtype` record, which has to fit in two bytes.
+fn rdata_length(rtype: RecordType, len: usize) -> Result<u16, DnsError> {
+  u16::try_from(len).map_err(|_| DnsError::FieldTooLong { rtype, field: "rdata", len, max: usize::from(u16::MAX) })
+}
+
+/// The uncompressed wire format of `rdata`, which fails like `add_answer` on
+/// fields too long for the length in front of them.
+pub fn encode_rdata(rdata: &RData) -> Result<Vec<u8>, DnsError> {
+  let mut message = Message::new();
+  message.set_compression(false);
+  message.put_rdata(rdata)?;
+  rdata_length(rdata.record_type(), message.len() - HEADER_LENGTH)?;
+  Ok(message[HEADER_LENGTH..].to_vec())
+}
+
+/// The uncompressed wire format of `name`, which must be a valid domain name.
+pub fn encode_domain(name: &str) -> Result<BytesMut, DnsError> {
+  Ok(BytesMut::from(&name.parse::<Name>()?.to_wire()[..]))
+}
+
+#[test]
+fn test_question_and_answer_round_trip() {
+  use crate::record::{DnsClass, RecordType};
+  use std::net::Ipv4Addr;
+  let question = Question::new(Name::from_labels(["codecrafters", "io"]).unwrap(), RecordType::A, DnsClass::IN);
+  let answer = ResourceRecord::new(question.name.clone(), DnsClass::IN, 60, RData::A(Ipv4Addr::new(8, 8, 8, 8)));
+  let mut message = Message::new();
+  message.add_question(&question).unwrap();
+  message.add_answer(&answer).unwrap();
+  assert_eq!(&message[HEADER_LENGTH..], b"\x0ccodecrafters\x02io\0\0\x01\0\x01\xc0\x0c\0\x01\0\x01\0\0\0\x3c\0\x04\x08\x08\x08\x08");
+  assert_eq!(message.questions().unwrap(), vec![question]);
+  assert_eq!(message.answers().unwrap(), vec![answer]);
+}
+
+#[test]
+fn test_name_compression() {
+  use crate::record::{DnsClass, RecordType};
+  let www = Name::from_labels(["www", "example", "com"]).unwrap();
+  let example = Name::from_labels(["example", "com"]).unwrap();
+  let mut message = Message::new();
+  message.add_question(&Question::new(www.clone(), RecordType::CNAME, DnsClass::IN)).unwrap();
+  message.add_answer(&ResourceRecord::new(www.clone(), DnsClass::IN, 60, RData::CNAME(example.clone()))).unwrap();
+  message.add_answer(&ResourceRecord::new(Name::from_labels(["mail", "example", "com"]).unwrap(), DnsClass::IN, 60, RData::CNAME(www.clone()))).unwrap();
+  assert_eq!(
+    &message[HEADER_LENGTH..],
+    b"\x03www\x07example\x03com\0\0\x05\0\x01\xc0\x0c\0\x05\0\x01\0\0\0\x3c\0\x02\xc0\x10\x04mail\xc0\x10\0\x05\0\x01\0\0\0\x3c\0\x02\xc0\x0c"
+  );
+  let answers = message.answers().unwrap();
+  assert_eq!(answers[0].rdata, RData::CNAME(example));
+  assert_eq!(answers[1].name, Name::from_labels(["mail", "example", "com"]).unwrap());
+  assert_eq!(answers[1].rdata, RData::CNAME(www.clone()));
+
+  let mut uncompressed = Message::new();
+  uncompressed.set_compression(false);
+  uncompressed.add_question(&Question::new(www.clone(), RecordType::CNAME, DnsClass::IN)).unwrap();
+  uncompressed.add_answer(&ResourceRecord::new(www.clone(), DnsClass::IN, 60, RData::CNAME(www))).unwrap();
+  assert!(!uncompressed.contains(&0xc0));
+  assert_eq!(uncompressed.len(), HEADER_LENGTH + 21 + 44);
+}
+
+#[test]
+fn test_rdata_round_trip() {
+  use crate::record::{DnsClass, RecordType};
+  use std::net::Ipv6Addr;
+  let zone = Name::from_labels(["example", "com"]).unwrap();
+  let host = |label: &str| Name::from_labels([label, "example", "com"]).unwrap();
+  let rdatas = [
+    RData::AAAA(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1)),
+    RData::MX { preference: 10, exchange: host("mail") },
+    RData::TXT(vec![b"v=spf1 -all".to_vec(), b"".to_vec()]),
+    RData::SOA { mname: host("ns1"), rname: host("hostmaster"), serial: 2024010101, refresh: 7200, retry: 3600, expire: 1209600, minimum: 300 },
+    RData::SRV { priority: 0, weight: 5, port: 5060, target: host("sip") },
+    RData::CAA { flags: 128, tag: b"issue".to_vec(), value: b"ca.example.net".to_vec() },
+  ];
+  let mut message = Message::new();
+  message.add_question(&Question::new(zone.clone(), RecordType::ANY, DnsClass::IN)).unwrap();
+  for rdata in &rdatas {
+    message.add_answer(&ResourceRecord::new(zone.clone(), DnsClass::IN, 300, rdata.clone())).unwrap();
+  }
+  let answers = Message::parse(&message).unwrap().answers().unwrap();
+  assert_eq!(answers.into_iter().map(|answer| answer.rdata).collect::<Vec<_>>(), rdatas);
+  // The MX exchange and SOA names are compressed, the SRV target is not.
+  let wire: &[u8] = &message;
+  assert!(wire.windows(6).any(|w| w == b"\x04mail\xc0"));
+  assert!(wire.windows(17).any(|w| w == b"\x03sip\x07example\x03com\0"));
+}
+
+#[test]
+fn test_unknown_rdata_is_copied_verbatim() {
+  use crate::record::{DnsClass, RecordType};
+  let zone = Name::from_labels(["example", "com"]).unwrap();
+  // Looks like a name that could be compressed against the owner, which must not happen.
+  let data = b"\x07example\x03com\0\xc0\x0c".to_vec();
+  let rdata = RData::Unknown { rtype: RecordType::Unknown(65534), data: data.clone() };
+  let mut message = Message::new();
+  message.add_answer(&ResourceRecord::new(zone.clone(), DnsClass::IN, 60, rdata.clone())).unwrap();
+  message.add_answer(&ResourceRecord::new(zone, DnsClass::IN, 60, rdata.clone())).unwrap();
+  let answers = Message::parse(&message).unwrap().answers().unwrap();
+  assert!(answers.iter().all(|answer| answer.rdata == rdata));
+  let wire: &[u8] = &message;
+  assert_eq!(wire.windows(data.len()).filter(|w| *w == &data[..]).count(), 2);
+}
+
+#[test]
+fn test_bad_rdata_does_not_fail_the_message() {
+  use crate::{record::{DnsClass, RecordType}, svcb::SvcParam};
+  let zone = Name::from_labels(["example", "com"]).unwrap();
+  // SvcParams out of order, and an address one byte short.
+  let https = RData::HTTPS { priority: 1, target: Name::root(), params: vec![SvcParam::Port(443), SvcParam::Alpn(vec![b"h2".to_vec()])] };
+  let short = RData::Unknown { rtype: RecordType::A, data: vec![192, 0, 2] };
+  let good = RData::A([192, 0, 2, 1].into());
+  let mut message = Message::new();
+  for rdata in [&https, &short, &good] {
+    message.add_answer(&ResourceRecord::new(zone.clone(), DnsClass::IN, 60, rdata.clone())).unwrap();
+  }
+  let answers = Message::parse(&message).unwrap().answers().unwrap();
+  assert_eq!(answers.into_iter().map(|answer| answer.rdata).collect::<Vec<_>>(), [https, short, good]);
+}
+
+#[test]
+fn test_oversized_fields() {
+  use crate::{builder::MessageBuilder, record::DnsClass, svcb::SvcParam};
+  let txt = RData::TXT(vec![vec![b'a'; 256]]);
+  assert!(matches!(txt.validate(), Err(DnsError::FieldTooLong { field: "string", len: 256, max: 255, .. })));
+  assert!(matches!(encode_rdata(&txt), Err(DnsError::FieldTooLong { field: "string", .. })));
+  // A record that does not fit is left out whole, compressed names and all.
+  let zone = Name::from_labels(["example", "com"]).unwrap();
+  let mut message = Message::new();
+  message.add_answer(&ResourceRecord::new(zone.clone(), DnsClass::IN, 60, RData::TXT(vec![b"ok".to_vec()]))).unwrap();
+  let before = message.to_vec();
+  let long = ResourceRecord::new(Name::from_labels(["www", "example", "com"]).unwrap(), DnsClass::IN, 60, txt);
+  assert!(matches!(message.add_answer(&long), Err(DnsError::FieldTooLong { .. })));
+  assert_eq!(&message[..], &before[..]);
+  message.add_answer(&ResourceRecord::new(Name::from_labels(["www", "example", "com"]).unwrap(), DnsClass::IN, 60, RData::A([192, 0, 2, 1].into()))).unwrap();
+  assert_eq!(message.answers().unwrap().len(), 2);
+  assert!(matches!(MessageBuilder::new(0).answer(long).build(), Err(DnsError::FieldTooLong { field: "string", .. })));
+  let option = EdnsOption::Unknown { code: 65001, data: vec![0; 65536] };
+  let edns = Edns { options: vec![option], ..Edns::default() };
+  assert!(matches!(MessageBuilder::new(0).edns(Some(edns)).build(), Err(DnsError::FieldTooLong { field: "option", .. })));
+  let https = RData::HTTPS { priority: 1, target: Name::root(), params: vec![SvcParam::Alpn(vec![vec![b'h'; 256]])] };
+  assert!(matches!(encode_rdata(&https), Err(DnsError::BadSvcParams(_))));
+  let nsec3 = RData::NSEC3PARAM { hash_algorithm: 1, flags: 0, iterations: 0, salt: vec![0; 300] };
+  assert!(matches!(nsec3.validate(), Err(DnsError::FieldTooLong { field: "salt", .. })));
+  let caa = |tag: &[u8]| RData::CAA { flags: 0, tag: tag.to_vec(), value: b"ca.example.net".to_vec() };
+  assert!(caa(b"issue").validate().is_ok());
+  assert!(matches!(caa(b"").validate(), Err(DnsError::BadRdata(RecordType::CAA))));
+  assert!(matches!(caa(b"is sue").validate(), Err(DnsError::BadRdata(RecordType::CAA))));
+  let unknown = RData::Unknown { rtype: RecordType::Unknown(65534), data: vec![0; 65536] };
+  assert!(matches!(unknown.validate(), Err(DnsError::FieldTooLong { field: "rdata", .. })));
+}
+
+#[test]
+fn test_all_sections_round_trip() {
+  use crate::record::{DnsClass, RecordType};
+  use std::net::Ipv4Addr;
+  let zone = Name::from_labels(["example", "com"]).unwrap();
+  let ns = Name::from_labels(["ns1", "example", "com"]).unwrap();
+  let question = Question::new(zone.clone(), RecordType::NS, DnsClass::IN);
+  let answer = ResourceRecord::new(zone.clone(), DnsClass::IN, 3600, RData::NS(ns.clone()));
+  let authority = ResourceRecord::new(zone, DnsClass::IN, 3600, RData::NS(ns.clone()));
+  let glue = ResourceRecord::new(ns, DnsClass::IN, 3600, RData::A(Ipv4Addr::new(192, 0, 2, 1)));
+  let mut message = Message::new();
+  message.add_question(&question).unwrap();
+  message.add_answer(&answer).unwrap();
+  message.add_authority(&authority).unwrap();
+  message.add_additional(&glue).unwrap();
+  assert!(matches!(message.add_question(&question), Err(DnsError::SectionOrder)));
+  assert!(matches!(message.add_answer(&answer), Err(DnsError::SectionOrder)));
+  assert!(matches!(message.add_authority(&authority), Err(DnsError::SectionOrder)));
+  let message = Message::from(&message[..]);
+  assert_eq!(message.questions().unwrap(), vec![question]);
+  assert_eq!(message.answers().unwrap(), vec![answer]);
+  assert_eq!(message.authorities().unwrap(), vec![authority]);
+  assert_eq!(message.additionals().unwrap(), vec![glue.clone()]);
+  let copy = message.header_only();
+  assert_eq!(copy.len(), HEADER_LENGTH);
+  assert_eq!(copy.id(), message.id());
+  assert_eq!(copy.additional_count(), 0);
+}
+
+#[test]
+fn test_header_flags() {
+  let mut message = Message::parse(b"\x12\x34\x01\x20\0\x01\0\x02\0\x03\0\x04").unwrap();
+  assert!(message.rd() && message.ad());
+  assert!(!message.is_response() && !message.cd());
+  message.set_response();
+  message.set_aa(true);
+  message.set_ra(true);
+  message.set_ad(false);
+  message.set_opcode(Opcode::Notify);
+  message.set_rcode(Rcode::Refused);
+  assert_eq!(&message[2..4], b"\xa5\x85");
+  let header = message.header();
+  assert_eq!(header.id, 0x1234);
+  assert_eq!(header.flags.opcode, Opcode::Notify);
+  assert!(header.flags.qr && header.flags.aa && header.flags.rd && header.flags.ra);
+  assert_eq!(header.flags.rcode, Rcode::Refused);
+  assert_eq!((header.question_count, header.answer_count, header.authority_count, header.additional_count), (1, 2, 3, 4));
+  message.set_flags(Flags::default());
+  assert_eq!(&message[2..4], b"\0\0");
+}
+
+#[test]
+fn test_edns() {
+  use crate::record::{DnsClass, RecordType};
+  let mut message = Message::new();
+  message.add_question(&Question::new(Name::from_labels(["example", "com"]).unwrap(), RecordType::A, DnsClass::IN)).unwrap();
+  assert_eq!(message.edns().unwrap(), None);
+  let edns = Edns {
+    extended_rcode: Rcode::BadCookie.high(),
+    dnssec_ok: true,
+    options: vec![EdnsOption::Unknown { code: 65001, data: vec![1, 2, 3] }],
+    ..Edns::default()
+  };
+  message.set_rcode(Rcode::BadCookie);
+  message.add_additional(&edns.to_record()).unwrap();
+  let message = Message::parse(&message).unwrap();
+  assert!(message.validate().is_ok());
+  assert_eq!(message.edns().unwrap(), Some(edns.clone()));
+  assert_eq!(message.rcode(), Rcode::YXRRSet);
+  assert_eq!(message.extended_rcode().unwrap(), Rcode::BadCookie);
+  let mut message = message;
+  message.add_additional(&edns.to_record()).unwrap();
+  assert!(matches!(message.edns(), Err(DnsError::DuplicateOpt)));
+}
+
+#[test]
+fn test_validate() {
+  let message = Message::parse(b"\x12\x34\x01\0\0\x01\0\0\0\0\0\0\x03abc\0\0\x01\0\x01").unwrap();
+  assert!(message.validate().is_ok());
+  let message = Message::parse(b"\x12\x34\x01\0\0\x01\0\0\0\0\0\0\x03abc\0\0\x01\0\x01junk").unwrap();
+  assert!(matches!(message.validate(), Err(DnsError::TrailingData(4))));
+  let message = Message::parse(b"\x12\x34\x01\0\0\x01\0\x01\0\0\0\0\x03abc\0\0\x01\0\x01").unwrap();
+  assert!(matches!(message.validate(), Err(DnsError::Truncated)));
+}
+
+#[test]
+fn test_format_error() {
+  assert!(Message::format_error(b"\x12").is_none());
+  let message = Message::format_error(b"\x12\x34").unwrap();
+  assert_eq!(&message[..], b"\x12\x34\x80\x01\0\0\0\0\0\0\0\0");
+  let message = Message::format_error(b"\x12\x34\x09\0\0\x01\0\0\0\0\0\x01junk").unwrap();
+  assert_eq!(&message[..], b"\x12\x34\x89\x01\0\0\0\0\0\0\0\0");
+  assert_eq!(message.opcode(), Opcode::IQuery);
+  assert!(message.rd());
+}
+
+#[test]
+fn test_parse_errors() {
+  assert!(matches!(Message::parse(b"\x12\x34\x01\0"), Err(DnsError::MalformedHeader)));
+  let message = Message::parse(b"\x12\x34\x01\0\0\x01\0\0\0\0\0\0\x03abc").unwrap();
+  assert!(matches!(message.questions(), Err(DnsError::Truncated)));
+  let message = Message::parse(b"\x12\x34\x01\0\0\x01\0\0\0\0\0\0\x83abc\0\0\x01\0\x01").unwrap();
+  assert!(matches!(message.questions(), Err(DnsError::BadLabel)));
+  let message = Message::parse(b"\x12\x34\x01\0\0\x01\0\0\0\0\0\0\x03abc\0\0\x01").unwrap();
+  assert!(matches!(message.questions(), Err(DnsError::Truncated)));
+}
+
+#[test]
+fn test_encode_domain() {
+  assert_eq!(&encode_domain("codecrafters.io").unwrap()[..], b"\x0ccodecrafters\x02io\0");
+  assert_eq!(&encode_domain("codecrafters.io.").unwrap()[..], b"\x0ccodecrafters\x02io\0");
+  assert_eq!(&encode_domain(".").unwrap()[..], b"\0");
+  assert!(matches!(encode_domain(&format!("{}.io", "a".repeat(64))), Err(DnsError::LabelTooLong(64))));
+  assert!(matches!(encode_domain("codecrafters..io"), Err(DnsError::EmptyLabel)));
+}
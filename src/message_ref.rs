@@ -0,0 +1,428 @@
+#![allow(dead_code)]
+use std::fmt;
+
+use nom::{
+  number::complete::{be_u16, be_u32},
+  sequence::tuple,
+  Offset,
+};
+use crate::{
+  edns::Edns,
+  error::DnsError,
+  header::{Flags, Header, Opcode, Rcode},
+  message::{Message, HEADER_LENGTH},
+  name::Name,
+  parser::{decode_wire_rdata, Labels},
+  record::{DnsClass, Question, RData, RecordType, ResourceRecord},
+};
+
+/// A read-only view of a message in a borrowed buffer. Questions and records are
+/// decoded one at a time as they are iterated, and names are left in the buffer.
+#[derive(Clone, Copy)]
+pub struct MessageRef<'a> {
+  data: &'a [u8],
+  /// Where the answer, authority and additional sections start, so that each can
+  /// be read without going through the ones before. `None` for a section that
+  /// something malformed comes before.
+  sections: [Option<usize>; 3],
+}
+
+impl<'a> MessageRef<'a> {
+  pub fn parse(data: &'a [u8]) -> Result<Self, DnsError> {
+    if data.len() < HEADER_LENGTH {
+      return Err(DnsError::MalformedHeader);
+    }
+    Ok(Self::new(data))
+  }
+
+  /// Finds where each section starts, once, without decoding any rdata.
+  fn new(data: &'a [u8]) -> Self {
+    let mut message = Self { data, sections: [None; 3] };
+    if data.len() < HEADER_LENGTH {
+      return message;
+    }
+    let Ok(mut position) = skip_questions(data) else {
+      return message;
+    };
+    let counts = [message.u16_at(6), message.u16_at(8), message.u16_at(10)];
+    for (start, count) in message.sections.iter_mut().zip(counts) {
+      *start = Some(position);
+      for _ in 0..count {
+        match read_record(data, position, Section::Answer) {
+          Ok((_, end)) => position = end,
+          Err(_) => return message,
+        }
+      }
+    }
+    message
+  }
+
+  pub fn as_bytes(&self) -> &'a [u8] {
+    self.data
+  }
+  pub fn to_message(self) -> Message {
+    Message::from(self.data)
+  }
+
+  fn u16_at(&self, offset: usize) -> u16 {
+    u16::from_be_bytes([self.data[offset], self.data[offset + 1]])
+  }
+  pub fn id(&self) -> u16 {
+    self.u16_at(0)
+  }
+  pub fn flags(&self) -> Flags {
+    Flags::from(self.u16_at(2))
+  }
+  pub fn opcode(&self) -> Opcode {
+    self.flags().opcode
+  }
+  pub fn rcode(&self) -> Rcode {
+    self.flags().rcode
+  }
+  pub fn is_response(&self) -> bool {
+    self.flags().qr
+  }
+  pub fn header(&self) -> Header {
+    Header {
+      id: self.id(),
+      flags: self.flags(),
+      question_count: self.u16_at(4),
+      answer_count: self.u16_at(6),
+      authority_count: self.u16_at(8),
+      additional_count: self.u16_at(10),
+    }
+  }
+
+  pub fn questions(&self) -> Questions<'a> {
+    Questions {
+      msg: self.data,
+      position: HEADER_LENGTH,
+      remaining: self.u16_at(4),
+      done: false,
+    }
+  }
+
+  /// Every record of the answer, authority and additional sections, in that order.
+  pub fn records(&self) -> Records<'a> {
+    Records {
+      msg: self.data,
+      position: None,
+      remaining: [self.u16_at(6), self.u16_at(8), self.u16_at(10)],
+      done: false,
+    }
+  }
+  pub fn answers(&self) -> impl Iterator<Item = Result<RecordRef<'a>, DnsError>> {
+    self.section(Section::Answer)
+  }
+  pub fn authorities(&self) -> impl Iterator<Item = Result<RecordRef<'a>, DnsError>> {
+    self.section(Section::Authority)
+  }
+  pub fn additionals(&self) -> impl Iterator<Item = Result<RecordRef<'a>, DnsError>> {
+    self.section(Section::Additional)
+  }
+  /// The records of one section, read from where it starts. When that is not
+  /// known, reading from the start of the message gets to the error that hides it.
+  fn section(&self, section: Section) -> impl Iterator<Item = Result<RecordRef<'a>, DnsError>> {
+    let index = section as usize;
+    let from_start = self.sections[index].map(|start| {
+      let mut remaining = [0; 3];
+      remaining[index] = self.u16_at(6 + 2 * index);
+      Records { msg: self.data, position: Some(start), remaining, done: false }
+    });
+    let from_message = from_start.is_none().then(|| {
+      self
+        .records()
+        .skip_while(move |record| matches!(record, Ok(record) if record.section < section))
+        .take_while(move |record| !matches!(record, Ok(record) if record.section > section))
+    });
+    from_start.into_iter().flatten().chain(from_message.into_iter().flatten())
+  }
+
+  pub fn edns(&self) -> Result<Option<Edns>, DnsError> {
+    let mut edns = None;
+    for record in self.additionals() {
+      let record = record?;
+      if record.rtype == RecordType::OPT {
+        if edns.is_some() {
+          return Err(DnsError::DuplicateOpt);
+        }
+        edns = Edns::from_record(&record.to_record()?);
+      }
+    }
+    Ok(edns)
+  }
+
+  /// The full 12 bit rcode, combining the header with the OPT record.
+  pub fn extended_rcode(&self) -> Result<Rcode, DnsError> {
+    Ok(match self.edns()? {
+      Some(edns) => edns.rcode(self.rcode()),
+      None => self.rcode(),
+    })
+  }
+
+  /// Checks that every section decodes, rdata included, that nothing follows the
+  /// last record, and that a TSIG record, if any, is that last record.
+  pub fn validate(&self) -> Result<(), DnsError> {
+    let (mut records, mut signed) = (self.records(), false);
+    for record in &mut records {
+      let record = record?;
+      record.rdata()?;
+      if signed {
+        return Err(DnsError::MisplacedTsig);
+      }
+      signed = record.rtype == RecordType::TSIG;
+      if signed && record.section != Section::Additional {
+        return Err(DnsError::MisplacedTsig);
+      }
+    }
+    let end = records.position.unwrap_or(self.data.len());
+    if end < self.data.len() {
+      return Err(DnsError::TrailingData(self.data.len() - end));
+    }
+    Ok(())
+  }
+}
+
+impl<'a> From<&'a Message> for MessageRef<'a> {
+  fn from(message: &'a Message) -> Self {
+    Self::new(message)
+  }
+}
+
+impl fmt::Debug for MessageRef<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("MessageRef").field("data", &self.data).finish()
+  }
+}
+
+/// A name left in the message it was read from.
+#[derive(Clone, Copy)]
+pub struct NameRef<'a> {
+  msg: &'a [u8],
+  offset: usize,
+}
+
+impl<'a> NameRef<'a> {
+  /// Checks the name at `offset` of `msg`, returning it and the offset following it.
+  fn read(msg: &'a [u8], offset: usize) -> Result<(Self, usize), DnsError> {
+    let end = Labels::new(msg, offset).skip()?;
+    Ok((Self { msg, offset }, end))
+  }
+  /// Where the name starts in its message, which for an owner name is where its record starts.
+  pub fn offset(&self) -> usize {
+    self.offset
+  }
+  pub fn labels(&self) -> impl Iterator<Item = &'a [u8]> {
+    // Already walked once by `read`, so this cannot fail.
+    Labels::new(self.msg, self.offset).map_while(Result::ok)
+  }
+  pub fn to_name(self) -> Name {
+    Name::from_labels(self.labels()).expect("labels were checked by NameRef::read")
+  }
+}
+
+impl PartialEq<Name> for NameRef<'_> {
+  fn eq(&self, other: &Name) -> bool {
+    let mut labels = self.labels();
+    other.labels().iter().all(|label| labels.next().is_some_and(|ours| ours.eq_ignore_ascii_case(label))) && labels.next().is_none()
+  }
+}
+
+impl fmt::Debug for NameRef<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "NameRef({self})")
+  }
+}
+
+impl fmt::Display for NameRef<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    self.to_name().fmt(f)
+  }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct QuestionRef<'a> {
+  pub name: NameRef<'a>,
+  pub qtype: RecordType,
+  pub qclass: DnsClass,
+}
+
+impl QuestionRef<'_> {
+  pub fn to_question(self) -> Question {
+    Question::new(self.name.to_name(), self.qtype, self.qclass)
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Section {
+  Answer,
+  Authority,
+  Additional,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RecordRef<'a> {
+  pub section: Section,
+  pub name: NameRef<'a>,
+  pub rtype: RecordType,
+  pub class: DnsClass,
+  pub ttl: u32,
+  /// The rdata as it is on the wire, possibly with pointers into the rest of the message.
+  pub data: &'a [u8],
+  msg: &'a [u8],
+}
+
+impl RecordRef<'_> {
+  pub fn rdata(&self) -> Result<RData, DnsError> {
+    Ok(decode_wire_rdata(self.msg, self.data, self.rtype)?)
+  }
+  pub fn to_record(self) -> Result<ResourceRecord, DnsError> {
+    Ok(ResourceRecord::new(self.name.to_name(), self.class, self.ttl, self.rdata()?))
+  }
+}
+
+pub struct Questions<'a> {
+  msg: &'a [u8],
+  position: usize,
+  remaining: u16,
+  done: bool,
+}
+
+impl<'a> Questions<'a> {
+  fn read(&mut self) -> Result<QuestionRef<'a>, DnsError> {
+    let (name, end) = NameRef::read(self.msg, self.position)?;
+    let i = &self.msg[end..];
+    let (r, (qtype, qclass)) = tuple((be_u16, be_u16))(i)?;
+    self.position = self.msg.offset(r);
+    Ok(QuestionRef { name, qtype: qtype.into(), qclass: qclass.into() })
+  }
+}
+
+impl<'a> Iterator for Questions<'a> {
+  type Item = Result<QuestionRef<'a>, DnsError>;
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.done || self.remaining == 0 {
+      return None;
+    }
+    self.remaining -= 1;
+    let question = self.read();
+    self.done = question.is_err();
+    Some(question)
+  }
+}
+
+pub struct Records<'a> {
+  msg: &'a [u8],
+  /// Start of the next record, once the questions have been skipped.
+  position: Option<usize>,
+  remaining: [u16; 3],
+  done: bool,
+}
+
+impl<'a> Records<'a> {
+  fn read(&mut self, section: Section) -> Result<RecordRef<'a>, DnsError> {
+    let position = match self.position {
+      Some(position) => position,
+      None => skip_questions(self.msg)?,
+    };
+    let (record, end) = read_record(self.msg, position, section)?;
+    self.position = Some(end);
+    Ok(record)
+  }
+}
+
+/// The record at `position`, and the offset following it.
+fn read_record(msg: &[u8], position: usize, section: Section) -> Result<(RecordRef<'_>, usize), DnsError> {
+  let (name, end) = NameRef::read(msg, position)?;
+  let (r, (rtype, class, ttl, length)) = tuple((be_u16, be_u16, be_u32, be_u16))(&msg[end..])?;
+  let data = r.get(..length as usize).ok_or(DnsError::TruncatedRdata)?;
+  let record = RecordRef { section, name, rtype: rtype.into(), class: class.into(), ttl, data, msg };
+  Ok((record, msg.offset(r) + data.len()))
+}
+
+/// The offset following the questions of a message at least a header long.
+fn skip_questions(msg: &[u8]) -> Result<usize, DnsError> {
+  let remaining = u16::from_be_bytes([msg[4], msg[5]]);
+  let mut questions = Questions { msg, position: HEADER_LENGTH, remaining, done: false };
+  for question in &mut questions {
+    question?;
+  }
+  Ok(questions.position)
+}
+
+impl<'a> Iterator for Records<'a> {
+  type Item = Result<RecordRef<'a>, DnsError>;
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.done {
+      return None;
+    }
+    let Some(index) = self.remaining.iter().position(|&count| count > 0) else {
+      // Nothing left, but `position` should still say where the records end.
+      self.done = true;
+      return match self.position {
+        Some(_) => None,
+        None => match skip_questions(self.msg) {
+          Ok(position) => {
+            self.position = Some(position);
+            None
+          }
+          Err(e) => Some(Err(e)),
+        },
+      };
+    };
+    self.remaining[index] -= 1;
+    let section = [Section::Answer, Section::Authority, Section::Additional][index];
+    let record = self.read(section);
+    self.done = record.is_err();
+    Some(record)
+  }
+}
+
+#[test]
+fn test_message_ref_sections() {
+  let message = Message::response_to(&Message::query("example.com".parse().unwrap(), RecordType::A).id(7).build().unwrap())
+    .unwrap()
+    .answer("example.com. 60 IN CNAME www.example.com.".parse().unwrap())
+    .answer("www.example.com. 60 IN A 192.0.2.1".parse().unwrap())
+    .authority("example.com. 60 IN NS ns.example.com.".parse().unwrap())
+    .edns(Some(Edns::default()))
+    .build().unwrap();
+  let view = MessageRef::from(&message);
+  assert_eq!(view.id(), 7);
+  assert!(view.is_response());
+  let questions: Vec<_> = view.questions().collect::<Result<_, _>>().unwrap();
+  assert_eq!(questions.len(), 1);
+  assert_eq!(questions[0].name, "example.com".parse::<Name>().unwrap());
+  assert_eq!(questions[0].qtype, RecordType::A);
+  let answers: Vec<_> = view.answers().collect::<Result<_, _>>().unwrap();
+  assert_eq!(answers.len(), 2);
+  assert_eq!(answers[1].name, "www.example.com".parse::<Name>().unwrap());
+  assert_eq!(answers[1].data, [192, 0, 2, 1]);
+  assert_eq!(answers[0].rdata().unwrap(), RData::CNAME("www.example.com".parse().unwrap()));
+  assert_eq!(view.authorities().count(), 1);
+  assert_eq!(view.additionals().next().unwrap().unwrap().rtype, RecordType::OPT);
+  assert_eq!(view.edns().unwrap(), Some(Edns::default()));
+  assert!(view.validate().is_ok());
+  // Each section starts where the one before it ends.
+  let [answers, authorities, additionals] = view.sections.map(Option::unwrap);
+  assert_eq!(answers, HEADER_LENGTH + 17);
+  assert_eq!(view.records().nth(2).unwrap().unwrap().name.offset(), authorities);
+  assert_eq!(view.records().nth(3).unwrap().unwrap().name.offset(), additionals);
+}
+
+#[test]
+fn test_message_ref_errors() {
+  assert!(matches!(MessageRef::parse(b"\x12\x34\x01\0"), Err(DnsError::MalformedHeader)));
+  let view = MessageRef::parse(b"\x12\x34\x01\0\0\x01\0\x01\0\0\0\0\x83abc\0\0\x01\0\x01").unwrap();
+  let mut questions = view.questions();
+  assert!(matches!(questions.next(), Some(Err(DnsError::BadLabel))));
+  assert!(questions.next().is_none());
+  assert!(matches!(view.answers().next(), Some(Err(DnsError::BadLabel))));
+  assert!(matches!(view.additionals().next(), Some(Err(DnsError::BadLabel))));
+  let view = MessageRef::parse(b"\x12\x34\x01\0\0\0\0\x01\0\x01\0\0\x01a\0\0\x01\0\x01\0\0\0\0\0\x05\x01\x02").unwrap();
+  assert!(matches!(view.answers().next(), Some(Err(DnsError::TruncatedRdata))));
+  // The authority section cannot be found past the broken answer.
+  assert!(matches!(view.authorities().next(), Some(Err(DnsError::TruncatedRdata))));
+  let view = MessageRef::parse(b"\x12\x34\x01\0\0\x01\0\0\0\0\0\0\x03abc\0\0\x01\0\x01junk").unwrap();
+  assert!(matches!(view.validate(), Err(DnsError::TrailingData(4))));
+}
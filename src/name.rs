@@ -0,0 +1,295 @@
+#![allow(dead_code)]
+use std::{
+  cmp::Ordering,
+  fmt,
+  hash::{Hash, Hasher},
+  str::FromStr,
+};
+
+use crate::{
+  error::DnsError,
+  presentation::{split_unescaped, unescape},
+};
+
+/// Longest a single label may be (RFC 1035 section 2.3.4).
+pub const MAX_LABEL_LENGTH: usize = 63;
+/// Longest a name may be on the wire, root label included (RFC 1035 section 2.3.4).
+pub const MAX_NAME_LENGTH: usize = 255;
+
+/// A fully qualified domain name. Names compare and hash ignoring ASCII case, and
+/// order canonically (RFC 4034 section 6.1), but keep the case they were given.
+#[derive(Debug, Clone, Default)]
+pub struct Name {
+  labels: Vec<Vec<u8>>,
+}
+
+impl Name {
+  pub fn root() -> Self {
+    Self { labels: Vec::new() }
+  }
+  pub fn from_labels<L: AsRef<[u8]>>(labels: impl IntoIterator<Item = L>) -> Result<Self, DnsError> {
+    let mut name = Self::root();
+    for label in labels {
+      name.push_label(label.as_ref())?;
+    }
+    Ok(name)
+  }
+  pub fn labels(&self) -> &[Vec<u8>] {
+    &self.labels
+  }
+  pub fn is_root(&self) -> bool {
+    self.labels.is_empty()
+  }
+  /// Appends `label` below the root, keeping the name within the RFC 1035 limits.
+  pub fn push_label(&mut self, label: &[u8]) -> Result<(), DnsError> {
+    if label.is_empty() {
+      return Err(DnsError::EmptyLabel);
+    }
+    if label.len() > MAX_LABEL_LENGTH {
+      return Err(DnsError::LabelTooLong(label.len()));
+    }
+    if self.wire_len() + label.len() + 1 > MAX_NAME_LENGTH {
+      return Err(DnsError::NameTooLong);
+    }
+    self.labels.push(label.to_vec());
+    Ok(())
+  }
+  /// Length of the name in uncompressed wire format, including the root label.
+  pub fn wire_len(&self) -> usize {
+    self.labels.iter().map(|label| label.len() + 1).sum::<usize>() + 1
+  }
+  /// The uncompressed wire format.
+  pub fn to_wire(&self) -> Vec<u8> {
+    let mut wire = Vec::with_capacity(self.wire_len());
+    for label in &self.labels {
+      wire.push(label.len() as u8);
+      wire.extend_from_slice(label);
+    }
+    wire.push(0);
+    wire
+  }
+  /// The canonical form of RFC 4034 section 6.2, with every ASCII letter in lowercase.
+  pub fn to_lowercase(&self) -> Self {
+    Self {
+      labels: self.labels.iter().map(|label| label.to_ascii_lowercase()).collect(),
+    }
+  }
+  /// The name one label up, or `None` for the root.
+  pub fn parent(&self) -> Option<Self> {
+    (!self.is_root()).then(|| Self { labels: self.labels[1..].to_vec() })
+  }
+  /// Whether `self` is `other` or below it.
+  pub fn is_subdomain_of(&self, other: &Name) -> bool {
+    self.labels.len() >= other.labels.len()
+      && self.labels.iter().rev().zip(other.labels.iter().rev()).all(|(a, b)| a.eq_ignore_ascii_case(b))
+  }
+}
+
+impl PartialEq for Name {
+  fn eq(&self, other: &Self) -> bool {
+    self.labels.len() == other.labels.len() && self.labels.iter().zip(&other.labels).all(|(a, b)| a.eq_ignore_ascii_case(b))
+  }
+}
+
+impl Eq for Name {}
+
+impl Hash for Name {
+  fn hash<H: Hasher>(&self, state: &mut H) {
+    for label in &self.labels {
+      state.write_u8(label.len() as u8);
+      for byte in label {
+        state.write_u8(byte.to_ascii_lowercase());
+      }
+    }
+    state.write_u8(0);
+  }
+}
+
+/// Canonical DNS name order: label by label from the root, each label compared as a
+/// lowercased octet string, and a name sorting before the names below it.
+impl Ord for Name {
+  fn cmp(&self, other: &Self) -> Ordering {
+    for (a, b) in self.labels.iter().rev().zip(other.labels.iter().rev()) {
+      match a.iter().map(u8::to_ascii_lowercase).cmp(b.iter().map(u8::to_ascii_lowercase)) {
+        Ordering::Equal => continue,
+        ordering => return ordering,
+      }
+    }
+    self.labels.len().cmp(&other.labels.len())
+  }
+}
+
+impl PartialOrd for Name {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+/// Fully qualified, with the trailing dot; the root name is just `.`. Dots and other
+/// characters special in master files are escaped as `\.`, and anything outside
+/// printable ASCII as `\DDD`, so every label survives a round trip through text.
+/// The alternate form `{:#}` shows valid A-labels as the Unicode U-labels they encode.
+impl fmt::Display for Name {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if self.is_root() {
+      return f.write_str(".");
+    }
+    for label in &self.labels {
+      match u_label(label) {
+        Some(unicode) if f.alternate() => f.write_str(&unicode)?,
+        _ => {
+          for &byte in label {
+            match byte {
+              b'.' | b'\\' | b'"' | b';' | b'(' | b')' | b'@' | b'$' => write!(f, "\\{}", byte as char)?,
+              0x21..=0x7e => write!(f, "{}", byte as char)?,
+              _ => write!(f, "\\{byte:03}")?,
+            }
+          }
+        }
+      }
+      f.write_str(".")?;
+    }
+    Ok(())
+  }
+}
+
+/// The Unicode form of `label` if it is a valid A-label (RFC 5890).
+fn u_label(label: &[u8]) -> Option<String> {
+  if !label.get(..4)?.eq_ignore_ascii_case(b"xn--") {
+    return None;
+  }
+  let (unicode, result) = idna::domain_to_unicode(std::str::from_utf8(label).ok()?);
+  result.ok().map(|_| unicode)
+}
+
+/// Converts a label typed in Unicode to the A-labels that go on the wire. The
+/// IDNA mapping may split it, when it holds a full stop such as `。`. Labels
+/// typed in ASCII are taken byte for byte, even where `\DDD` escapes spell UTF-8.
+fn a_labels(text: &str) -> Result<Vec<Vec<u8>>, DnsError> {
+  let label = unescape(text)?;
+  match std::str::from_utf8(&label) {
+    Ok(unicode) if !text.is_ascii() => {
+      let ascii = idna::domain_to_ascii(unicode).map_err(|_| DnsError::Idna(unicode.to_string()))?;
+      Ok(ascii.split('.').map(|label| label.as_bytes().to_vec()).collect())
+    }
+    _ => Ok(vec![label]),
+  }
+}
+
+/// Every name is taken as fully qualified, with or without the trailing dot.
+/// Labels may hold `\X` and `\DDD` escapes (RFC 1035 section 5.1), and labels in
+/// Unicode are converted to A-labels.
+impl FromStr for Name {
+  type Err = DnsError;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    if s == "." {
+      return Ok(Self::root());
+    }
+    let mut labels = split_unescaped(s, '.');
+    if labels.len() > 1 && labels.last() == Some(&"") {
+      labels.pop();
+    }
+    let mut name = Self::root();
+    for label in labels {
+      for label in a_labels(label)? {
+        name.push_label(&label)?;
+      }
+    }
+    Ok(name)
+  }
+}
+
+#[test]
+fn test_name_text_round_trip() {
+  let name: Name = "www.example.com".parse().unwrap();
+  assert_eq!(name, Name::from_labels(["www", "example", "com"]).unwrap());
+  assert_eq!(name.to_string(), "www.example.com.");
+  assert_eq!("www.example.com.".parse::<Name>().unwrap(), name);
+  assert!(".".parse::<Name>().unwrap().is_root());
+  assert_eq!(Name::root().to_string(), ".");
+  assert!("www..example.com".parse::<Name>().is_err());
+  assert!("".parse::<Name>().is_err());
+}
+
+#[test]
+fn test_name_limits() {
+  let label = "a".repeat(MAX_LABEL_LENGTH);
+  assert!(label.parse::<Name>().is_ok());
+  assert!(matches!(format!("{label}a.com").parse::<Name>(), Err(DnsError::LabelTooLong(64))));
+  let name = [label.as_str(); 4].join(".");
+  assert!(matches!(name.parse::<Name>(), Err(DnsError::NameTooLong)));
+  let name = [label.as_str(), label.as_str(), label.as_str(), &"a".repeat(61)].join(".");
+  assert_eq!(name.parse::<Name>().unwrap().wire_len(), MAX_NAME_LENGTH);
+  assert!(matches!(Name::from_labels(["a", ""]), Err(DnsError::EmptyLabel)));
+  assert_eq!(Name::root().to_wire(), b"\0");
+  assert_eq!("Example.com.".parse::<Name>().unwrap().to_wire(), b"\x07Example\x03com\0");
+}
+
+#[test]
+fn test_name_case_insensitive() {
+  use std::collections::HashSet;
+  let lower: Name = "www.example.com".parse().unwrap();
+  let mixed: Name = "WWW.Example.COM".parse().unwrap();
+  assert_eq!(lower, mixed);
+  assert_eq!(HashSet::from([lower.clone()]).get(&mixed), Some(&lower));
+  assert_eq!(mixed.to_string(), "WWW.Example.COM.");
+  assert_eq!(mixed.to_lowercase().to_string(), "www.example.com.");
+  assert!(mixed.is_subdomain_of(&"EXAMPLE.com".parse().unwrap()));
+  assert!(mixed.is_subdomain_of(&Name::root()));
+  assert_eq!(mixed.parent(), Some("example.com".parse().unwrap()));
+  assert_eq!(Name::root().parent(), None);
+  assert!(!mixed.is_subdomain_of(&"ample.com".parse().unwrap()));
+}
+
+#[test]
+fn test_name_canonical_order() {
+  // The example from RFC 4034 section 6.1, where \001 and \200 are single octets.
+  let names: [&[&[u8]]; 9] = [
+    &[b"example"],
+    &[b"a", b"example"],
+    &[b"yljkjljk", b"a", b"example"],
+    &[b"Z", b"a", b"example"],
+    &[b"zABC", b"a", b"EXAMPLE"],
+    &[b"z", b"example"],
+    &[b"\x01", b"z", b"example"],
+    &[b"*", b"z", b"example"],
+    &[b"\x80", b"z", b"example"],
+  ];
+  let expected: Vec<Name> = names.iter().map(|labels| Name::from_labels(labels.iter()).unwrap()).collect();
+  let mut sorted = expected.clone();
+  sorted.reverse();
+  sorted.sort();
+  assert_eq!(sorted.iter().map(Name::labels).collect::<Vec<_>>(), expected.iter().map(Name::labels).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_name_escapes() {
+  let name: Name = r"My\ Printer\.\032v2._ipp._tcp.local.".parse().unwrap();
+  assert_eq!(name.labels()[0], b"My Printer. v2");
+  assert_eq!(name.labels().len(), 4);
+  assert_eq!(name.to_string(), r"My\032Printer\.\032v2._ipp._tcp.local.");
+  assert_eq!(name.to_string().parse::<Name>().unwrap(), name);
+  let name = Name::from_labels([&b"a\\b"[..], b"\0\xff\"();@$"]).unwrap();
+  assert_eq!(name.to_string(), r#"a\\b.\000\255\"\(\)\;\@\$."#);
+  assert_eq!(name.to_string().parse::<Name>().unwrap().labels(), name.labels());
+  assert_eq!(r"a\\.".parse::<Name>().unwrap().labels(), [b"a\\"]);
+  assert!(r"a\256.com".parse::<Name>().is_err());
+  assert!(r"a\".parse::<Name>().is_err());
+}
+
+#[test]
+fn test_name_idna() {
+  let name: Name = "Bücher.example".parse().unwrap();
+  assert_eq!(name, Name::from_labels(["xn--bcher-kva", "example"]).unwrap());
+  assert_eq!(name.to_string(), "xn--bcher-kva.example.");
+  assert_eq!(format!("{name:#}"), "bücher.example.");
+  assert_eq!(format!("{name:#}").parse::<Name>().unwrap(), name);
+  let name: Name = "例子。测试.".parse().unwrap();
+  assert_eq!(name.labels().len(), 2);
+  assert_eq!(format!("{name:#}"), "例子.测试.");
+  // Not valid punycode, so shown as it is.
+  let name: Name = "xn--a.xn--zz-.example".parse().unwrap();
+  assert_eq!(format!("{name:#}"), name.to_string());
+  assert!(matches!("bad\u{fffd}.example".parse::<Name>(), Err(DnsError::Idna(_))));
+  assert_eq!(r"\194\128.example".parse::<Name>().unwrap().labels()[0], b"\xc2\x80");
+}
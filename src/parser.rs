@@ -1,17 +1,25 @@
 #![allow(dead_code)]
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use bytes::{BufMut, BytesMut};
 use nom::{
   bits,
   branch::alt,
   bytes::complete::{tag, take},
   character::complete::char as nom_char,
-  combinator::{map, value},
+  combinator::{all_consuming, map, rest, value},
   error::Error,
-  multi::{count, length_data, many_till},
-  number::complete::{be_u16, be_u8},
-  sequence::{preceded, terminated},
+  multi::{count, length_data, many0, many1, many_till},
+  number::complete::{be_u128, be_u16, be_u32, be_u8},
+  sequence::{preceded, terminated, tuple},
   IResult, Offset,
 };
+use crate::{
+  edns::{ClientSubnet, EdnsOption, CLIENT_SUBNET, COOKIE, EXTENDED_ERROR},
+  error::DnsError,
+  name::{Name, MAX_NAME_LENGTH},
+  record::{Question, RData, RecordType, ResourceRecord},
+  svcb::{self, SvcParam},
+};
 
 fn parse_question(i: &[u8]) -> IResult<&[u8], &[u8]> {
   let (r, _) = many_till(length_data(be_u8), nom_char('\0'))(i)?;
@@ -30,7 +38,7 @@ pub enum DomainPart<'a> {
   Terminator,
 }
 
-pub fn parse_pointer(i: &[u8]) -> IResult<&[u8], DomainPart> {
+pub fn parse_pointer(i: &[u8]) -> IResult<&[u8], DomainPart<'_>> {
   map(
     bits::bits::<_, _, Error<_>, _, _>(preceded(
       bits::complete::tag(0b11, 2usize),
@@ -40,11 +48,11 @@ pub fn parse_pointer(i: &[u8]) -> IResult<&[u8], DomainPart> {
   )(i)
 }
 
-pub fn parse_terminator(i: &[u8]) -> IResult<&[u8], DomainPart> {
+pub fn parse_terminator(i: &[u8]) -> IResult<&[u8], DomainPart<'_>> {
   value(DomainPart::Terminator, tag(&[0u8]))(i)
 }
 
-pub fn parse_label(i: &[u8]) -> IResult<&[u8], DomainPart> {
+pub fn parse_label(i: &[u8]) -> IResult<&[u8], DomainPart<'_>> {
   map(
     length_data(bits::bits::<_, usize, Error<_>, _, _>(preceded(
       bits::complete::tag(0b00, 2usize),
@@ -67,32 +75,20 @@ pub fn parse_domains(i: &[u8], cnt: usize) -> IResult<&[u8], Vec<&[u8]>> {
   count(parse_domain, cnt)(i)
 }
 
-pub fn expand_question(i: &[u8], offset: usize) -> IResult<&[u8], BytesMut> {
-  let (r, (labels, ptr_or_ter)) = many_till(parse_label, alt((parse_terminator, parse_pointer)))(&i[offset..])?;
+pub fn expand_question(i: &[u8], offset: usize) -> IResult<&[u8], BytesMut, DnsError> {
+  let (r, name) = decode_name(i, offset)?;
   let mut res = BytesMut::new();
-  for label in labels {
-    let DomainPart::Label(label) = label else {
-      continue;
-    };
+  for label in name.labels() {
     res.put_u8(label.len() as u8);
-    res.put(label);
-  }
-  match ptr_or_ter {
-    DomainPart::Pointer(ptr) => {
-      let (_, mut question) = expand_question(i, ptr)?;
-      res.put(question.split_to(question.len() - 4));
-    }
-    DomainPart::Terminator => res.put_u8(0),
-    DomainPart::Label(label) => {
-      panic!("ptr_or_ter cannot be a label: {label:?}")
-    }
+    res.put(&label[..]);
   }
+  res.put_u8(0);
   let (r, record_type_class) = take(4usize)(r)?;
   res.put(record_type_class);
   Ok((r, res))
 }
 
-pub fn expand_answer(i: &[u8], offset: usize) -> IResult<&[u8], BytesMut> {
+pub fn expand_answer(i: &[u8], offset: usize) -> IResult<&[u8], BytesMut, DnsError> {
   let (r, mut question) = expand_question(i, offset)?;
   let (r, ttl) = take(4usize)(r)?;
   question.put(ttl);
@@ -103,6 +99,359 @@ pub fn expand_answer(i: &[u8], offset: usize) -> IResult<&[u8], BytesMut> {
   Ok((r, question))
 }
 
+/// More compression pointers than any sensible encoder would chain for one name.
+pub const MAX_POINTER_HOPS: usize = 64;
+
+/// The labels of the possibly compressed name at some offset of a message, borrowed
+/// from it. Every pointer has to point before the run of labels it ends, so the walk
+/// always terminates. The first error ends the iteration.
+#[derive(Debug, Clone)]
+pub struct Labels<'a> {
+  msg: &'a [u8],
+  position: usize,
+  run_start: usize,
+  hops: usize,
+  length: usize,
+  /// Where the name ends in place when it is compressed: after its first pointer.
+  pointer_end: Option<usize>,
+  end: Option<usize>,
+  done: bool,
+}
+
+impl<'a> Labels<'a> {
+  pub fn new(msg: &'a [u8], offset: usize) -> Self {
+    Self { msg, position: offset, run_start: offset, hops: 0, length: 1, pointer_end: None, end: None, done: false }
+  }
+
+  /// Walks the whole name, returning the offset of whatever follows it.
+  pub fn skip(mut self) -> Result<usize, DnsError> {
+    for label in &mut self {
+      label?;
+    }
+    self.end.ok_or(DnsError::Truncated)
+  }
+
+  /// Offset of whatever follows the name, once every label has been read.
+  pub fn end(&self) -> Option<usize> {
+    self.end
+  }
+
+  fn step(&mut self) -> Result<Option<&'a [u8]>, DnsError> {
+    loop {
+      let i = self.msg.get(self.position..).filter(|i| !i.is_empty()).ok_or(DnsError::Truncated)?;
+      let (r, part) = alt((parse_terminator, parse_pointer, parse_label))(i).map_err(|e| match e {
+        nom::Err::Incomplete(_) => DnsError::Truncated,
+        _ => DnsError::BadLabel,
+      })?;
+      let next = self.msg.offset(r);
+      match part {
+        DomainPart::Label(label) => {
+          self.length += label.len() + 1;
+          if self.length > MAX_NAME_LENGTH {
+            return Err(DnsError::NameTooLong);
+          }
+          self.position = next;
+          return Ok(Some(label));
+        }
+        DomainPart::Pointer(target) => {
+          if target >= self.run_start {
+            return Err(DnsError::ForwardPointer { at: self.position, target });
+          }
+          self.hops += 1;
+          if self.hops > MAX_POINTER_HOPS {
+            return Err(DnsError::TooManyPointers);
+          }
+          self.pointer_end.get_or_insert(next);
+          self.run_start = target;
+          self.position = target;
+        }
+        DomainPart::Terminator => {
+          self.end = Some(self.pointer_end.unwrap_or(next));
+          return Ok(None);
+        }
+      }
+    }
+  }
+}
+
+impl<'a> Iterator for Labels<'a> {
+  type Item = Result<&'a [u8], DnsError>;
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.done {
+      return None;
+    }
+    let step = self.step();
+    self.done = !matches!(step, Ok(Some(_)));
+    step.transpose()
+  }
+}
+
+/// Reads the possibly compressed name at `offset` of `msg`, returning the input following it.
+pub fn decode_name(msg: &[u8], offset: usize) -> IResult<&[u8], Name, DnsError> {
+  let mut name = Name::root();
+  let mut labels = Labels::new(msg, offset);
+  for label in &mut labels {
+    name.push_label(label.map_err(nom::Err::Error)?).map_err(nom::Err::Error)?;
+  }
+  let end = labels.end().unwrap_or(msg.len());
+  Ok((&msg[end..], name))
+}
+
+pub fn decode_question(msg: &[u8], offset: usize) -> IResult<&[u8], Question, DnsError> {
+  let (r, name) = decode_name(msg, offset)?;
+  let (r, (qtype, qclass)) = tuple((be_u16, be_u16))(r)?;
+  Ok((r, Question::new(name, qtype.into(), qclass.into())))
+}
+
+pub fn decode_questions(msg: &[u8], offset: usize, cnt: usize) -> IResult<&[u8], Vec<Question>, DnsError> {
+  let mut r = msg.get(offset..).ok_or(nom::Err::Error(DnsError::Truncated))?;
+  let mut questions = Vec::with_capacity(cnt);
+  for _ in 0..cnt {
+    let (rest, question) = decode_question(msg, msg.offset(r))?;
+    questions.push(question);
+    r = rest;
+  }
+  Ok((r, questions))
+}
+
+pub fn decode_record(msg: &[u8], offset: usize) -> IResult<&[u8], ResourceRecord, DnsError> {
+  let (r, name) = decode_name(msg, offset)?;
+  let (r, (rtype, class, ttl)) = tuple((be_u16, be_u16, be_u32))(r)?;
+  let (r, length) = be_u16(r)?;
+  let (r, data) = take(length)(r).map_err(|e: nom::Err<DnsError>| e.map(|_| DnsError::TruncatedRdata))?;
+  let rdata = decode_wire_rdata(msg, data, rtype.into())?;
+  Ok((r, ResourceRecord::new(name, class.into(), ttl, rdata)))
+}
+
+pub fn decode_records(msg: &[u8], offset: usize, cnt: usize) -> IResult<&[u8], Vec<ResourceRecord>, DnsError> {
+  let mut r = msg.get(offset..).ok_or(nom::Err::Error(DnsError::Truncated))?;
+  let mut records = Vec::with_capacity(cnt);
+  for _ in 0..cnt {
+    let (rest, record) = decode_record(msg, msg.offset(r))?;
+    records.push(record);
+    r = rest;
+  }
+  Ok((r, records))
+}
+
+pub fn decode_edns_option(i: &[u8]) -> IResult<&[u8], EdnsOption, DnsError> {
+  let (r, code) = be_u16(i)?;
+  let (r, length) = be_u16(r)?;
+  let (r, data) = take(length)(r)?;
+  let option = match code {
+    CLIENT_SUBNET => EdnsOption::ClientSubnet(decode_client_subnet(data).ok_or(nom::Err::Failure(DnsError::BadEdnsOption(code)))?),
+    COOKIE => match data.len() {
+      8 | 16..=40 => EdnsOption::Cookie { client: data[..8].try_into().unwrap(), server: data[8..].to_vec() },
+      // A failure, so that `many0` does not take it for the end of the options.
+      _ => return Err(nom::Err::Failure(DnsError::BadEdnsOption(code))),
+    },
+    // The text should be UTF-8, but is only ever shown. An extended error is only
+    // informational, so one too short to decode is kept as it is rather than
+    // failing the whole message.
+    EXTENDED_ERROR if data.len() >= 2 => EdnsOption::ExtendedError {
+      info_code: u16::from_be_bytes([data[0], data[1]]),
+      extra_text: String::from_utf8_lossy(&data[2..]).into_owned(),
+    },
+    _ => EdnsOption::Unknown { code, data: data.to_vec() },
+  };
+  Ok((r, option))
+}
+
+/// RFC 7871 section 6 has the address hold just the bytes the source prefix
+/// needs, with no bits set past it.
+fn decode_client_subnet(data: &[u8]) -> Option<ClientSubnet> {
+  let [family_high, family_low, source_prefix, scope_prefix]: [u8; 4] = data.get(..4)?.try_into().ok()?;
+  let address = &data[4..];
+  let mut octets = [0u8; 16];
+  let max_prefix = match u16::from_be_bytes([family_high, family_low]) {
+    1 => 32,
+    2 => 128,
+    _ => return None,
+  };
+  if source_prefix > max_prefix || scope_prefix > max_prefix || address.len() != (usize::from(source_prefix) + 7) / 8 {
+    return None;
+  }
+  octets[..address.len()].copy_from_slice(address);
+  let ip = match max_prefix {
+    32 => IpAddr::from([octets[0], octets[1], octets[2], octets[3]]),
+    _ => IpAddr::from(octets),
+  };
+  let subnet = ClientSubnet::new(ip, source_prefix);
+  (subnet.address == ip).then_some(ClientSubnet { scope_prefix, ..subnet })
+}
+
+/// A SvcParam: its key, then its value behind a 16 bit length.
+pub fn decode_svc_param(i: &[u8]) -> IResult<&[u8], SvcParam, DnsError> {
+  let (r, key) = be_u16(i)?;
+  let (r, value) = length_data(be_u16)(r)?;
+  let (_, param) = all_consuming(|v| match key {
+    svcb::MANDATORY => map(many0(be_u16), SvcParam::Mandatory)(v),
+    svcb::ALPN => map(many0(character_string), |ids| SvcParam::Alpn(ids.into_iter().map(<[u8]>::to_vec).collect()))(v),
+    svcb::NO_DEFAULT_ALPN => Ok((v, SvcParam::NoDefaultAlpn)),
+    svcb::PORT => map(be_u16, SvcParam::Port)(v),
+    svcb::IPV4HINT => map(many0(be_u32), |ips| SvcParam::Ipv4Hint(ips.into_iter().map(Ipv4Addr::from).collect()))(v),
+    svcb::ECH => Ok((&v[v.len()..], SvcParam::Ech(v.to_vec()))),
+    svcb::IPV6HINT => map(many0(be_u128), |ips| SvcParam::Ipv6Hint(ips.into_iter().map(Ipv6Addr::from).collect()))(v),
+    key => Ok((&v[v.len()..], SvcParam::Unknown { key, value: v.to_vec() })),
+  })(value)?;
+  Ok((r, param))
+}
+
+/// An NSEC or NSEC3 type bitmap: windows in ascending order, each 1 to 32 bytes
+/// long and ending in a byte with a bit set.
+fn type_bitmap(i: &[u8]) -> IResult<&[u8], Vec<RecordType>, DnsError> {
+  let (r, windows) = many0(tuple((be_u8, length_data(be_u8))))(i)?;
+  let mut types = Vec::new();
+  let mut previous = None;
+  for (window, bits) in windows {
+    if previous.is_some_and(|previous| previous >= window) || bits.is_empty() || bits.len() > 32 || bits[bits.len() - 1] == 0 {
+      return Err(nom::Err::Error(DnsError::Malformed(nom::error::ErrorKind::Verify)));
+    }
+    previous = Some(window);
+    for (i, byte) in bits.iter().enumerate() {
+      for bit in (0..8).filter(|bit| byte & (0x80 >> bit) != 0) {
+        types.push(RecordType::from((window as u16) << 8 | (i * 8 + bit) as u16));
+      }
+    }
+  }
+  Ok((r, types))
+}
+
+/// A `<character-string>`: a length byte followed by that many bytes.
+fn character_string(i: &[u8]) -> IResult<&[u8], &[u8], DnsError> {
+  let (r, length) = be_u8(i)?;
+  take(length)(r)
+}
+
+/// Parses a name found in `data`, a slice of `msg`. The name may point anywhere
+/// earlier in `msg`, but its own labels must end within `data`.
+fn rdata_name<'a>(msg: &'a [u8], data: &'a [u8]) -> impl Fn(&'a [u8]) -> IResult<&'a [u8], Name, DnsError> {
+  move |i| {
+    let (r, name) = decode_name(msg, msg.offset(i))?;
+    let rest = data.get(msg.offset(r) - msg.offset(data)..).ok_or(nom::Err::Error(DnsError::Truncated))?;
+    Ok((rest, name))
+  }
+}
+
+/// Decodes `data`, a slice of `msg`, as the rdata of a record of type `rtype`.
+pub fn decode_rdata<'a>(msg: &'a [u8], data: &'a [u8], rtype: RecordType) -> Result<RData, nom::Err<DnsError>> {
+  let name = rdata_name(msg, data);
+  let (r, rdata) = match rtype {
+    RecordType::A => map(be_u32, |ip| RData::A(Ipv4Addr::from(ip)))(data),
+    RecordType::AAAA => map(be_u128, |ip| RData::AAAA(Ipv6Addr::from(ip)))(data),
+    RecordType::NS => map(&name, RData::NS)(data),
+    RecordType::CNAME => map(&name, RData::CNAME)(data),
+    RecordType::PTR => map(&name, RData::PTR)(data),
+    RecordType::MX => map(tuple((be_u16, &name)), |(preference, exchange)| RData::MX { preference, exchange })(data),
+    RecordType::TXT => map(many1(character_string), |strings| RData::TXT(strings.into_iter().map(<[u8]>::to_vec).collect()))(data),
+    RecordType::SOA => map(
+      tuple((&name, &name, be_u32, be_u32, be_u32, be_u32, be_u32)),
+      |(mname, rname, serial, refresh, retry, expire, minimum)| RData::SOA { mname, rname, serial, refresh, retry, expire, minimum },
+    )(data),
+    RecordType::SRV => map(tuple((be_u16, be_u16, be_u16, &name)), |(priority, weight, port, target)| RData::SRV { priority, weight, port, target })(data),
+    RecordType::CAA => map(tuple((be_u8, character_string, rest)), |(flags, tag, value): (u8, &[u8], &[u8])| RData::CAA {
+      flags,
+      tag: tag.to_vec(),
+      value: value.to_vec(),
+    })(data),
+    RecordType::OPT => map(many0(decode_edns_option), RData::OPT)(data),
+    RecordType::DS => map(tuple((be_u16, be_u8, be_u8, rest)), |(key_tag, algorithm, digest_type, digest): (_, _, _, &[u8])| RData::DS {
+      key_tag,
+      algorithm,
+      digest_type,
+      digest: digest.to_vec(),
+    })(data),
+    RecordType::RRSIG => map(
+      tuple((be_u16, be_u8, be_u8, be_u32, be_u32, be_u32, be_u16, &name, rest)),
+      |(type_covered, algorithm, labels, original_ttl, expiration, inception, key_tag, signer_name, signature): (u16, _, _, _, _, _, _, _, &[u8])| RData::RRSIG {
+        type_covered: type_covered.into(),
+        algorithm,
+        labels,
+        original_ttl,
+        expiration,
+        inception,
+        key_tag,
+        signer_name,
+        signature: signature.to_vec(),
+      },
+    )(data),
+    RecordType::NSEC => map(tuple((&name, type_bitmap)), |(next_domain, types)| RData::NSEC { next_domain, types })(data),
+    RecordType::DNSKEY => map(tuple((be_u16, be_u8, be_u8, rest)), |(flags, protocol, algorithm, public_key): (_, _, _, &[u8])| RData::DNSKEY {
+      flags,
+      protocol,
+      algorithm,
+      public_key: public_key.to_vec(),
+    })(data),
+    RecordType::NSEC3 => map(
+      tuple((be_u8, be_u8, be_u16, length_data(be_u8), length_data(be_u8), type_bitmap)),
+      |(hash_algorithm, flags, iterations, salt, next_hashed_owner, types): (_, _, _, &[u8], &[u8], _)| RData::NSEC3 {
+        hash_algorithm,
+        flags,
+        iterations,
+        salt: salt.to_vec(),
+        next_hashed_owner: next_hashed_owner.to_vec(),
+        types,
+      },
+    )(data),
+    RecordType::NSEC3PARAM => map(tuple((be_u8, be_u8, be_u16, length_data(be_u8))), |(hash_algorithm, flags, iterations, salt): (_, _, _, &[u8])| {
+      RData::NSEC3PARAM { hash_algorithm, flags, iterations, salt: salt.to_vec() }
+    })(data),
+    RecordType::SVCB | RecordType::HTTPS => map(tuple((be_u16, &name, many0(decode_svc_param))), |(priority, target, params)| match rtype {
+      RecordType::SVCB => RData::SVCB { priority, target, params },
+      _ => RData::HTTPS { priority, target, params },
+    })(data),
+    RecordType::TSIG => map(
+      tuple((&name, be_u16, be_u32, be_u16, length_data(be_u16), be_u16, be_u16, length_data(be_u16))),
+      |(algorithm, time_high, time_low, fudge, mac, original_id, error, other): (_, u16, u32, _, &[u8], _, _, &[u8])| RData::TSIG {
+        algorithm,
+        time_signed: (time_high as u64) << 32 | time_low as u64,
+        fudge,
+        mac: mac.to_vec(),
+        original_id,
+        error,
+        other: other.to_vec(),
+      },
+    )(data),
+    rtype => Ok((&data[data.len()..], RData::Unknown { rtype, data: data.to_vec() })),
+  }
+  .map_err(|e| match e {
+    // `length_data` asks for more input when a length runs past the rdata.
+    nom::Err::Incomplete(_) => nom::Err::Error(DnsError::BadRdata(rtype)),
+    e => e.map(|e| match e {
+      DnsError::Truncated | DnsError::Malformed(_) => DnsError::BadRdata(rtype),
+      e => e,
+    }),
+  })?;
+  if !r.is_empty() {
+    return Err(nom::Err::Error(DnsError::BadRdata(rtype)));
+  }
+  Ok(rdata)
+}
+
+/// Decodes the rdata of a record read off the wire like `decode_rdata`, but keeps
+/// rdata that does not decode as `RData::Unknown`, so one bad record does not cost
+/// the rest of the message. Only types without names qualify: a name may point
+/// into the message around it, and mean something else copied anywhere else.
+pub fn decode_wire_rdata<'a>(msg: &'a [u8], data: &'a [u8], rtype: RecordType) -> Result<RData, nom::Err<DnsError>> {
+  match decode_rdata(msg, data, rtype) {
+    Err(_) if matches!(
+      rtype,
+      RecordType::A
+        | RecordType::AAAA
+        | RecordType::TXT
+        | RecordType::CAA
+        | RecordType::DS
+        | RecordType::DNSKEY
+        | RecordType::NSEC3
+        | RecordType::NSEC3PARAM
+    ) =>
+    {
+      Ok(RData::Unknown { rtype, data: data.to_vec() })
+    }
+    rdata => rdata,
+  }
+}
+
 #[test]
 fn test_expand_question() {
   let i = b"\xd7R\x01\0\0\x01\0\0\0\0\0\0\x0ccodecrafters\x02io\0\0\x01\0\x01";
@@ -208,7 +557,7 @@ fn test_bits() {
   assert_eq!(offset, 2);
   assert_eq!(value, 0b11);
   let e = bits::complete::tag::<_, u8, _, Error<_>>(0b10, 2usize)((&i[..], 0)).unwrap_err();
-  eprintln!("{e:?}");
+  assert!(matches!(e, nom::Err::Error(Error { input: (_, 0), code: nom::error::ErrorKind::TagBits })), "{e:?}");
   let ((r, offset), value) = bits::streaming::take::<_, u8, _, Error<_>>(2usize)((&i[..], 0)).unwrap();
   assert_eq!(r, i);
   assert_eq!(offset, 2);
@@ -217,4 +566,234 @@ fn test_bits() {
   assert_eq!(r, b"");
   assert_eq!(flag, 0b11);
   assert_eq!(value, 0b10_0101_0110_1000);
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_decode_question() {
+  let i = b"\xfc=\x01\0\0\x02\0\0\0\0\0\0\x03abc\x11longassdomainname\x03com\0\0\x01\0\x01\x03def\xc0\x10\0\x1c\0\x01";
+  let (r, questions) = decode_questions(i, 12, 2).unwrap();
+  assert_eq!(r, b"");
+  assert_eq!(questions[0].name, Name::from_labels(["abc", "longassdomainname", "com"]).unwrap());
+  assert_eq!(questions[0].qtype, RecordType::A);
+  assert_eq!(questions[1].name, Name::from_labels(["def", "longassdomainname", "com"]).unwrap());
+  assert_eq!(questions[1].qtype, RecordType::AAAA);
+  assert_eq!(questions[1].qclass, crate::record::DnsClass::IN);
+}
+
+#[test]
+fn test_decode_record() {
+  let i = b"\0\x01\x81\x80\0\x01\0\x02\0\0\0\0\x03www\x07example\x03com\0\0\x05\0\x01\xc0\x0c\0\x05\0\x01\0\0\x0e\x10\0\x02\xc0\x10\xc0\x10\0\x01\0\x01\0\0\0\x3c\0\x04\x5d\xb8\xd8\x22";
+  let (r, _) = decode_questions(i, 12, 1).unwrap();
+  let (r, answers) = decode_records(i, i.offset(r), 2).unwrap();
+  assert_eq!(r, b"");
+  let example = Name::from_labels(["example", "com"]).unwrap();
+  assert_eq!(answers[0].name, Name::from_labels(["www", "example", "com"]).unwrap());
+  assert_eq!(answers[0].ttl, 3600);
+  assert_eq!(answers[0].rdata, RData::CNAME(example.clone()));
+  assert_eq!(answers[1].name, example);
+  assert_eq!(answers[1].rdata, RData::A(Ipv4Addr::new(93, 184, 216, 34)));
+}
+
+#[test]
+fn test_decode_record_unknown_type() {
+  let i = b"\x01a\0\xff\xfe\0\x01\0\0\0\0\0\x03abc";
+  let (r, record) = decode_record(i, 0).unwrap();
+  assert_eq!(r, b"");
+  assert_eq!(record.rtype(), RecordType::Unknown(65534));
+  assert_eq!(record.rdata, RData::Unknown { rtype: RecordType::Unknown(65534), data: b"abc".to_vec() });
+}
+
+#[test]
+fn test_decode_name_rejects_bad_pointers() {
+  let i = b"\0\0\x01\0\0\x01\0\0\0\0\0\0\xc0\x0c\0\x01\0\x01";
+  assert!(matches!(decode_name(i, 12), Err(nom::Err::Error(DnsError::ForwardPointer { at: 12, target: 12 }))));
+  let i = b"\x01a\xc0\x00";
+  assert!(matches!(decode_name(i, 0), Err(nom::Err::Error(DnsError::ForwardPointer { at: 2, target: 0 }))));
+  let i = b"\x01a\0\x01b\xc0\x05\xc0\x03";
+  assert!(matches!(decode_name(i, 7), Err(nom::Err::Error(DnsError::ForwardPointer { at: 5, target: 5 }))));
+  let mut i = vec![0u8];
+  for hop in 0..=MAX_POINTER_HOPS {
+    let target = if hop == 0 { 0 } else { 2 * hop - 1 };
+    i.extend_from_slice(&(0xc000 | target as u16).to_be_bytes());
+  }
+  let start = i.len() - 2;
+  assert!(matches!(decode_name(&i, start), Err(nom::Err::Error(DnsError::TooManyPointers))));
+  let (r, name) = decode_name(&i, start - 2).unwrap();
+  assert_eq!(r, &i[start..]);
+  assert!(name.is_root());
+  assert!(matches!(expand_question(b"\x40abc\0\0\x01\0\x01", 0), Err(nom::Err::Error(DnsError::BadLabel))));
+}
+
+#[test]
+fn test_decode_name_length_limit() {
+  let mut i = Vec::new();
+  for _ in 0..4 {
+    i.push(62u8);
+    i.extend_from_slice(&[b'a'; 62]);
+  }
+  i.push(0);
+  let (r, name) = decode_name(&i, 0).unwrap();
+  assert!(r.is_empty());
+  assert_eq!(name.wire_len(), 253);
+  i.splice(0..0, *b"\x02bb");
+  assert!(matches!(decode_name(&i, 0), Err(nom::Err::Error(DnsError::NameTooLong))));
+}
+
+#[test]
+fn test_decode_record_errors() {
+  let i = b"\x01a\0\0\x01\0\x01\0\0\0\0\0\x05\x01\x02\x03\x04";
+  assert!(matches!(decode_record(i, 0), Err(nom::Err::Error(DnsError::TruncatedRdata))));
+  let i = b"\x01a\0\0\x01\0\x01\0\0\0\0\0\x03\x01\x02\x03";
+  assert!(matches!(decode_rdata(&i[13..], &i[13..], RecordType::A), Err(nom::Err::Error(DnsError::BadRdata(RecordType::A)))));
+  assert_eq!(decode_record(i, 0).unwrap().1.rdata, RData::Unknown { rtype: RecordType::A, data: vec![1, 2, 3] });
+  let i = b"\x01a\0\0\x05\0\x01\0\0\0\0\0\x02\x01b\0";
+  assert!(matches!(decode_record(i, 0), Err(nom::Err::Error(DnsError::BadRdata(RecordType::CNAME)))));
+  assert!(matches!(decode_record(b"\x01a\0\0\x01", 0), Err(nom::Err::Error(DnsError::Truncated))));
+}
+
+#[test]
+fn test_decode_opt_record() {
+  let i = b"\0\0\x29\x10\0\0\0\x80\0\0\x12\xfd\xe9\0\x02\x01\x02\0\x0a\0\x08\x01\x02\x03\x04\x05\x06\x07\x08";
+  let (r, record) = decode_record(i, 0).unwrap();
+  assert_eq!(r, b"");
+  assert_eq!(record.rtype(), RecordType::OPT);
+  let cookie = EdnsOption::Cookie { client: [1, 2, 3, 4, 5, 6, 7, 8], server: vec![] };
+  assert_eq!(record.rdata, RData::OPT(vec![EdnsOption::Unknown { code: 65001, data: vec![1, 2] }, cookie]));
+  let i = b"\0\0\x29\x10\0\0\0\x80\0\0\x09\0\x0f\0\x05\0\x16abc";
+  let ede = EdnsOption::ExtendedError { info_code: 22, extra_text: "abc".to_string() };
+  assert_eq!(decode_record(i, 0).unwrap().1.rdata, RData::OPT(vec![ede]));
+  let i = b"\0\0\x29\x10\0\0\0\x80\0\0\x05\0\x0f\0\x01\0";
+  assert_eq!(decode_record(i, 0).unwrap().1.rdata, RData::OPT(vec![EdnsOption::Unknown { code: 15, data: vec![0] }]));
+  let i = b"\0\0\x29\x10\0\0\0\x80\0\0\x0b\0\x08\0\x07\0\x01\x18\x10\xc0\0\x02";
+  let subnet = ClientSubnet { address: "192.0.2.0".parse().unwrap(), source_prefix: 24, scope_prefix: 16 };
+  assert_eq!(decode_record(i, 0).unwrap().1.rdata, RData::OPT(vec![EdnsOption::ClientSubnet(subnet)]));
+  // The address has exactly the bytes of the source prefix, and nothing set after it.
+  for data in [&b"\0\x01\x18\0\xc0\0\x02\x01"[..], b"\0\x01\x17\0\xc0\0\x03", b"\0\x01\x21\0\xc0\0\x02\x01\0", b"\0\x03\0\0", b"\0\x01\x18"] {
+    let rdata = [&[0, 8, 0, data.len() as u8][..], data].concat();
+    assert!(matches!(decode_rdata(&rdata, &rdata, RecordType::OPT), Err(nom::Err::Failure(DnsError::BadEdnsOption(8)))), "{data:?}");
+  }
+  // Client cookies are 8 bytes, and server cookies 8 to 32.
+  let i = b"\0\0\x29\x10\0\0\0\x80\0\0\x04\0\x0a\0\0";
+  assert!(matches!(decode_record(i, 0), Err(nom::Err::Failure(DnsError::BadEdnsOption(10)))));
+  let i = b"\0\0\x29\x10\0\0\0\x80\0\0\x0e\0\x0a\0\x0a\x01\x02\x03\x04\x05\x06\x07\x08\x09\x0a";
+  assert!(matches!(decode_record(i, 0), Err(nom::Err::Failure(DnsError::BadEdnsOption(10)))));
+  let i = b"\0\0\x29\x10\0\0\0\x80\0\0\x05\xfd\xe9\0\x02\x01";
+  assert!(matches!(decode_record(i, 0), Err(nom::Err::Error(DnsError::BadRdata(RecordType::OPT)))));
+}
+
+#[test]
+fn test_decode_common_rdata() {
+  let mut i = Vec::from(&b"\x07example\x03com\0"[..]);
+  let mut record = |rtype: u16, rdata: &[u8]| {
+    let offset = i.len();
+    i.extend_from_slice(b"\xc0\0");
+    i.extend_from_slice(&rtype.to_be_bytes());
+    i.extend_from_slice(b"\0\x01\0\0\x0e\x10");
+    i.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+    i.extend_from_slice(rdata);
+    offset
+  };
+  let mx = record(15, b"\0\x0a\x04mail\xc0\0");
+  let txt = record(16, b"\x05hello\0\x05world");
+  let soa = record(6, b"\x03ns1\xc0\0\x0ahostmaster\xc0\0\0\0\0\x01\0\0\x0e\x10\0\0\x07\x08\0\x09\x3a\x80\0\0\x01\x2c");
+  let srv = record(33, b"\0\x01\0\x02\x01\xbb\x03sip\xc0\0");
+  let caa = record(257, b"\0\x05issueletsencrypt.org");
+  let decode = |offset| decode_record(&i, offset).unwrap().1.rdata;
+  assert_eq!(decode(mx), RData::MX { preference: 10, exchange: Name::from_labels(["mail", "example", "com"]).unwrap() });
+  assert_eq!(decode(txt), RData::TXT(vec![b"hello".to_vec(), vec![], b"world".to_vec()]));
+  assert_eq!(
+    decode(soa),
+    RData::SOA {
+      mname: Name::from_labels(["ns1", "example", "com"]).unwrap(),
+      rname: Name::from_labels(["hostmaster", "example", "com"]).unwrap(),
+      serial: 1,
+      refresh: 3600,
+      retry: 1800,
+      expire: 604800,
+      minimum: 300,
+    }
+  );
+  assert_eq!(decode(srv), RData::SRV { priority: 1, weight: 2, port: 443, target: Name::from_labels(["sip", "example", "com"]).unwrap() });
+  assert_eq!(decode(caa), RData::CAA { flags: 0, tag: b"issue".to_vec(), value: b"letsencrypt.org".to_vec() });
+}
+
+#[test]
+fn test_decode_rdata_errors() {
+  let i = b"\x01a\0\0\x0f\0\x01\0\0\0\0\0\x03\0\x0a\x01b\0";
+  assert!(matches!(decode_record(i, 0), Err(nom::Err::Error(DnsError::BadRdata(RecordType::MX)))));
+  let i = b"\x01a\0\0\x0f\0\x01\0\0\0\0\0\x06\0\x0a\x01b\0\x01";
+  assert!(matches!(decode_record(i, 0), Err(nom::Err::Error(DnsError::BadRdata(RecordType::MX)))));
+  // Rdata without names is kept as it is.
+  let i = b"\x01a\0\0\x10\0\x01\0\0\0\0\0\x03\x05ab";
+  assert!(matches!(decode_rdata(&i[13..], &i[13..], RecordType::TXT), Err(nom::Err::Error(DnsError::BadRdata(RecordType::TXT)))));
+  assert_eq!(decode_record(i, 0).unwrap().1.rdata, RData::Unknown { rtype: RecordType::TXT, data: b"\x05ab".to_vec() });
+  let i = b"\x01a\0\0\x05\0\x01\0\0\0\0\0\x02\xc0\x0d";
+  assert!(matches!(decode_record(i, 0), Err(nom::Err::Error(DnsError::ForwardPointer { .. }))));
+}
+
+#[test]
+fn test_decode_svcb_rdata() {
+  // Test vectors from RFC 9460 appendix D.
+  let target = Name::from_labels(["foo", "example", "org"]).unwrap();
+  let data = b"\0\x10\x03foo\x07example\x03org\0\0\0\0\x04\0\x01\0\x04\0\x01\0\x09\x02h2\x05h3-19\0\x04\0\x04\xc0\0\x02\x01";
+  let params = vec![
+    SvcParam::Mandatory(vec![svcb::ALPN, svcb::IPV4HINT]),
+    SvcParam::Alpn(vec![b"h2".to_vec(), b"h3-19".to_vec()]),
+    SvcParam::Ipv4Hint(vec![Ipv4Addr::new(192, 0, 2, 1)]),
+  ];
+  assert_eq!(decode_rdata(data, data, RecordType::SVCB).unwrap(), RData::SVCB { priority: 16, target: target.clone(), params: params.clone() });
+  assert_eq!(decode_rdata(data, data, RecordType::HTTPS).unwrap(), RData::HTTPS { priority: 16, target, params });
+  let data = b"\0\0\x03foo\x07example\x03com\0";
+  assert!(matches!(decode_rdata(data, data, RecordType::SVCB).unwrap(), RData::SVCB { priority: 0, .. }));
+  let invalid: [&[u8]; 2] = [
+    // Keys out of order.
+    b"\0\x01\0\0\x03\0\x02\0\x35\0\x01\0\x03\x02h2",
+    // Mandatory lists a key that is missing.
+    b"\0\x01\0\0\0\0\x02\0\x03",
+  ];
+  for data in invalid {
+    // Read off the wire as they are, and only rejected when built or read as text.
+    let rdata = decode_rdata(data, data, RecordType::HTTPS).unwrap();
+    assert!(matches!(rdata.validate(), Err(DnsError::BadSvcParams(_))), "{rdata:?}");
+  }
+  let bad: [&[u8]; 3] = [
+    // Port value too long.
+    b"\0\x01\0\0\x03\0\x03\0\x35\0",
+    // ipv6hint not a multiple of 16 bytes.
+    b"\0\x01\0\0\x06\0\x04\x20\x01\x0d\xb8",
+    // Value runs past the end of the rdata.
+    b"\0\x01\0\0\x01\0\x09\x02h2",
+  ];
+  for data in bad {
+    assert!(decode_rdata(data, data, RecordType::HTTPS).is_err());
+  }
+}
+
+#[test]
+fn test_decode_type_bitmap() {
+  let nsec = [&b"\x04host\x07example\x03com\0\x00\x06\x40\x01\x00\x00\x00\x03\x04\x1b"[..], &[0; 26], b"\x20"].concat();
+  assert_eq!(
+    decode_rdata(&nsec, &nsec, RecordType::NSEC).unwrap(),
+    RData::NSEC {
+      next_domain: Name::from_labels(["host", "example", "com"]).unwrap(),
+      types: vec![RecordType::A, RecordType::MX, RecordType::RRSIG, RecordType::NSEC, RecordType::Unknown(1234)],
+    }
+  );
+  let bad = [
+    // Windows out of order.
+    b"\x01\x01\x40\0\x01\x40".to_vec(),
+    // Empty window.
+    b"\0\0".to_vec(),
+    // Window ending in a zero byte.
+    b"\0\x02\x40\0".to_vec(),
+    // Window longer than 32 bytes.
+    [&b"\0\x21"[..], &[0xff; 33]].concat(),
+    // Window running past the end.
+    b"\0\x02\x40".to_vec(),
+  ];
+  for bitmap in bad {
+    let data = [&b"\0"[..], &bitmap].concat();
+    let result = decode_rdata(&data, &data, RecordType::NSEC);
+    assert!(matches!(result, Err(nom::Err::Error(DnsError::BadRdata(RecordType::NSEC)))), "{bitmap:02x?}: {result:?}");
+  }
+}
@@ -0,0 +1,1090 @@
+#![allow(dead_code)]
+//! The zone-file text form of records (RFC 1035 section 5) and dig-style dumps of whole messages.
+use std::{
+  fmt::{self, Display, Write},
+  net::{Ipv4Addr, Ipv6Addr},
+  str::FromStr,
+};
+
+use crate::{
+  edns::{Edns, EdnsOption, EDE_NAMES},
+  error::DnsError,
+  header::{Opcode, Rcode},
+  message::Message,
+  message_ref::{MessageRef, RecordRef},
+  name::Name,
+  parser::decode_rdata,
+  record::{DnsClass, Question, RData, RecordType, ResourceRecord},
+  svcb::{self, SvcParam},
+};
+use data_encoding::{BASE32HEX_NOPAD, BASE64};
+
+impl Display for RecordType {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      RecordType::A => f.write_str("A"),
+      RecordType::NS => f.write_str("NS"),
+      RecordType::CNAME => f.write_str("CNAME"),
+      RecordType::SOA => f.write_str("SOA"),
+      RecordType::PTR => f.write_str("PTR"),
+      RecordType::MX => f.write_str("MX"),
+      RecordType::TXT => f.write_str("TXT"),
+      RecordType::AAAA => f.write_str("AAAA"),
+      RecordType::SRV => f.write_str("SRV"),
+      RecordType::OPT => f.write_str("OPT"),
+      RecordType::DS => f.write_str("DS"),
+      RecordType::RRSIG => f.write_str("RRSIG"),
+      RecordType::NSEC => f.write_str("NSEC"),
+      RecordType::DNSKEY => f.write_str("DNSKEY"),
+      RecordType::NSEC3 => f.write_str("NSEC3"),
+      RecordType::NSEC3PARAM => f.write_str("NSEC3PARAM"),
+      RecordType::SVCB => f.write_str("SVCB"),
+      RecordType::HTTPS => f.write_str("HTTPS"),
+      RecordType::TSIG => f.write_str("TSIG"),
+      RecordType::ANY => f.write_str("ANY"),
+      RecordType::CAA => f.write_str("CAA"),
+      RecordType::Unknown(other) => write!(f, "TYPE{other}"),
+    }
+  }
+}
+
+impl FromStr for RecordType {
+  type Err = DnsError;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let rtype = match s.to_ascii_uppercase().as_str() {
+      "A" => Self::A,
+      "NS" => Self::NS,
+      "CNAME" => Self::CNAME,
+      "SOA" => Self::SOA,
+      "PTR" => Self::PTR,
+      "MX" => Self::MX,
+      "TXT" => Self::TXT,
+      "AAAA" => Self::AAAA,
+      "SRV" => Self::SRV,
+      "OPT" => Self::OPT,
+      "DS" => Self::DS,
+      "RRSIG" => Self::RRSIG,
+      "NSEC" => Self::NSEC,
+      "DNSKEY" => Self::DNSKEY,
+      "NSEC3" => Self::NSEC3,
+      "NSEC3PARAM" => Self::NSEC3PARAM,
+      "SVCB" => Self::SVCB,
+      "HTTPS" => Self::HTTPS,
+      "TSIG" => Self::TSIG,
+      "ANY" => Self::ANY,
+      "CAA" => Self::CAA,
+      other => match other.strip_prefix("TYPE").map(str::parse::<u16>) {
+        Some(Ok(value)) => Self::from(value),
+        _ => return Err(DnsError::Syntax(format!("unknown record type {s:?}"))),
+      },
+    };
+    Ok(rtype)
+  }
+}
+
+impl Display for DnsClass {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      DnsClass::IN => f.write_str("IN"),
+      DnsClass::CH => f.write_str("CH"),
+      DnsClass::HS => f.write_str("HS"),
+      DnsClass::NONE => f.write_str("NONE"),
+      DnsClass::ANY => f.write_str("ANY"),
+      DnsClass::Unknown(other) => write!(f, "CLASS{other}"),
+    }
+  }
+}
+
+impl FromStr for DnsClass {
+  type Err = DnsError;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let class = match s.to_ascii_uppercase().as_str() {
+      "IN" => Self::IN,
+      "CH" => Self::CH,
+      "HS" => Self::HS,
+      "NONE" => Self::NONE,
+      "ANY" => Self::ANY,
+      other => match other.strip_prefix("CLASS").map(str::parse::<u16>) {
+        Some(Ok(value)) => Self::from(value),
+        _ => return Err(DnsError::Syntax(format!("unknown class {s:?}"))),
+      },
+    };
+    Ok(class)
+  }
+}
+
+impl Display for Opcode {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Opcode::Query => f.write_str("QUERY"),
+      Opcode::IQuery => f.write_str("IQUERY"),
+      Opcode::Status => f.write_str("STATUS"),
+      Opcode::Notify => f.write_str("NOTIFY"),
+      Opcode::Update => f.write_str("UPDATE"),
+      Opcode::Unknown(other) => write!(f, "RESERVED{other}"),
+    }
+  }
+}
+
+impl Display for Rcode {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let name = match self {
+      Rcode::NoError => "NOERROR",
+      Rcode::FormErr => "FORMERR",
+      Rcode::ServFail => "SERVFAIL",
+      Rcode::NXDomain => "NXDOMAIN",
+      Rcode::NotImp => "NOTIMP",
+      Rcode::Refused => "REFUSED",
+      Rcode::YXDomain => "YXDOMAIN",
+      Rcode::YXRRSet => "YXRRSET",
+      Rcode::NXRRSet => "NXRRSET",
+      Rcode::NotAuth => "NOTAUTH",
+      Rcode::NotZone => "NOTZONE",
+      Rcode::BadVers => "BADVERS",
+      Rcode::BadKey => "BADKEY",
+      Rcode::BadTime => "BADTIME",
+      Rcode::BadMode => "BADMODE",
+      Rcode::BadName => "BADNAME",
+      Rcode::BadAlg => "BADALG",
+      Rcode::BadTrunc => "BADTRUNC",
+      Rcode::BadCookie => "BADCOOKIE",
+      Rcode::Unknown(other) => return write!(f, "RCODE{other}"),
+    };
+    f.write_str(name)
+  }
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+  bytes.iter().fold(String::with_capacity(bytes.len() * 2), |mut s, byte| {
+    let _ = write!(s, "{byte:02X}");
+    s
+  })
+}
+
+/// Reads hex digits, in either case, two to a byte.
+pub fn from_hex(s: &str) -> Result<Vec<u8>, DnsError> {
+  if s.len() % 2 != 0 || !s.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+    return Err(DnsError::Syntax(format!("bad hex {s:?}")));
+  }
+  Ok((0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap()).collect())
+}
+
+/// An NSEC3 salt in hex, or `-` when there is none.
+struct Salt<'a>(&'a [u8]);
+
+impl Display for Salt<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self.0 {
+      [] => f.write_char('-'),
+      salt => f.write_str(&hex(salt)),
+    }
+  }
+}
+
+/// Days since 1970-01-01 of a date in the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+  let year = if month <= 2 { year - 1 } else { year };
+  let era = year.div_euclid(400);
+  let year_of_era = year - era * 400;
+  let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+  let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+  era * 146097 + day_of_era - 719468
+}
+
+/// The date `days` after 1970-01-01, as year, month and day.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+  let days = days + 719468;
+  let era = days.div_euclid(146097);
+  let day_of_era = days - era * 146097;
+  let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+  let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+  let month = (5 * day_of_year + 2) / 153;
+  let day = day_of_year - (153 * month + 2) / 5 + 1;
+  let month = if month < 10 { month + 3 } else { month - 9 };
+  (year_of_era + era * 400 + i64::from(month <= 2), month, day)
+}
+
+/// RRSIG times as `YYYYMMDDHHmmSS` in UTC (RFC 4034 section 3.2).
+struct Timestamp(u32);
+
+impl Display for Timestamp {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let seconds = self.0 as i64;
+    let (year, month, day) = civil_from_days(seconds / 86400);
+    let time = seconds % 86400;
+    write!(f, "{year:04}{month:02}{day:02}{:02}{:02}{:02}", time / 3600, time / 60 % 60, time % 60)
+  }
+}
+
+/// The error field of TSIG, where 16 is BADSIG rather than BADVERS (RFC 8945 section 3).
+struct TsigError(u16);
+
+impl Display for TsigError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self.0 {
+      16 => f.write_str("BADSIG"),
+      error => Rcode::from(error).fmt(f),
+    }
+  }
+}
+
+/// Binary TSIG fields, written as their length and then, unless empty, base64.
+struct SizedBase64<'a>(&'a [u8]);
+
+impl Display for SizedBase64<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self.0 {
+      [] => f.write_str("0"),
+      data => write!(f, "{} {}", data.len(), BASE64.encode(data)),
+    }
+  }
+}
+
+/// A `<character-string>` in double quotes, escaping anything that is not printable ASCII.
+struct CharacterString<'a>(&'a [u8]);
+
+impl Display for CharacterString<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_char('"')?;
+    for &byte in self.0 {
+      match byte {
+        b'"' | b'\\' => write!(f, "\\{}", byte as char)?,
+        0x20..=0x7e => f.write_char(byte as char)?,
+        _ => write!(f, "\\{byte:03}")?,
+      }
+    }
+    f.write_char('"')
+  }
+}
+
+impl Display for RData {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      RData::A(ip) => write!(f, "{ip}"),
+      RData::AAAA(ip) => write!(f, "{ip}"),
+      RData::NS(name) | RData::CNAME(name) | RData::PTR(name) => name.fmt(f),
+      RData::MX { preference, exchange } => {
+        write!(f, "{preference} ")?;
+        exchange.fmt(f)
+      }
+      RData::TXT(strings) => {
+        for (i, string) in strings.iter().enumerate() {
+          if i > 0 {
+            f.write_char(' ')?;
+          }
+          write!(f, "{}", CharacterString(string))?;
+        }
+        Ok(())
+      }
+      RData::SOA { mname, rname, serial, refresh, retry, expire, minimum } => {
+        mname.fmt(f)?;
+        f.write_char(' ')?;
+        rname.fmt(f)?;
+        write!(f, " {serial} {refresh} {retry} {expire} {minimum}")
+      }
+      RData::SRV { priority, weight, port, target } => {
+        write!(f, "{priority} {weight} {port} ")?;
+        target.fmt(f)
+      }
+      RData::CAA { flags, tag, value } => write!(f, "{flags} {} {}", String::from_utf8_lossy(tag), CharacterString(value)),
+      RData::OPT(options) => {
+        let data: Vec<u8> = options.iter().flat_map(|option| {
+          let data = option.data();
+          [option.code().to_be_bytes(), (data.len() as u16).to_be_bytes()].concat().into_iter().chain(data)
+        }).collect();
+        write!(f, "\\# {} {}", data.len(), hex(&data))
+      }
+      RData::DS { key_tag, algorithm, digest_type, digest } => write!(f, "{key_tag} {algorithm} {digest_type} {}", hex(digest)),
+      RData::RRSIG { type_covered, algorithm, labels, original_ttl, expiration, inception, key_tag, signer_name, signature } => {
+        write!(f, "{type_covered} {algorithm} {labels} {original_ttl} {} {} {key_tag} ", Timestamp(*expiration), Timestamp(*inception))?;
+        signer_name.fmt(f)?;
+        write!(f, " {}", BASE64.encode(signature))
+      }
+      RData::NSEC { next_domain, types } => {
+        next_domain.fmt(f)?;
+        for rtype in types {
+          write!(f, " {rtype}")?;
+        }
+        Ok(())
+      }
+      RData::DNSKEY { flags, protocol, algorithm, public_key } => write!(f, "{flags} {protocol} {algorithm} {}", BASE64.encode(public_key)),
+      RData::NSEC3 { hash_algorithm, flags, iterations, salt, next_hashed_owner, types } => {
+        let next_hashed_owner = BASE32HEX_NOPAD.encode(next_hashed_owner).to_ascii_lowercase();
+        write!(f, "{hash_algorithm} {flags} {iterations} {} {next_hashed_owner}", Salt(salt))?;
+        for rtype in types {
+          write!(f, " {rtype}")?;
+        }
+        Ok(())
+      }
+      RData::NSEC3PARAM { hash_algorithm, flags, iterations, salt } => write!(f, "{hash_algorithm} {flags} {iterations} {}", Salt(salt)),
+      RData::SVCB { priority, target, params } | RData::HTTPS { priority, target, params } => {
+        write!(f, "{priority} ")?;
+        target.fmt(f)?;
+        for param in params {
+          write!(f, " {param}")?;
+        }
+        Ok(())
+      }
+      RData::TSIG { algorithm, time_signed, fudge, mac, original_id, error, other } => {
+        algorithm.fmt(f)?;
+        write!(f, " {time_signed} {fudge} {} {original_id} {} {}", SizedBase64(mac), TsigError(*error), SizedBase64(other))
+      }
+      RData::Unknown { data, .. } if data.is_empty() => f.write_str("\\# 0"),
+      RData::Unknown { data, .. } => write!(f, "\\# {} {}", data.len(), hex(data)),
+    }
+  }
+}
+
+/// A SvcParamKey by name, or as `keyNNNNN` when it has none.
+struct SvcParamKey(u16);
+
+impl Display for SvcParamKey {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self.0 {
+      svcb::MANDATORY => f.write_str("mandatory"),
+      svcb::ALPN => f.write_str("alpn"),
+      svcb::NO_DEFAULT_ALPN => f.write_str("no-default-alpn"),
+      svcb::PORT => f.write_str("port"),
+      svcb::IPV4HINT => f.write_str("ipv4hint"),
+      svcb::ECH => f.write_str("ech"),
+      svcb::IPV6HINT => f.write_str("ipv6hint"),
+      key => write!(f, "key{key}"),
+    }
+  }
+}
+
+fn svc_param_key(s: &str) -> Result<u16, DnsError> {
+  let key = match s.to_ascii_lowercase().as_str() {
+    "mandatory" => svcb::MANDATORY,
+    "alpn" => svcb::ALPN,
+    "no-default-alpn" => svcb::NO_DEFAULT_ALPN,
+    "port" => svcb::PORT,
+    "ipv4hint" => svcb::IPV4HINT,
+    "ech" => svcb::ECH,
+    "ipv6hint" => svcb::IPV6HINT,
+    other => match other.strip_prefix("key").map(str::parse::<u16>) {
+      Some(Ok(key)) => key,
+      _ => return Err(DnsError::Syntax(format!("unknown SvcParamKey {s:?}"))),
+    },
+  };
+  Ok(key)
+}
+
+/// One item of a SvcParamValue, escaping commas so lists split back apart.
+struct SvcParamValue<'a>(&'a [u8]);
+
+impl Display for SvcParamValue<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    for &byte in self.0 {
+      match byte {
+        b',' | b'\\' | b'"' | b';' | b'(' | b')' => write!(f, "\\{}", byte as char)?,
+        0x21..=0x7e => f.write_char(byte as char)?,
+        _ => write!(f, "\\{byte:03}")?,
+      }
+    }
+    Ok(())
+  }
+}
+
+fn write_list<T>(f: &mut fmt::Formatter<'_>, items: &[T], item: impl Fn(&T) -> String) -> fmt::Result {
+  for (i, value) in items.iter().enumerate() {
+    f.write_str(if i == 0 { "=" } else { "," })?;
+    f.write_str(&item(value))?;
+  }
+  Ok(())
+}
+
+/// `key=value` as in RFC 9460 section 2.1, or just the key when the value is empty.
+impl Display for SvcParam {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", SvcParamKey(self.key()))?;
+    match self {
+      SvcParam::Mandatory(keys) => write_list(f, keys, |key| SvcParamKey(*key).to_string()),
+      SvcParam::Alpn(ids) => write_list(f, ids, |id| SvcParamValue(id).to_string()),
+      SvcParam::NoDefaultAlpn => Ok(()),
+      SvcParam::Port(port) => write!(f, "={port}"),
+      SvcParam::Ipv4Hint(ips) => write_list(f, ips, Ipv4Addr::to_string),
+      SvcParam::Ech(value) => write!(f, "={}", BASE64.encode(value)),
+      SvcParam::Ipv6Hint(ips) => write_list(f, ips, Ipv6Addr::to_string),
+      SvcParam::Unknown { value, .. } if value.is_empty() => Ok(()),
+      SvcParam::Unknown { value, .. } => write!(f, "={}", SvcParamValue(value)),
+    }
+  }
+}
+
+impl Display for Question {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    self.name.fmt(f)?;
+    write!(f, "\t\t{}\t{}", self.qclass, self.qtype)
+  }
+}
+
+impl Display for ResourceRecord {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    self.name.fmt(f)?;
+    write!(f, "\t{}\t{}\t{}\t", self.ttl, self.class, self.rtype())?;
+    self.rdata.fmt(f)
+  }
+}
+
+impl Display for EdnsOption {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      EdnsOption::ClientSubnet(subnet) => write!(f, "CLIENT-SUBNET: {}/{}/{}", subnet.address, subnet.source_prefix, subnet.scope_prefix),
+      EdnsOption::Cookie { client, server } => write!(f, "COOKIE: {}{}", hex(client), hex(server)),
+      EdnsOption::ExtendedError { info_code, extra_text } => {
+        write!(f, "EDE: {info_code}")?;
+        if let Some(name) = EDE_NAMES.get(*info_code as usize) {
+          write!(f, " ({name})")?;
+        }
+        if !extra_text.is_empty() {
+          write!(f, ": ({extra_text})")?;
+        }
+        Ok(())
+      }
+      EdnsOption::Unknown { code, data } => write!(f, "OPT={code}: {}", hex(data)),
+    }
+  }
+}
+
+impl Display for Edns {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "; EDNS: version: {}, flags:{}; udp: {}", self.version, if self.dnssec_ok { " do" } else { "" }, self.udp_payload_size)?;
+    for option in &self.options {
+      write!(f, "\n; {option}")?;
+    }
+    Ok(())
+  }
+}
+
+impl Display for Message {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    self.view().fmt(f)
+  }
+}
+
+fn to_records<'a>(records: impl Iterator<Item = Result<RecordRef<'a>, DnsError>>) -> Result<Vec<ResourceRecord>, DnsError> {
+  records.map(|record| record?.to_record()).collect()
+}
+
+/// The same layout `dig` uses, so dumps can be compared side by side. With `{:#}`,
+/// names are shown in Unicode wherever they hold A-labels.
+impl Display for MessageRef<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let (questions, answers, authorities, additionals, edns) = match (
+      self.questions().map(|question| Ok(question?.to_question())).collect::<Result<Vec<_>, DnsError>>(),
+      to_records(self.answers()),
+      to_records(self.authorities()),
+      to_records(self.additionals()),
+      self.edns(),
+    ) {
+      (Ok(questions), Ok(answers), Ok(authorities), Ok(additionals), Ok(edns)) => (questions, answers, authorities, additionals, edns),
+      (Err(e), ..) | (_, Err(e), ..) | (_, _, Err(e), ..) | (.., Err(e), _) | (.., Err(e)) => {
+        return write!(f, ";; malformed message ({e}): {:02X?}", self.as_bytes());
+      }
+    };
+    let header = self.header();
+    let status = edns.as_ref().map_or(header.flags.rcode, |edns| edns.rcode(header.flags.rcode));
+    writeln!(f, ";; ->>HEADER<<- opcode: {}, status: {status}, id: {}", header.flags.opcode, header.id)?;
+    let flags = [
+      (header.flags.qr, "qr"),
+      (header.flags.aa, "aa"),
+      (header.flags.tc, "tc"),
+      (header.flags.rd, "rd"),
+      (header.flags.ra, "ra"),
+      (header.flags.ad, "ad"),
+      (header.flags.cd, "cd"),
+    ];
+    f.write_str(";; flags:")?;
+    for (_, flag) in flags.iter().filter(|(set, _)| *set) {
+      write!(f, " {flag}")?;
+    }
+    write!(
+      f,
+      "; QUERY: {}, ANSWER: {}, AUTHORITY: {}, ADDITIONAL: {}",
+      header.question_count, header.answer_count, header.authority_count, header.additional_count
+    )?;
+    if let Some(edns) = &edns {
+      write!(f, "\n\n;; OPT PSEUDOSECTION:\n{edns}")?;
+    }
+    f.write_str("\n\n;; QUESTION SECTION:")?;
+    for question in &questions {
+      f.write_str("\n;")?;
+      question.fmt(f)?;
+    }
+    let additionals: Vec<_> = additionals.into_iter().filter(|record| record.rtype() != RecordType::OPT).collect();
+    for (title, records) in [("ANSWER", answers), ("AUTHORITY", authorities), ("ADDITIONAL", additionals)] {
+      if records.is_empty() {
+        continue;
+      }
+      write!(f, "\n\n;; {title} SECTION:")?;
+      for record in &records {
+        f.write_char('\n')?;
+        record.fmt(f)?;
+      }
+    }
+    Ok(())
+  }
+}
+
+/// One field of a record in presentation format, still holding its escapes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Token {
+  text: String,
+  quoted: bool,
+}
+
+/// Splits presentation format into fields, dropping `;` comments and the
+/// parentheses that let a record span several lines.
+fn tokenize(s: &str) -> Result<Vec<Token>, DnsError> {
+  let mut tokens = Vec::new();
+  let mut chars = s.chars();
+  let mut current: Option<Token> = None;
+  while let Some(c) = chars.next() {
+    let in_quotes = current.as_ref().is_some_and(|token| token.quoted);
+    match c {
+      '\\' => {
+        let escaped = chars.next().ok_or_else(|| DnsError::Syntax(format!("dangling escape in {s:?}")))?;
+        let token = current.get_or_insert_with(|| Token { text: String::new(), quoted: false });
+        token.text.push('\\');
+        token.text.push(escaped);
+      }
+      '"' if in_quotes => tokens.extend(current.take()),
+      '"' if current.is_none() => current = Some(Token { text: String::new(), quoted: true }),
+      _ if in_quotes => current.as_mut().unwrap().text.push(c),
+      ';' => {
+        tokens.extend(current.take());
+        chars.by_ref().find(|&c| c == '\n');
+      }
+      '(' | ')' => tokens.extend(current.take()),
+      c if c.is_whitespace() => tokens.extend(current.take()),
+      c => current.get_or_insert_with(|| Token { text: String::new(), quoted: false }).text.push(c),
+    }
+  }
+  if current.as_ref().is_some_and(|token| token.quoted) {
+    return Err(DnsError::Syntax(format!("unterminated string in {s:?}")));
+  }
+  tokens.extend(current);
+  Ok(tokens)
+}
+
+/// Undoes `\X` and `\DDD` escapes.
+pub fn unescape(s: &str) -> Result<Vec<u8>, DnsError> {
+  let mut bytes = Vec::with_capacity(s.len());
+  let mut rest = s.as_bytes();
+  while let Some((&byte, r)) = rest.split_first() {
+    rest = r;
+    if byte != b'\\' {
+      bytes.push(byte);
+      continue;
+    }
+    match rest {
+      [a, b, c, r @ ..] if a.is_ascii_digit() && b.is_ascii_digit() && c.is_ascii_digit() => {
+        let value = (a - b'0') as u16 * 100 + (b - b'0') as u16 * 10 + (c - b'0') as u16;
+        bytes.push(u8::try_from(value).map_err(|_| DnsError::Syntax(format!("bad escape in {s:?}")))?);
+        rest = r;
+      }
+      [escaped, r @ ..] => {
+        bytes.push(*escaped);
+        rest = r;
+      }
+      [] => return Err(DnsError::Syntax(format!("dangling escape in {s:?}"))),
+    }
+  }
+  Ok(bytes)
+}
+
+fn field<T: FromStr>(token: &Token) -> Result<T, DnsError> {
+  token.text.parse().map_err(|_| DnsError::Syntax(format!("bad field {:?}", token.text)))
+}
+
+fn name(token: &Token) -> Result<Name, DnsError> {
+  token.text.parse()
+}
+
+fn character_string(token: &Token) -> Result<Vec<u8>, DnsError> {
+  let bytes = unescape(&token.text)?;
+  if bytes.len() > 255 {
+    return Err(DnsError::Syntax(format!("string longer than 255 bytes: {:?}", token.text)));
+  }
+  Ok(bytes)
+}
+
+/// An RRSIG time, as `YYYYMMDDHHmmSS` or a plain number of seconds. Dates past
+/// 2106 wrap around, as serial number arithmetic expects.
+fn timestamp(token: &Token) -> Result<u32, DnsError> {
+  let text = &token.text;
+  if text.len() != 14 || !text.bytes().all(|byte| byte.is_ascii_digit()) {
+    return field(token);
+  }
+  let part = |range: std::ops::Range<usize>| text[range].parse::<i64>().unwrap();
+  let (month, day, hour, minute, second) = (part(4..6), part(6..8), part(8..10), part(10..12), part(12..14));
+  if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 59 {
+    return Err(DnsError::Syntax(format!("bad time {text:?}")));
+  }
+  let seconds = days_from_civil(part(0..4), month, day) * 86400 + hour * 3600 + minute * 60 + second;
+  Ok(seconds as u32)
+}
+
+/// Base64 that may be split into several fields.
+fn base64(tokens: &[Token]) -> Result<Vec<u8>, DnsError> {
+  let text: String = tokens.iter().map(|token| token.text.as_str()).collect();
+  BASE64.decode(text.as_bytes()).map_err(|_| DnsError::Syntax(format!("bad base64 {text:?}")))
+}
+
+/// A length followed, unless it is 0, by that many bytes in base64.
+fn sized_base64(tokens: &[Token]) -> Result<(Vec<u8>, &[Token]), DnsError> {
+  match tokens {
+    [size, rest @ ..] if size.text == "0" => Ok((Vec::new(), rest)),
+    [size, data, rest @ ..] => {
+      let (size, data): (usize, _) = (field(size)?, base64(std::slice::from_ref(data))?);
+      if data.len() != size {
+        return Err(DnsError::Syntax(format!("{size} bytes expected, found {}", data.len())));
+      }
+      Ok((data, rest))
+    }
+    _ => Err(DnsError::Syntax("missing TSIG field".to_string())),
+  }
+}
+
+fn tsig_error(token: &Token) -> Result<u16, DnsError> {
+  let error = token.text.to_ascii_uppercase();
+  if let Some(value) = error.strip_prefix("RCODE").and_then(|value| value.parse().ok()) {
+    return Ok(value);
+  }
+  (0..=u16::from(Rcode::BadCookie))
+    .find(|&value| TsigError(value).to_string() == error)
+    .map_or_else(|| field(token), Ok)
+}
+
+fn salt(token: &Token) -> Result<Vec<u8>, DnsError> {
+  let salt = match token.text.as_str() {
+    "-" => Vec::new(),
+    text => from_hex(text)?,
+  };
+  if salt.len() > 255 {
+    return Err(DnsError::Syntax(format!("salt longer than 255 bytes: {:?}", token.text)));
+  }
+  Ok(salt)
+}
+
+/// The types of an NSEC or NSEC3 bitmap, in ascending order.
+fn types(tokens: &[Token]) -> Result<Vec<RecordType>, DnsError> {
+  let mut types = tokens.iter().map(|token| token.text.parse()).collect::<Result<Vec<RecordType>, _>>()?;
+  types.sort_by_key(|&rtype| u16::from(rtype));
+  types.dedup();
+  Ok(types)
+}
+
+/// Splits presentation text at every `separator` that is not escaped.
+pub fn split_unescaped(s: &str, separator: char) -> Vec<&str> {
+  let mut parts = Vec::new();
+  let mut start = 0;
+  let mut escaped = false;
+  for (i, c) in s.char_indices() {
+    match c {
+      _ if escaped => escaped = false,
+      '\\' => escaped = true,
+      c if c == separator => {
+        parts.push(&s[start..i]);
+        start = i + c.len_utf8();
+      }
+      _ => {}
+    }
+  }
+  parts.push(&s[start..]);
+  parts
+}
+
+/// A `key=value` field of SVCB or HTTPS rdata. The value may be in double quotes,
+/// and lists are split at unescaped commas.
+fn svc_param(token: &Token) -> Result<SvcParam, DnsError> {
+  let bad = || DnsError::Syntax(format!("bad SvcParam {:?}", token.text));
+  let (key, value) = match token.text.split_once('=') {
+    Some((key, value)) => (key, Some(value.strip_prefix('"').and_then(|value| value.strip_suffix('"')).unwrap_or(value))),
+    None => (token.text.as_str(), None),
+  };
+  let list = || value.filter(|value| !value.is_empty()).map(|value| split_unescaped(value, ',')).ok_or_else(bad);
+  let param = match svc_param_key(key)? {
+    svcb::MANDATORY => {
+      let mut keys = list()?.into_iter().map(svc_param_key).collect::<Result<Vec<_>, _>>()?;
+      keys.sort_unstable();
+      SvcParam::Mandatory(keys)
+    }
+    svcb::ALPN => SvcParam::Alpn(list()?.into_iter().map(unescape).collect::<Result<_, _>>()?),
+    svcb::NO_DEFAULT_ALPN if value.is_none() => SvcParam::NoDefaultAlpn,
+    svcb::NO_DEFAULT_ALPN => return Err(bad()),
+    svcb::PORT => SvcParam::Port(value.and_then(|port| port.parse().ok()).ok_or_else(bad)?),
+    svcb::IPV4HINT => SvcParam::Ipv4Hint(list()?.into_iter().map(|ip| ip.parse().map_err(|_| bad())).collect::<Result<_, _>>()?),
+    svcb::ECH => SvcParam::Ech(BASE64.decode(value.ok_or_else(bad)?.as_bytes()).map_err(|_| bad())?),
+    svcb::IPV6HINT => SvcParam::Ipv6Hint(list()?.into_iter().map(|ip| ip.parse().map_err(|_| bad())).collect::<Result<_, _>>()?),
+    key => SvcParam::Unknown { key, value: value.map(unescape).transpose()?.unwrap_or_default() },
+  };
+  Ok(param)
+}
+
+/// The RFC 3597 `\# <length> <hex>` form, which rdata of any type may be written in.
+/// The hex may be split into several fields.
+fn generic_rdata(rtype: RecordType, length: &Token, hex: &[Token]) -> Result<RData, DnsError> {
+  let length: usize = field(length)?;
+  let data = from_hex(&hex.iter().map(|token| token.text.as_str()).collect::<String>())?;
+  if data.len() != length {
+    return Err(DnsError::Syntax(format!("\\# length {length} does not match {} bytes of data", data.len())));
+  }
+  match rtype {
+    RecordType::Unknown(_) => Ok(RData::Unknown { rtype, data }),
+    rtype => Ok(decode_rdata(&data, &data, rtype)?),
+  }
+}
+
+fn parse_rdata(rtype: RecordType, fields: &[Token]) -> Result<RData, DnsError> {
+  if let [marker, length, hex @ ..] = fields {
+    if marker.text == "\\#" && !marker.quoted {
+      let rdata = generic_rdata(rtype, length, hex)?;
+      rdata.validate()?;
+      return Ok(rdata);
+    }
+  }
+  let wrong_count = || DnsError::Syntax(format!("wrong number of fields for {rtype}"));
+  let rdata = match (rtype, fields) {
+    (RecordType::A, [ip]) => RData::A(field(ip)?),
+    (RecordType::AAAA, [ip]) => RData::AAAA(field(ip)?),
+    (RecordType::NS, [target]) => RData::NS(name(target)?),
+    (RecordType::CNAME, [target]) => RData::CNAME(name(target)?),
+    (RecordType::PTR, [target]) => RData::PTR(name(target)?),
+    (RecordType::MX, [preference, exchange]) => RData::MX { preference: field(preference)?, exchange: name(exchange)? },
+    (RecordType::TXT, strings) if !strings.is_empty() => RData::TXT(strings.iter().map(character_string).collect::<Result<_, _>>()?),
+    (RecordType::SOA, [mname, rname, serial, refresh, retry, expire, minimum]) => RData::SOA {
+      mname: name(mname)?,
+      rname: name(rname)?,
+      serial: field(serial)?,
+      refresh: field(refresh)?,
+      retry: field(retry)?,
+      expire: field(expire)?,
+      minimum: field(minimum)?,
+    },
+    (RecordType::SRV, [priority, weight, port, target]) => RData::SRV {
+      priority: field(priority)?,
+      weight: field(weight)?,
+      port: field(port)?,
+      target: name(target)?,
+    },
+    (RecordType::CAA, [flags, tag, value]) => RData::CAA { flags: field(flags)?, tag: tag.text.as_bytes().to_vec(), value: unescape(&value.text)? },
+    (RecordType::DS, [key_tag, algorithm, digest_type, digest @ ..]) if !digest.is_empty() => RData::DS {
+      key_tag: field(key_tag)?,
+      algorithm: field(algorithm)?,
+      digest_type: field(digest_type)?,
+      digest: from_hex(&digest.iter().map(|token| token.text.as_str()).collect::<String>())?,
+    },
+    (RecordType::RRSIG, [type_covered, algorithm, labels, original_ttl, expiration, inception, key_tag, signer_name, signature @ ..]) => RData::RRSIG {
+      type_covered: type_covered.text.parse()?,
+      algorithm: field(algorithm)?,
+      labels: field(labels)?,
+      original_ttl: field(original_ttl)?,
+      expiration: timestamp(expiration)?,
+      inception: timestamp(inception)?,
+      key_tag: field(key_tag)?,
+      signer_name: name(signer_name)?,
+      signature: base64(signature)?,
+    },
+    (RecordType::NSEC, [next_domain, rtypes @ ..]) => RData::NSEC { next_domain: name(next_domain)?, types: types(rtypes)? },
+    (RecordType::DNSKEY, [flags, protocol, algorithm, public_key @ ..]) => RData::DNSKEY {
+      flags: field(flags)?,
+      protocol: field(protocol)?,
+      algorithm: field(algorithm)?,
+      public_key: base64(public_key)?,
+    },
+    (RecordType::NSEC3, [hash_algorithm, flags, iterations, salt_field, next_hashed_owner, rtypes @ ..]) => RData::NSEC3 {
+      hash_algorithm: field(hash_algorithm)?,
+      flags: field(flags)?,
+      iterations: field(iterations)?,
+      salt: salt(salt_field)?,
+      next_hashed_owner: BASE32HEX_NOPAD
+        .decode(next_hashed_owner.text.to_ascii_uppercase().as_bytes())
+        .map_err(|_| DnsError::Syntax(format!("bad base32hex {:?}", next_hashed_owner.text)))?,
+      types: types(rtypes)?,
+    },
+    (RecordType::NSEC3PARAM, [hash_algorithm, flags, iterations, salt_field]) => RData::NSEC3PARAM {
+      hash_algorithm: field(hash_algorithm)?,
+      flags: field(flags)?,
+      iterations: field(iterations)?,
+      salt: salt(salt_field)?,
+    },
+    (RecordType::SVCB | RecordType::HTTPS, [priority, target, params @ ..]) => {
+      let (priority, target) = (field(priority)?, name(target)?);
+      let mut params = params.iter().map(svc_param).collect::<Result<Vec<_>, _>>()?;
+      params.sort_by_key(SvcParam::key);
+      match rtype {
+        RecordType::SVCB => RData::SVCB { priority, target, params },
+        _ => RData::HTTPS { priority, target, params },
+      }
+    }
+    (RecordType::TSIG, [algorithm, time_signed, fudge, rest @ ..]) => {
+      let (mac, rest) = sized_base64(rest)?;
+      let [original_id, error, rest @ ..] = rest else {
+        return Err(wrong_count());
+      };
+      let (other, rest) = sized_base64(rest)?;
+      if !rest.is_empty() {
+        return Err(wrong_count());
+      }
+      let time_signed: u64 = field(time_signed)?;
+      if time_signed >> 48 != 0 {
+        return Err(DnsError::Syntax(format!("time signed {time_signed} does not fit in 48 bits")));
+      }
+      RData::TSIG { algorithm: name(algorithm)?, time_signed, fudge: field(fudge)?, mac, original_id: field(original_id)?, error: tsig_error(error)?, other }
+    }
+    (
+      RecordType::A
+      | RecordType::AAAA
+      | RecordType::NS
+      | RecordType::CNAME
+      | RecordType::PTR
+      | RecordType::MX
+      | RecordType::TXT
+      | RecordType::SOA
+      | RecordType::SRV
+      | RecordType::CAA
+      | RecordType::DS
+      | RecordType::RRSIG
+      | RecordType::NSEC
+      | RecordType::DNSKEY
+      | RecordType::NSEC3
+      | RecordType::NSEC3PARAM
+      | RecordType::SVCB
+      | RecordType::HTTPS,
+      _,
+    ) => return Err(wrong_count()),
+    (rtype, _) => return Err(DnsError::Syntax(format!("no presentation format for {rtype}"))),
+  };
+  rdata.validate()?;
+  Ok(rdata)
+}
+
+impl RData {
+  /// Reads the rdata of a record of type `rtype` from its presentation format fields.
+  pub fn from_text(rtype: RecordType, s: &str) -> Result<Self, DnsError> {
+    parse_rdata(rtype, &tokenize(s)?)
+  }
+}
+
+/// Parses a single record as `<name> <ttl> [<class>] <type> <rdata>`.
+/// Names are always taken as fully qualified, and the class defaults to IN.
+impl FromStr for ResourceRecord {
+  type Err = DnsError;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let tokens = tokenize(s)?;
+    let [owner, ttl, rest @ ..] = &tokens[..] else {
+      return Err(DnsError::Syntax(format!("too few fields in {s:?}")));
+    };
+    let (class, rtype, fields) = match rest {
+      [class, rtype, fields @ ..] if class.text.parse::<DnsClass>().is_ok() && rtype.text.parse::<RecordType>().is_ok() => {
+        (class.text.parse()?, rtype.text.parse()?, fields)
+      }
+      [rtype, fields @ ..] => (DnsClass::IN, rtype.text.parse()?, fields),
+      [] => return Err(DnsError::Syntax(format!("no record type in {s:?}"))),
+    };
+    Ok(Self::new(name(owner)?, class, field(ttl)?, parse_rdata(rtype, fields)?))
+  }
+}
+
+#[test]
+fn test_record_text_round_trip() {
+  let records = [
+    "example.com.\t3600\tIN\tA\t93.184.216.34",
+    "example.com.\t3600\tIN\tAAAA\t2001:db8::1",
+    "www.example.com.\t300\tIN\tCNAME\texample.com.",
+    "example.com.\t3600\tIN\tMX\t10 mail.example.com.",
+    "example.com.\t3600\tIN\tTXT\t\"v=spf1 -all\" \"say \\\"hi\\\"\\\\\" \"\\007\"",
+    "example.com.\t3600\tIN\tSOA\tns1.example.com. hostmaster.example.com. 1 7200 3600 1209600 300",
+    "_sip._udp.example.com.\t3600\tIN\tSRV\t0 5 5060 sip.example.com.",
+    "example.com.\t3600\tIN\tCAA\t0 issue \"letsencrypt.org\"",
+  ];
+  for text in records {
+    let record: ResourceRecord = text.parse().unwrap();
+    assert_eq!(record.to_string(), text);
+  }
+  let record: ResourceRecord = "example.com. 3600 IN TXT \"v=spf1 -all\" \"say \\\"hi\\\"\\\\\" \"\\007\"".parse().unwrap();
+  assert_eq!(record.rdata, RData::TXT(vec![b"v=spf1 -all".to_vec(), b"say \"hi\"\\".to_vec(), vec![7]]));
+}
+
+#[test]
+fn test_record_from_str_variants() {
+  let soa: ResourceRecord = "example.com. 3600 SOA ns1.example.com. hostmaster.example.com. (
+      2024010101 ; serial
+      7200 3600 1209600 300 )"
+    .parse()
+    .unwrap();
+  assert_eq!(soa.class, DnsClass::IN);
+  assert_eq!(soa.rtype(), RecordType::SOA);
+  let txt: ResourceRecord = "example.com 60 CH TXT bare-word".parse().unwrap();
+  assert_eq!(txt.class, DnsClass::CH);
+  assert_eq!(txt.rdata, RData::TXT(vec![b"bare-word".to_vec()]));
+  assert!("example.com. 60 IN A".parse::<ResourceRecord>().is_err());
+  assert!("example.com. 60 IN A 1.2.3.4 5.6.7.8".parse::<ResourceRecord>().is_err());
+  assert!("example.com. sixty IN A 1.2.3.4".parse::<ResourceRecord>().is_err());
+  assert!("example.com. 60 IN TXT \"unterminated".parse::<ResourceRecord>().is_err());
+  assert!("example.com. 60 IN BOGUS 1".parse::<ResourceRecord>().is_err());
+  assert!("example.com. 60 IN CAA 0 \"\" \"letsencrypt.org\"".parse::<ResourceRecord>().is_err());
+  assert!(format!("example.com. 60 IN TXT {}", "a".repeat(256)).parse::<ResourceRecord>().is_err());
+  let mac = BASE64.encode(&[0; 65536]);
+  assert!(format!("example. 0 ANY TSIG hmac-sha256. 0 300 65536 {mac} 0 NOERROR 0").parse::<ResourceRecord>().is_err());
+  let mx: ResourceRecord = "bücher.example. 60 IN MX 10 mail.bücher.example.".parse().unwrap();
+  assert_eq!(mx.to_string(), "xn--bcher-kva.example.\t60\tIN\tMX\t10 mail.xn--bcher-kva.example.");
+  assert_eq!(format!("{mx:#}"), "bücher.example.\t60\tIN\tMX\t10 mail.bücher.example.");
+}
+
+#[test]
+fn test_message_display() {
+  let mut message = Message::new();
+  message.set_id(0x1234);
+  message.set_response();
+  message.set_rd(true);
+  message.set_ra(true);
+  message.add_question(&Question::new("example.com".parse().unwrap(), RecordType::A, DnsClass::IN)).unwrap();
+  message.add_answer(&"example.com. 3600 IN A 93.184.216.34".parse().unwrap()).unwrap();
+  message.add_additional(&Edns { dnssec_ok: true, ..Edns::default() }.to_record()).unwrap();
+  assert_eq!(
+    message.to_string(),
+    ";; ->>HEADER<<- opcode: QUERY, status: NOERROR, id: 4660
+;; flags: qr rd ra; QUERY: 1, ANSWER: 1, AUTHORITY: 0, ADDITIONAL: 1
+
+;; OPT PSEUDOSECTION:
+; EDNS: version: 0, flags: do; udp: 4096
+
+;; QUESTION SECTION:
+;example.com.\t\tIN\tA
+
+;; ANSWER SECTION:
+example.com.\t3600\tIN\tA\t93.184.216.34"
+  );
+  let truncated = Message::from(&message[..40]);
+  assert!(truncated.to_string().starts_with(";; malformed message"));
+  let options = [
+    EdnsOption::Cookie { client: [1; 8], server: vec![] },
+    EdnsOption::ClientSubnet(crate::edns::ClientSubnet { scope_prefix: 16, ..crate::edns::ClientSubnet::new("2001:db8::1".parse().unwrap(), 48) }),
+    EdnsOption::ExtendedError { info_code: 6, extra_text: "signature expired".to_string() },
+    EdnsOption::ExtendedError { info_code: 300, extra_text: String::new() },
+    EdnsOption::Unknown { code: 65001, data: vec![0xab] },
+  ];
+  let text: Vec<_> = options.iter().map(EdnsOption::to_string).collect();
+  assert_eq!(text, ["COOKIE: 0101010101010101", "CLIENT-SUBNET: 2001:db8::/48/16", "EDE: 6 (DNSSEC Bogus): (signature expired)", "EDE: 300", "OPT=65001: AB"]);
+}
+
+#[test]
+fn test_generic_rdata() {
+  let record: ResourceRecord = "example.com. 60 IN TYPE65534 \\# 4 0A000001".parse().unwrap();
+  assert_eq!(record.rdata, RData::Unknown { rtype: RecordType::Unknown(65534), data: vec![10, 0, 0, 1] });
+  assert_eq!(record.to_string(), "example.com.\t60\tIN\tTYPE65534\t\\# 4 0A000001");
+  let empty: ResourceRecord = "example.com. 60 IN TYPE65534 \\# 0".parse().unwrap();
+  assert_eq!(empty.to_string(), "example.com.\t60\tIN\tTYPE65534\t\\# 0");
+  // Known types may be written generically too, and hex may be split into fields.
+  let a: ResourceRecord = "example.com. 60 IN A \\# 4 0A00 0001".parse().unwrap();
+  assert_eq!(a.rdata, RData::A("10.0.0.1".parse().unwrap()));
+  let mx = RData::from_text(RecordType::MX, "\\# 6 000a 02 6d78 00").unwrap();
+  assert_eq!(mx, RData::MX { preference: 10, exchange: "mx".parse().unwrap() });
+  assert!(RData::from_text(RecordType::Unknown(65534), "\\# 3 0A000001").is_err());
+  assert!(RData::from_text(RecordType::A, "\\# 3 0A0000").is_err());
+  assert!(RData::from_text(RecordType::Unknown(65534), "\\# 1 0G").is_err());
+}
+
+#[test]
+fn test_svcb_text() {
+  let records = [
+    "example.com.\t300\tIN\tHTTPS\t1 . alpn=h2,h3 ipv4hint=192.0.2.1,192.0.2.2 ech=AEX+DQBB ipv6hint=2001:db8::1",
+    "example.com.\t300\tIN\tHTTPS\t0 svc.example.net.",
+    "_8443._foo.api.example.com.\t300\tIN\tSVCB\t16 foo.example.org. mandatory=alpn,ipv4hint alpn=h2,h3-19 ipv4hint=192.0.2.1",
+    "example.com.\t300\tIN\tSVCB\t1 foo.example.org. alpn=f\\\\oo\\,bar,h2 no-default-alpn port=53 key667=hello key668",
+  ];
+  for text in records {
+    let record: ResourceRecord = text.parse().unwrap();
+    assert_eq!(record.to_string(), text);
+  }
+  // Keys may be written in any order, and values quoted.
+  let rdata = RData::from_text(RecordType::SVCB, "1 foo.example.org. port=53 mandatory=port,alpn ALPN=\"h2\"").unwrap();
+  assert_eq!(rdata.to_string(), "1 foo.example.org. mandatory=alpn,port alpn=h2 port=53");
+  let RData::SVCB { params, .. } = RData::from_text(RecordType::SVCB, "1 . alpn=f\\\\oo\\,bar,h2").unwrap() else {
+    unreachable!()
+  };
+  assert_eq!(params, vec![SvcParam::Alpn(vec![b"f\\oo,bar".to_vec(), b"h2".to_vec()])]);
+  for bad in [
+    "1 . port=53 port=54",
+    "1 . mandatory=port",
+    "1 . mandatory=mandatory,alpn alpn=h2",
+    "1 . no-default-alpn",
+    "1 . no-default-alpn=x alpn=h2",
+    "1 . port=http",
+    "1 . ipv4hint=",
+    "1 . ipv6hint=192.0.2.1",
+    "1 . ech=!!",
+    "1 . bogus=1",
+    "1",
+  ] {
+    assert!(RData::from_text(RecordType::HTTPS, bad).is_err(), "{bad}");
+  }
+}
+
+#[test]
+fn test_dnssec_text() {
+  // The examples of RFC 4034 and RFC 5155.
+  let dnskey: ResourceRecord = "dskey.example.com. 86400 IN DNSKEY 256 3 5 ( AQOeiiR0GOMYkDshWoSKz9Xz
+    fwJr1AYtsmx3TGkJaNXVbfi/ 2pHm822aJ5iI9BMzNXxeYCmZ DRD99WYwYqUSdjMmmAphXdvx egXd/M5+X7OrzKBaMbCVdFLU
+    Uh6DhweJBjEVv5f2wwjM9Xzc nOf+EPbtG9DMBmADjFDc2w/r ljwvFw== ) ; key id = 60485"
+    .parse()
+    .unwrap();
+  assert_eq!(dnskey.rdata.key_tag(), Some(60485));
+  let ds: ResourceRecord = "dskey.example.com. 86400 IN DS 60485 5 1 ( 2BB183AF5F22588179A53B0A 98631FAD1A292118 )".parse().unwrap();
+  assert_eq!(ds.to_string(), "dskey.example.com.\t86400\tIN\tDS\t60485 5 1 2BB183AF5F22588179A53B0A98631FAD1A292118");
+  let rrsig: ResourceRecord = "host.example.com. 86400 IN RRSIG A 5 3 86400 20030322173103 ( 20030220173103 2642 example.com.
+    oJB1W6WNGv+ldvQ3WDG0MQkg5IEhjRip8WTr PYGv07h108dUKGMeDPKijVCHX3DDKdfb+v6o B9wfuh3DTJXUAfI/M0zmO/zz8bW0Rznl8O3t
+    GNazPwQKkRN20XPXV6nwwfoXmJQbsLNrLfkG J5D6fwFm8nN+6pBzeDQfsS3Ap3o= )"
+    .parse()
+    .unwrap();
+  let RData::RRSIG { type_covered, expiration, signer_name, .. } = &rrsig.rdata else {
+    unreachable!()
+  };
+  assert_eq!((*type_covered, *expiration), (RecordType::A, 1048354263));
+  assert_eq!(signer_name, &"example.com".parse::<Name>().unwrap());
+  let records = [
+    dnskey.to_string(),
+    rrsig.to_string(),
+    "alfa.example.com.\t86400\tIN\tNSEC\thost.example.com. A MX RRSIG NSEC TYPE1234".to_string(),
+    "0p9mhaveqvm6t7vbl5lop2u3t2rp3tom.example.\t3600\tIN\tNSEC3\t1 1 12 AABBCCDD 2t7b4g4vsa5smi47k61mv5bv1a22bojr NS SOA MX RRSIG DNSKEY NSEC3PARAM".to_string(),
+    "example.\t3600\tIN\tNSEC3PARAM\t1 0 12 -".to_string(),
+    "example.\t0\tIN\tRRSIG\tSOA 13 1 3600 21060207062815 19700101000000 1 example. AAAA".to_string(),
+  ];
+  for text in records {
+    let record: ResourceRecord = text.parse().unwrap();
+    assert_eq!(record.to_string(), text);
+  }
+  // Types may be given in any order, and times as plain seconds.
+  assert_eq!(RData::from_text(RecordType::NSEC, "a.example. MX A MX").unwrap().to_string(), "a.example. A MX");
+  let rrsig = RData::from_text(RecordType::RRSIG, "A 13 2 60 4294967295 0 1 example. AAAA").unwrap();
+  assert!(rrsig.to_string().starts_with("A 13 2 60 21060207062815 19700101000000 "));
+  for (rtype, bad) in [
+    (RecordType::DS, "1 8 2"),
+    (RecordType::DS, "1 8 2 XYZ"),
+    (RecordType::RRSIG, "A 13 2 60 20031322173103 0 1 example. AAAA"),
+    (RecordType::RRSIG, "A 13 2 60 0 0 1 example. !"),
+    (RecordType::NSEC, "a.example. BOGUS"),
+    (RecordType::NSEC3, "1 0 0 - 2t7b4g4vsa5smi47k61mv5bv1a22boj! A"),
+    (RecordType::NSEC3PARAM, "1 0 0 ABC"),
+  ] {
+    assert!(RData::from_text(rtype, bad).is_err(), "{bad}");
+  }
+}
+
+#[test]
+fn test_tsig_text() {
+  let text = "test.key.\t0\tANY\tTSIG\thmac-sha256. 1700000000 300 32 +Vj6t0r8fdPi8I8EG+PEQC2gBQsdXYTvaRfwx2abkgM= 4660 NOERROR 0";
+  let record: ResourceRecord = text.parse().unwrap();
+  assert_eq!(record.to_string(), text);
+  let rdata = RData::from_text(RecordType::TSIG, "hmac-sha256. 1700000000 300 0 4660 BADTIME 6 AABq1H8c").unwrap();
+  let RData::TSIG { mac, error, other, .. } = &rdata else {
+    unreachable!()
+  };
+  assert_eq!((mac.len(), *error, other.len()), (0, 18, 6));
+  for bad in ["hmac-sha256. 281474976710656 300 0 1 NOERROR 0", "hmac-sha256. 1 300 2 AAAA 1 NOERROR 0", "hmac-sha256. 1 300 0 1 BOGUS 0"] {
+    assert!(RData::from_text(RecordType::TSIG, bad).is_err(), "{bad}");
+  }
+}
@@ -0,0 +1,312 @@
+#![allow(dead_code)]
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use crate::{edns::EdnsOption, error::DnsError, message::encode_rdata, name::Name, svcb::{self, SvcParam}};
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RecordType {
+  A,
+  NS,
+  CNAME,
+  SOA,
+  PTR,
+  MX,
+  TXT,
+  AAAA,
+  SRV,
+  OPT,
+  DS,
+  RRSIG,
+  NSEC,
+  DNSKEY,
+  NSEC3,
+  NSEC3PARAM,
+  SVCB,
+  HTTPS,
+  TSIG,
+  ANY,
+  CAA,
+  Unknown(u16),
+}
+
+impl From<u16> for RecordType {
+  fn from(value: u16) -> Self {
+    match value {
+      1 => Self::A,
+      2 => Self::NS,
+      5 => Self::CNAME,
+      6 => Self::SOA,
+      12 => Self::PTR,
+      15 => Self::MX,
+      16 => Self::TXT,
+      28 => Self::AAAA,
+      33 => Self::SRV,
+      41 => Self::OPT,
+      43 => Self::DS,
+      46 => Self::RRSIG,
+      47 => Self::NSEC,
+      48 => Self::DNSKEY,
+      50 => Self::NSEC3,
+      51 => Self::NSEC3PARAM,
+      64 => Self::SVCB,
+      65 => Self::HTTPS,
+      250 => Self::TSIG,
+      255 => Self::ANY,
+      257 => Self::CAA,
+      other => Self::Unknown(other),
+    }
+  }
+}
+
+impl From<RecordType> for u16 {
+  fn from(value: RecordType) -> Self {
+    match value {
+      RecordType::A => 1,
+      RecordType::NS => 2,
+      RecordType::CNAME => 5,
+      RecordType::SOA => 6,
+      RecordType::PTR => 12,
+      RecordType::MX => 15,
+      RecordType::TXT => 16,
+      RecordType::AAAA => 28,
+      RecordType::SRV => 33,
+      RecordType::OPT => 41,
+      RecordType::DS => 43,
+      RecordType::RRSIG => 46,
+      RecordType::NSEC => 47,
+      RecordType::DNSKEY => 48,
+      RecordType::NSEC3 => 50,
+      RecordType::NSEC3PARAM => 51,
+      RecordType::SVCB => 64,
+      RecordType::HTTPS => 65,
+      RecordType::TSIG => 250,
+      RecordType::ANY => 255,
+      RecordType::CAA => 257,
+      RecordType::Unknown(other) => other,
+    }
+  }
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DnsClass {
+  IN,
+  CH,
+  HS,
+  NONE,
+  ANY,
+  Unknown(u16),
+}
+
+impl From<u16> for DnsClass {
+  fn from(value: u16) -> Self {
+    match value {
+      1 => Self::IN,
+      3 => Self::CH,
+      4 => Self::HS,
+      254 => Self::NONE,
+      255 => Self::ANY,
+      other => Self::Unknown(other),
+    }
+  }
+}
+
+impl From<DnsClass> for u16 {
+  fn from(value: DnsClass) -> Self {
+    match value {
+      DnsClass::IN => 1,
+      DnsClass::CH => 3,
+      DnsClass::HS => 4,
+      DnsClass::NONE => 254,
+      DnsClass::ANY => 255,
+      DnsClass::Unknown(other) => other,
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Question {
+  pub name: Name,
+  pub qtype: RecordType,
+  pub qclass: DnsClass,
+}
+
+impl Question {
+  pub fn new(name: Name, qtype: RecordType, qclass: DnsClass) -> Self {
+    Self { name, qtype, qclass }
+  }
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RData {
+  A(Ipv4Addr),
+  AAAA(Ipv6Addr),
+  NS(Name),
+  CNAME(Name),
+  PTR(Name),
+  MX {
+    preference: u16,
+    exchange: Name,
+  },
+  /// One or more `<character-string>`s.
+  TXT(Vec<Vec<u8>>),
+  SOA {
+    mname: Name,
+    rname: Name,
+    serial: u32,
+    refresh: u32,
+    retry: u32,
+    expire: u32,
+    minimum: u32,
+  },
+  SRV {
+    priority: u16,
+    weight: u16,
+    port: u16,
+    target: Name,
+  },
+  CAA {
+    flags: u8,
+    tag: Vec<u8>,
+    value: Vec<u8>,
+  },
+  OPT(Vec<EdnsOption>),
+  /// Delegation signer: the digest of a DNSKEY of the child zone, held by the parent.
+  DS {
+    key_tag: u16,
+    algorithm: u8,
+    digest_type: u8,
+    digest: Vec<u8>,
+  },
+  /// A signature over the RRset of `type_covered` at the owner name. The times are
+  /// seconds since the epoch, compared in serial number arithmetic.
+  RRSIG {
+    type_covered: RecordType,
+    algorithm: u8,
+    labels: u8,
+    original_ttl: u32,
+    expiration: u32,
+    inception: u32,
+    key_tag: u16,
+    signer_name: Name,
+    signature: Vec<u8>,
+  },
+  /// The next owner name in the zone, and the types present at this one. `types`
+  /// are kept in ascending order without repeats, as the bitmap holds them.
+  NSEC {
+    next_domain: Name,
+    types: Vec<RecordType>,
+  },
+  /// A zone's public key.
+  DNSKEY {
+    flags: u16,
+    protocol: u8,
+    algorithm: u8,
+    public_key: Vec<u8>,
+  },
+  /// NSEC over hashed owner names (RFC 5155), so the zone cannot be walked.
+  NSEC3 {
+    hash_algorithm: u8,
+    flags: u8,
+    iterations: u16,
+    salt: Vec<u8>,
+    next_hashed_owner: Vec<u8>,
+    types: Vec<RecordType>,
+  },
+  /// The hash parameters a zone's NSEC3 records use.
+  NSEC3PARAM {
+    hash_algorithm: u8,
+    flags: u8,
+    iterations: u16,
+    salt: Vec<u8>,
+  },
+  /// Service binding (RFC 9460). Priority 0 is AliasMode, where `target` is an
+  /// alias for the owner and `params` should be empty.
+  SVCB {
+    priority: u16,
+    target: Name,
+    params: Vec<SvcParam>,
+  },
+  /// SVCB for HTTPS origins, with the same rdata.
+  HTTPS {
+    priority: u16,
+    target: Name,
+    params: Vec<SvcParam>,
+  },
+  /// A transaction signature (RFC 8945), the last record of a signed message.
+  /// `time_signed` is 48 bits of seconds since the epoch.
+  TSIG {
+    algorithm: Name,
+    time_signed: u64,
+    fudge: u16,
+    mac: Vec<u8>,
+    original_id: u16,
+    error: u16,
+    other: Vec<u8>,
+  },
+  /// Any record whose rdata we do not decode, kept as the raw bytes from the wire.
+  Unknown { rtype: RecordType, data: Vec<u8> },
+}
+
+impl RData {
+  pub fn record_type(&self) -> RecordType {
+    match self {
+      RData::A(_) => RecordType::A,
+      RData::AAAA(_) => RecordType::AAAA,
+      RData::NS(_) => RecordType::NS,
+      RData::CNAME(_) => RecordType::CNAME,
+      RData::PTR(_) => RecordType::PTR,
+      RData::MX { .. } => RecordType::MX,
+      RData::TXT(_) => RecordType::TXT,
+      RData::SOA { .. } => RecordType::SOA,
+      RData::SRV { .. } => RecordType::SRV,
+      RData::CAA { .. } => RecordType::CAA,
+      RData::OPT(_) => RecordType::OPT,
+      RData::DS { .. } => RecordType::DS,
+      RData::RRSIG { .. } => RecordType::RRSIG,
+      RData::NSEC { .. } => RecordType::NSEC,
+      RData::DNSKEY { .. } => RecordType::DNSKEY,
+      RData::NSEC3 { .. } => RecordType::NSEC3,
+      RData::NSEC3PARAM { .. } => RecordType::NSEC3PARAM,
+      RData::SVCB { .. } => RecordType::SVCB,
+      RData::HTTPS { .. } => RecordType::HTTPS,
+      RData::TSIG { .. } => RecordType::TSIG,
+      RData::Unknown { rtype, .. } => *rtype,
+    }
+  }
+
+  /// Checks that a CAA tag is letters and digits (RFC 8659 section 4.1), that the
+  /// SvcParams of SVCB and HTTPS records are consistent, and that the rdata can be
+  /// written out: every field fits the length byte or word in front of it on the
+  /// wire, as does the rdata as a whole. Records read off the wire are not held
+  /// to the first two, and are passed on as they came.
+  pub fn validate(&self) -> Result<(), DnsError> {
+    match self {
+      RData::CAA { tag, .. } if tag.is_empty() || !tag.iter().all(u8::is_ascii_alphanumeric) => {
+        return Err(DnsError::BadRdata(RecordType::CAA));
+      }
+      RData::SVCB { params, .. } | RData::HTTPS { params, .. } => svcb::validate(params)?,
+      _ => {}
+    }
+    encode_rdata(self).map(|_| ())
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResourceRecord {
+  pub name: Name,
+  pub class: DnsClass,
+  pub ttl: u32,
+  pub rdata: RData,
+}
+
+impl ResourceRecord {
+  pub fn new(name: Name, class: DnsClass, ttl: u32, rdata: RData) -> Self {
+    Self { name, class, ttl, rdata }
+  }
+  pub fn rtype(&self) -> RecordType {
+    self.rdata.record_type()
+  }
+}
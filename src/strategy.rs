@@ -0,0 +1,322 @@
+//! proptest strategies for random names, records and messages, and the
+//! round-trip properties every encoder and decoder pair has to satisfy.
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use proptest::{collection::vec, option, prelude::*, sample::select};
+use crate::{
+  builder::MessageBuilder,
+  edns::{ClientSubnet, Edns, EdnsOption, CLIENT_SUBNET, COOKIE, EXTENDED_ERROR},
+  header::Flags,
+  message::Message,
+  message_ref::MessageRef,
+  name::{Name, MAX_LABEL_LENGTH},
+  record::{DnsClass, Question, RData, RecordType, ResourceRecord},
+  svcb::SvcParam,
+};
+
+/// Mostly a few common labels, so that names share suffixes and get compressed,
+/// with the odd label of arbitrary bytes.
+pub fn label() -> impl Strategy<Value = Vec<u8>> {
+  prop_oneof![
+    4 => select(&["example", "com", "www", "Mail", "a", "xn--bcher-kva"][..]).prop_map(|label| label.as_bytes().to_vec()),
+    1 => vec(any::<u8>(), 1..=MAX_LABEL_LENGTH),
+  ]
+}
+
+pub fn name() -> impl Strategy<Value = Name> {
+  vec(label(), 0..5).prop_filter_map("name longer than 255 bytes", |labels| Name::from_labels(labels).ok())
+}
+
+pub fn question() -> impl Strategy<Value = Question> {
+  (name(), any::<u16>(), any::<u16>()).prop_map(|(name, qtype, qclass)| Question::new(name, qtype.into(), qclass.into()))
+}
+
+fn unknown_type() -> impl Strategy<Value = RecordType> {
+  any::<u16>().prop_map(RecordType::from).prop_filter("known record type", |rtype| matches!(rtype, RecordType::Unknown(_)))
+}
+
+/// Self-consistent SvcParams, in key order.
+pub fn svc_params() -> impl Strategy<Value = Vec<SvcParam>> {
+  (
+    option::of(vec(vec(any::<u8>(), 1..8), 1..3)),
+    any::<bool>(),
+    option::of(any::<u16>()),
+    option::of(vec(any::<[u8; 4]>(), 1..3)),
+    option::of(vec(any::<u8>(), 0..32)),
+    option::of(vec(any::<[u8; 16]>(), 1..3)),
+    option::of((7..=u16::MAX, vec(any::<u8>(), 0..16))),
+    any::<bool>(),
+  )
+    .prop_map(|(alpn, no_default_alpn, port, ipv4hint, ech, ipv6hint, unknown, mandatory)| {
+      let mut params = Vec::new();
+      if let Some(ids) = alpn {
+        params.push(SvcParam::Alpn(ids));
+        if no_default_alpn {
+          params.push(SvcParam::NoDefaultAlpn);
+        }
+      }
+      params.extend(port.map(SvcParam::Port));
+      params.extend(ipv4hint.map(|ips| SvcParam::Ipv4Hint(ips.into_iter().map(Ipv4Addr::from).collect())));
+      params.extend(ech.map(SvcParam::Ech));
+      params.extend(ipv6hint.map(|ips| SvcParam::Ipv6Hint(ips.into_iter().map(Ipv6Addr::from).collect())));
+      params.extend(unknown.map(|(key, value)| SvcParam::Unknown { key, value }));
+      if mandatory && !params.is_empty() {
+        params.insert(0, SvcParam::Mandatory(params.iter().map(SvcParam::key).collect()));
+      }
+      params
+    })
+}
+
+/// The types of an NSEC or NSEC3 bitmap, in ascending order without repeats.
+fn types() -> impl Strategy<Value = Vec<RecordType>> {
+  vec(prop_oneof![1..=300u16, any::<u16>()], 0..6).prop_map(|mut types| {
+    types.sort_unstable();
+    types.dedup();
+    types.into_iter().map(RecordType::from).collect()
+  })
+}
+
+/// The rdata of the DNSSEC types.
+pub fn dnssec_rdata() -> impl Strategy<Value = RData> {
+  prop_oneof![
+    (any::<u16>(), any::<u8>(), any::<u8>(), vec(any::<u8>(), 1..48)).prop_map(|(key_tag, algorithm, digest_type, digest)| RData::DS {
+      key_tag,
+      algorithm,
+      digest_type,
+      digest,
+    }),
+    (any::<u16>(), any::<[u8; 2]>(), any::<[u32; 3]>(), any::<u16>(), name(), vec(any::<u8>(), 1..64)).prop_map(
+      |(type_covered, [algorithm, labels], [original_ttl, expiration, inception], key_tag, signer_name, signature)| RData::RRSIG {
+        type_covered: type_covered.into(),
+        algorithm,
+        labels,
+        original_ttl,
+        expiration,
+        inception,
+        key_tag,
+        signer_name,
+        signature,
+      }
+    ),
+    (name(), types()).prop_map(|(next_domain, types)| RData::NSEC { next_domain, types }),
+    (any::<u16>(), any::<u8>(), any::<u8>(), vec(any::<u8>(), 1..64)).prop_map(|(flags, protocol, algorithm, public_key)| RData::DNSKEY {
+      flags,
+      protocol,
+      algorithm,
+      public_key,
+    }),
+    (any::<[u8; 2]>(), any::<u16>(), vec(any::<u8>(), 0..8), vec(any::<u8>(), 1..32), types()).prop_map(
+      |([hash_algorithm, flags], iterations, salt, next_hashed_owner, types)| RData::NSEC3 { hash_algorithm, flags, iterations, salt, next_hashed_owner, types }
+    ),
+    (any::<[u8; 2]>(), any::<u16>(), vec(any::<u8>(), 0..8)).prop_map(|([hash_algorithm, flags], iterations, salt)| RData::NSEC3PARAM {
+      hash_algorithm,
+      flags,
+      iterations,
+      salt,
+    }),
+  ]
+}
+
+/// TSIG rdata, which only belongs at the end of a message, with its time in 48 bits.
+pub fn tsig_rdata() -> impl Strategy<Value = RData> {
+  (name(), 0..1u64 << 48, any::<[u16; 3]>(), vec(any::<u8>(), 0..64), vec(any::<u8>(), 0..8)).prop_map(
+    |(algorithm, time_signed, [fudge, original_id, error], mac, other)| RData::TSIG { algorithm, time_signed, fudge, mac, original_id, error, other }
+  )
+}
+
+/// Any rdata but OPT and TSIG, which have their own places in a message.
+pub fn rdata() -> impl Strategy<Value = RData> {
+  prop_oneof![
+    any::<[u8; 4]>().prop_map(|ip| RData::A(Ipv4Addr::from(ip))),
+    any::<[u8; 16]>().prop_map(|ip| RData::AAAA(Ipv6Addr::from(ip))),
+    name().prop_map(RData::NS),
+    name().prop_map(RData::CNAME),
+    name().prop_map(RData::PTR),
+    (any::<u16>(), name()).prop_map(|(preference, exchange)| RData::MX { preference, exchange }),
+    vec(vec(any::<u8>(), 0..=255), 1..4).prop_map(RData::TXT),
+    (name(), name(), any::<[u32; 5]>()).prop_map(|(mname, rname, [serial, refresh, retry, expire, minimum])| RData::SOA {
+      mname,
+      rname,
+      serial,
+      refresh,
+      retry,
+      expire,
+      minimum,
+    }),
+    (any::<u16>(), any::<u16>(), any::<u16>(), name()).prop_map(|(priority, weight, port, target)| RData::SRV { priority, weight, port, target }),
+    (any::<u8>(), vec(any::<u8>(), 0..=255), vec(any::<u8>(), 0..64)).prop_map(|(flags, tag, value)| RData::CAA { flags, tag, value }),
+    dnssec_rdata(),
+    (any::<bool>(), any::<u16>(), name(), svc_params()).prop_map(|(https, priority, target, params)| match https {
+      true => RData::HTTPS { priority, target, params },
+      false => RData::SVCB { priority, target, params },
+    }),
+    (unknown_type(), vec(any::<u8>(), 0..64)).prop_map(|(rtype, data)| RData::Unknown { rtype, data }),
+  ]
+}
+
+pub fn record() -> impl Strategy<Value = ResourceRecord> {
+  (name(), any::<u16>(), any::<u32>(), rdata()).prop_map(|(name, class, ttl, rdata)| ResourceRecord::new(name, DnsClass::from(class), ttl, rdata))
+}
+
+pub fn edns_option() -> impl Strategy<Value = EdnsOption> {
+  prop_oneof![
+    (prop_oneof![any::<[u8; 4]>().prop_map(IpAddr::from), any::<[u8; 16]>().prop_map(IpAddr::from)], any::<u8>(), any::<u8>()).prop_map(
+      |(address, source_prefix, scope_prefix)| {
+        let subnet = ClientSubnet::new(address, source_prefix);
+        EdnsOption::ClientSubnet(ClientSubnet { scope_prefix: scope_prefix.min(if address.is_ipv4() { 32 } else { 128 }), ..subnet })
+      }
+    ),
+    (any::<[u8; 8]>(), prop_oneof![Just(Vec::new()), vec(any::<u8>(), 8..=32)]).prop_map(|(client, server)| EdnsOption::Cookie { client, server }),
+    (any::<u16>(), ".{0,16}").prop_map(|(info_code, extra_text)| EdnsOption::ExtendedError { info_code, extra_text }),
+    (any::<u16>().prop_filter("typed option", |code| ![CLIENT_SUBNET, COOKIE, EXTENDED_ERROR].contains(code)), vec(any::<u8>(), 0..16)).prop_map(|(code, data)| EdnsOption::Unknown { code, data }),
+  ]
+}
+
+/// EDNS parameters as a response carries them, with the extended rcode left to the builder.
+pub fn edns() -> impl Strategy<Value = Edns> {
+  (any::<u16>(), any::<u8>(), any::<bool>(), vec(edns_option(), 0..3)).prop_map(
+    |(udp_payload_size, version, dnssec_ok, options)| Edns {
+      udp_payload_size,
+      extended_rcode: 0,
+      version,
+      dnssec_ok,
+      options,
+    },
+  )
+}
+
+/// Everything a message is built from.
+#[derive(Debug, Clone)]
+pub struct Parts {
+  pub id: u16,
+  pub flags: Flags,
+  pub questions: Vec<Question>,
+  pub answers: Vec<ResourceRecord>,
+  pub authorities: Vec<ResourceRecord>,
+  pub additionals: Vec<ResourceRecord>,
+  pub edns: Option<Edns>,
+  pub compression: bool,
+}
+
+impl Parts {
+  pub fn build(&self) -> Result<Message, crate::error::DnsError> {
+    let builder = self.questions.iter().cloned().fold(MessageBuilder::new(self.id).flags(self.flags), MessageBuilder::question);
+    builder
+      .answers(self.answers.clone())
+      .authorities(self.authorities.clone())
+      .additionals(self.additionals.clone())
+      .edns(self.edns.clone())
+      .compression(self.compression)
+      .build()
+  }
+
+  /// The parts of `message`, as they decode.
+  pub fn decode(message: &Message, compression: bool) -> Result<Self, crate::error::DnsError> {
+    Ok(Self {
+      id: message.id(),
+      flags: message.flags(),
+      questions: message.questions()?,
+      answers: message.answers()?,
+      authorities: message.authorities()?,
+      additionals: message.additionals()?.into_iter().filter(|record| record.rtype() != RecordType::OPT).collect(),
+      edns: message.edns()?,
+      compression,
+    })
+  }
+}
+
+pub fn parts() -> impl Strategy<Value = Parts> {
+  (
+    any::<u16>(),
+    any::<u16>(),
+    vec(question(), 0..3),
+    [vec(record(), 0..4), vec(record(), 0..3), vec(record(), 0..3)],
+    option::of(edns()),
+    any::<bool>(),
+  )
+    .prop_map(|(id, flags, questions, [answers, authorities, additionals], edns, compression)| Parts {
+      id,
+      flags: Flags::from(flags),
+      questions,
+      answers,
+      authorities,
+      additionals,
+      edns,
+      compression,
+    })
+}
+
+/// Every way of reading a message, none of which may panic whatever the input.
+fn read_everything(data: &[u8]) {
+  let _ = Message::format_error(data);
+  let Ok(view) = MessageRef::parse(data) else {
+    return;
+  };
+  let _ = view.validate();
+  let _ = view.edns();
+  for question in view.questions().flatten() {
+    let _ = question.to_question().to_string();
+  }
+  for record in view.records().flatten() {
+    let _ = record.to_record().map(|record| format!("{record} {record:#}"));
+  }
+  let _ = view.to_string();
+}
+
+proptest! {
+  #[test]
+  fn test_message_round_trip(parts in parts()) {
+    let message = parts.build()?;
+    prop_assert!(message.validate().is_ok());
+    let decoded = Parts::decode(&Message::parse(&message)?, parts.compression)?;
+    prop_assert_eq!(decoded.id, parts.id);
+    prop_assert_eq!(decoded.flags, parts.flags);
+    prop_assert_eq!(&decoded.questions, &parts.questions);
+    prop_assert_eq!(&decoded.answers, &parts.answers);
+    prop_assert_eq!(&decoded.authorities, &parts.authorities);
+    prop_assert_eq!(&decoded.additionals, &parts.additionals);
+    prop_assert_eq!(&decoded.edns, &parts.edns);
+    // Names compare ignoring case, so check the bytes too.
+    prop_assert_eq!(&decoded.build()?[..], &message[..]);
+  }
+
+  #[test]
+  fn test_compression_only_shrinks(parts in parts()) {
+    let compressed = Parts { compression: true, ..parts.clone() }.build()?;
+    let uncompressed = Parts { compression: false, ..parts }.build()?;
+    prop_assert!(compressed.len() <= uncompressed.len());
+    let compressed = Parts::decode(&compressed, false)?;
+    prop_assert_eq!(&compressed.build()?[..], &uncompressed[..]);
+  }
+
+  #[test]
+  fn test_dnssec_text_round_trip(rdata in dnssec_rdata()) {
+    prop_assert_eq!(RData::from_text(rdata.record_type(), &rdata.to_string())?, rdata);
+  }
+
+  #[test]
+  fn test_tsig_text_round_trip(rdata in tsig_rdata()) {
+    prop_assert_eq!(RData::from_text(RecordType::TSIG, &rdata.to_string())?, rdata);
+  }
+
+  #[test]
+  fn test_svcb_text_round_trip(priority in any::<u16>(), target in name(), params in svc_params()) {
+    let rdata = RData::HTTPS { priority, target, params };
+    prop_assert_eq!(RData::from_text(RecordType::HTTPS, &rdata.to_string())?, rdata);
+  }
+
+  #[test]
+  fn test_read_arbitrary_bytes(data in vec(any::<u8>(), 0..512)) {
+    read_everything(&data);
+  }
+
+  #[test]
+  fn test_read_corrupted_message(parts in parts(), corruptions in vec((any::<prop::sample::Index>(), any::<u8>()), 1..4)) {
+    let mut data = parts.build()?.to_vec();
+    for (index, byte) in corruptions {
+      let index = index.index(data.len());
+      data[index] = byte;
+    }
+    read_everything(&data);
+  }
+}
@@ -0,0 +1,114 @@
+#![allow(dead_code)]
+//! The SvcParams of SVCB and HTTPS records (RFC 9460 section 7).
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use crate::error::DnsError;
+
+pub const MANDATORY: u16 = 0;
+pub const ALPN: u16 = 1;
+pub const NO_DEFAULT_ALPN: u16 = 2;
+pub const PORT: u16 = 3;
+pub const IPV4HINT: u16 = 4;
+pub const ECH: u16 = 5;
+pub const IPV6HINT: u16 = 6;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SvcParam {
+  /// Keys a client has to understand to use the record.
+  Mandatory(Vec<u16>),
+  /// ALPN protocol IDs, such as `h2` and `h3`.
+  Alpn(Vec<Vec<u8>>),
+  NoDefaultAlpn,
+  Port(u16),
+  Ipv4Hint(Vec<Ipv4Addr>),
+  /// An ECHConfigList.
+  Ech(Vec<u8>),
+  Ipv6Hint(Vec<Ipv6Addr>),
+  Unknown { key: u16, value: Vec<u8> },
+}
+
+impl SvcParam {
+  pub fn key(&self) -> u16 {
+    match self {
+      SvcParam::Mandatory(_) => MANDATORY,
+      SvcParam::Alpn(_) => ALPN,
+      SvcParam::NoDefaultAlpn => NO_DEFAULT_ALPN,
+      SvcParam::Port(_) => PORT,
+      SvcParam::Ipv4Hint(_) => IPV4HINT,
+      SvcParam::Ech(_) => ECH,
+      SvcParam::Ipv6Hint(_) => IPV6HINT,
+      SvcParam::Unknown { key, .. } => *key,
+    }
+  }
+
+  /// The value as it goes on the wire, after the key and length. ALPN IDs each
+  /// have a length byte, so none can be longer than 255 bytes.
+  pub fn value(&self) -> Result<Vec<u8>, DnsError> {
+    Ok(match self {
+      SvcParam::Mandatory(keys) => keys.iter().flat_map(|key| key.to_be_bytes()).collect(),
+      SvcParam::Alpn(ids) => {
+        let mut value = Vec::new();
+        for id in ids {
+          let length = u8::try_from(id.len()).map_err(|_| DnsError::BadSvcParams(format!("alpn id is {} bytes, more than 255", id.len())))?;
+          value.push(length);
+          value.extend(id);
+        }
+        value
+      }
+      SvcParam::NoDefaultAlpn => Vec::new(),
+      SvcParam::Port(port) => port.to_be_bytes().to_vec(),
+      SvcParam::Ipv4Hint(ips) => ips.iter().flat_map(Ipv4Addr::octets).collect(),
+      SvcParam::Ech(value) | SvcParam::Unknown { value, .. } => value.clone(),
+      SvcParam::Ipv6Hint(ips) => ips.iter().flat_map(Ipv6Addr::octets).collect(),
+    })
+  }
+}
+
+/// Checks that `params` are in strictly increasing key order, and so free of
+/// duplicates, and that they are self-consistent: every key listed as mandatory
+/// is present, and no-default-alpn comes with alpn.
+pub fn validate(params: &[SvcParam]) -> Result<(), DnsError> {
+  if let Some(pair) = params.windows(2).find(|pair| pair[0].key() >= pair[1].key()) {
+    let error = if pair[0].key() == pair[1].key() { "duplicate key" } else { "keys out of order" };
+    return Err(DnsError::BadSvcParams(format!("{error} {}", pair[1].key())));
+  }
+  let has = |key| params.iter().any(|param| param.key() == key);
+  for param in params {
+    match param {
+      SvcParam::Mandatory(keys) => {
+        if keys.is_empty() || keys.contains(&MANDATORY) {
+          return Err(DnsError::BadSvcParams("mandatory must list other keys".to_string()));
+        }
+        if keys.windows(2).any(|pair| pair[0] >= pair[1]) {
+          return Err(DnsError::BadSvcParams("mandatory keys out of order or repeated".to_string()));
+        }
+        if let Some(key) = keys.iter().find(|&&key| !has(key)) {
+          return Err(DnsError::BadSvcParams(format!("mandatory key {key} is missing")));
+        }
+      }
+      SvcParam::Alpn(ids) if ids.is_empty() || ids.iter().any(Vec::is_empty) => {
+        return Err(DnsError::BadSvcParams("empty alpn".to_string()));
+      }
+      SvcParam::NoDefaultAlpn if !has(ALPN) => return Err(DnsError::BadSvcParams("no-default-alpn without alpn".to_string())),
+      SvcParam::Ipv4Hint(ips) if ips.is_empty() => return Err(DnsError::BadSvcParams("empty ipv4hint".to_string())),
+      SvcParam::Ipv6Hint(ips) if ips.is_empty() => return Err(DnsError::BadSvcParams("empty ipv6hint".to_string())),
+      _ => {}
+    }
+  }
+  Ok(())
+}
+
+#[test]
+fn test_validate() {
+  let alpn = SvcParam::Alpn(vec![b"h2".to_vec()]);
+  assert!(validate(&[]).is_ok());
+  assert!(validate(&[SvcParam::Mandatory(vec![ALPN, PORT]), alpn.clone(), SvcParam::Port(8443)]).is_ok());
+  assert!(validate(&[SvcParam::Port(8443), alpn.clone()]).is_err());
+  assert!(validate(&[SvcParam::Port(8443), SvcParam::Port(443)]).is_err());
+  assert!(validate(&[SvcParam::Mandatory(vec![PORT]), alpn.clone()]).is_err());
+  assert!(validate(&[SvcParam::Mandatory(vec![MANDATORY])]).is_err());
+  assert!(validate(&[SvcParam::NoDefaultAlpn]).is_err());
+  assert!(validate(&[alpn, SvcParam::NoDefaultAlpn]).is_ok());
+  assert!(validate(&[SvcParam::Alpn(vec![Vec::new()])]).is_err());
+  assert!(validate(&[SvcParam::Ipv6Hint(Vec::new())]).is_err());
+}
@@ -0,0 +1,319 @@
+#![allow(dead_code)]
+//! Transaction signatures (RFC 8945): an HMAC over the whole message, with a
+//! secret both ends share, in a TSIG record at the end of the additional section.
+use std::{fmt, str::FromStr};
+
+use data_encoding::BASE64;
+use ring::hmac;
+
+use crate::{
+  error::DnsError,
+  message::Message,
+  message_ref::MessageRef,
+  name::Name,
+  record::{DnsClass, RData, RecordType, ResourceRecord},
+};
+
+/// TSIG error for a MAC that does not verify.
+pub const BADSIG: u16 = 16;
+/// TSIG error for a key or algorithm the receiver does not have.
+pub const BADKEY: u16 = 17;
+/// TSIG error for a time signed too far from the receiver's clock.
+pub const BADTIME: u16 = 18;
+/// How far, in seconds, the signer's clock may be from the receiver's.
+pub const FUDGE: u16 = 300;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+  HmacSha256,
+  HmacSha512,
+}
+
+impl Algorithm {
+  /// The name that identifies the algorithm in TSIG records.
+  pub fn name(self) -> Name {
+    let name = match self {
+      Algorithm::HmacSha256 => "hmac-sha256.",
+      Algorithm::HmacSha512 => "hmac-sha512.",
+    };
+    name.parse().expect("algorithm names are valid")
+  }
+  fn hmac(self) -> hmac::Algorithm {
+    match self {
+      Algorithm::HmacSha256 => hmac::HMAC_SHA256,
+      Algorithm::HmacSha512 => hmac::HMAC_SHA512,
+    }
+  }
+}
+
+impl FromStr for Algorithm {
+  type Err = DnsError;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s.trim_end_matches('.').to_ascii_lowercase().as_str() {
+      "hmac-sha256" => Ok(Algorithm::HmacSha256),
+      "hmac-sha512" => Ok(Algorithm::HmacSha512),
+      _ => Err(DnsError::Syntax(format!("unsupported TSIG algorithm {s:?}"))),
+    }
+  }
+}
+
+/// A shared secret, known to both ends by its name.
+#[derive(Clone)]
+pub struct Key {
+  pub name: Name,
+  pub algorithm: Algorithm,
+  secret: hmac::Key,
+}
+
+impl Key {
+  pub fn new(name: Name, algorithm: Algorithm, secret: &[u8]) -> Self {
+    Self { name, algorithm, secret: hmac::Key::new(algorithm.hmac(), secret) }
+  }
+  /// How big the TSIG record of a message signed with this key is, which has to
+  /// fit in the space the message is allowed.
+  pub fn record_len(&self) -> usize {
+    let mac_len = self.algorithm.hmac().digest_algorithm().output_len();
+    self.name.to_wire().len() + 10 + self.algorithm.name().to_wire().len() + 16 + mac_len
+  }
+}
+
+/// Leaves the secret out.
+impl fmt::Debug for Key {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("Key").field("name", &self.name).field("algorithm", &self.algorithm).finish_non_exhaustive()
+  }
+}
+
+/// `[algorithm:]name:secret`, as `dig -y` takes it, with the secret in base64.
+/// The algorithm defaults to hmac-sha256.
+impl FromStr for Key {
+  type Err = DnsError;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let (algorithm, name, secret) = match s.split(':').collect::<Vec<_>>()[..] {
+      [name, secret] => (Algorithm::HmacSha256, name, secret),
+      [algorithm, name, secret] => (algorithm.parse()?, name, secret),
+      _ => return Err(DnsError::Syntax(format!("TSIG key {s:?} is not [algorithm:]name:secret"))),
+    };
+    let secret = BASE64.decode(secret.as_bytes()).map_err(|_| DnsError::Syntax(format!("TSIG secret of {name} is not base64")))?;
+    Ok(Self::new(name.parse()?, algorithm, &secret))
+  }
+}
+
+/// The outcome of checking a message's TSIG.
+#[derive(Debug)]
+pub enum Verification {
+  /// The message has no TSIG.
+  Unsigned,
+  /// Signed with `key`. The response's TSIG covers `mac`.
+  Verified { key: Key, mac: Vec<u8> },
+  /// The TSIG did not check out, with the error the reply should carry. `key` is
+  /// ours of that name and algorithm, if there is one.
+  Rejected { error: u16, tsig: ResourceRecord, key: Option<Key> },
+}
+
+/// The TSIG variables the MAC covers after the message (RFC 8945 section 4.3.3).
+fn variables(key_name: &Name, algorithm: &Name, time_signed: u64, fudge: u16, error: u16, other: &[u8]) -> Vec<u8> {
+  let mut data = key_name.to_lowercase().to_wire();
+  data.extend(u16::from(DnsClass::ANY).to_be_bytes());
+  data.extend(0u32.to_be_bytes());
+  data.extend(algorithm.to_lowercase().to_wire());
+  data.extend(&time_signed.to_be_bytes()[2..]);
+  data.extend(fudge.to_be_bytes());
+  data.extend(error.to_be_bytes());
+  data.extend((other.len() as u16).to_be_bytes());
+  data.extend(other);
+  data
+}
+
+/// A response's MAC also covers the MAC of the request it answers.
+fn request_prefix(request_mac: Option<&[u8]>) -> Vec<u8> {
+  request_mac.map_or_else(Vec::new, |mac| [&(mac.len() as u16).to_be_bytes()[..], mac].concat())
+}
+
+fn append(message: &mut Message, key: &Key, time_signed: u64, request_mac: Option<&[u8]>, error: u16, other: Vec<u8>) -> Result<Vec<u8>, DnsError> {
+  let algorithm = key.algorithm.name();
+  let data = [request_prefix(request_mac), message.to_vec(), variables(&key.name, &algorithm, time_signed, FUDGE, error, &other)].concat();
+  let mac = hmac::sign(&key.secret, &data).as_ref().to_vec();
+  let rdata = RData::TSIG { algorithm, time_signed, fudge: FUDGE, mac: mac.clone(), original_id: message.id(), error, other };
+  add_uncompressed(message, &ResourceRecord::new(key.name.clone(), DnsClass::ANY, 0, rdata))?;
+  Ok(mac)
+}
+
+fn add_uncompressed(message: &mut Message, record: &ResourceRecord) -> Result<(), DnsError> {
+  let compression = message.compression();
+  message.set_compression(false);
+  let added = message.add_additional(record);
+  message.set_compression(compression);
+  added
+}
+
+/// Signs `message` with `key` at `now`, in seconds since the epoch, as the last
+/// thing done to it. Responses pass the MAC of the request's TSIG. Returns the
+/// MAC, which the TSIG of the response will cover.
+pub fn sign(message: &mut Message, key: &Key, now: u64, request_mac: Option<&[u8]>) -> Result<Vec<u8>, DnsError> {
+  append(message, key, now, request_mac, 0, Vec::new())
+}
+
+/// Adds the TSIG of an error response to `response`, whose rcode should be
+/// NOTAUTH. Only BADTIME is signed, with our clock in the other data; BADSIG and
+/// BADKEY cannot be (RFC 8945 section 5.3.2).
+pub fn reject(response: &mut Message, error: u16, tsig: &ResourceRecord, key: Option<&Key>, now: u64) -> Result<(), DnsError> {
+  let RData::TSIG { algorithm, time_signed, fudge, mac, .. } = &tsig.rdata else {
+    return Ok(());
+  };
+  match key {
+    Some(key) if error == BADTIME => append(response, key, *time_signed, Some(mac), error, now.to_be_bytes()[2..].to_vec()).map(|_| ()),
+    _ => {
+      let rdata = RData::TSIG {
+        algorithm: algorithm.clone(),
+        time_signed: *time_signed,
+        fudge: *fudge,
+        mac: Vec::new(),
+        original_id: response.id(),
+        error,
+        other: Vec::new(),
+      };
+      add_uncompressed(response, &ResourceRecord::new(tsig.name.clone(), DnsClass::ANY, 0, rdata))
+    }
+  }
+}
+
+/// Checks the TSIG of `data` against `keys` at `now`, in seconds since the epoch.
+/// Responses pass the MAC of the request they answer. Truncated MACs are not
+/// accepted. Errors are for messages that do not parse, or whose TSIG is not last.
+pub fn verify(data: &[u8], keys: &[Key], now: u64, request_mac: Option<&[u8]>) -> Result<Verification, DnsError> {
+  let message = MessageRef::parse(data)?;
+  message.validate()?;
+  let Some(record) = message.additionals().last().transpose()?.filter(|record| record.rtype == RecordType::TSIG) else {
+    return Ok(Verification::Unsigned);
+  };
+  let start = record.name.offset();
+  let tsig = record.to_record()?;
+  let RData::TSIG { algorithm, time_signed, fudge, mac, original_id, error, other } = &tsig.rdata else {
+    return Err(DnsError::BadRdata(RecordType::TSIG));
+  };
+  let Some(key) = keys.iter().find(|key| key.name == tsig.name && key.algorithm.name() == *algorithm) else {
+    return Ok(Verification::Rejected { error: BADKEY, tsig, key: None });
+  };
+  // The message as it was signed: before the TSIG went in, with its original ID.
+  let mut signed = data[..start].to_vec();
+  let additional_count = message.header().additional_count - 1;
+  signed[..2].copy_from_slice(&original_id.to_be_bytes());
+  signed[10..12].copy_from_slice(&additional_count.to_be_bytes());
+  let variables = variables(&tsig.name, algorithm, *time_signed, *fudge, *error, other);
+  if hmac::verify(&key.secret, &[request_prefix(request_mac), signed, variables].concat(), mac).is_err() {
+    return Ok(Verification::Rejected { error: BADSIG, tsig, key: Some(key.clone()) });
+  }
+  if now.abs_diff(*time_signed) > u64::from(*fudge) {
+    return Ok(Verification::Rejected { error: BADTIME, tsig, key: Some(key.clone()) });
+  }
+  Ok(Verification::Verified { key: key.clone(), mac: mac.clone() })
+}
+
+#[cfg(test)]
+use data_encoding::HEXLOWER;
+
+#[cfg(test)]
+fn test_key() -> Key {
+  "test.key.:c2VjcmV0IGtleSBmb3IgdGVzdHM=".parse().unwrap()
+}
+
+#[cfg(test)]
+fn test_query() -> Message {
+  Message::query("example.".parse().unwrap(), RecordType::A).id(0x1234).build().unwrap()
+}
+
+#[test]
+fn test_key_from_str() {
+  let key = test_key();
+  assert_eq!(key.name, "test.key.".parse().unwrap());
+  assert_eq!(key.algorithm, Algorithm::HmacSha256);
+  let key: Key = "hmac-sha512:test.key.:c2VjcmV0".parse().unwrap();
+  assert_eq!(key.algorithm, Algorithm::HmacSha512);
+  assert!("hmac-md5:test.key.:c2VjcmV0".parse::<Key>().is_err());
+  assert!("test.key.:not base64".parse::<Key>().is_err());
+  assert!("test.key.".parse::<Key>().is_err());
+}
+
+#[test]
+fn test_sign_known_answer() {
+  // Worked out independently from RFC 8945 with Python's hmac module.
+  let expected = "123401000001000000000001076578616d706c6500000100010474657374036b65790000fa00ff00000000003d0b686d61\
+    632d7368613235360000006553f100012c0020f958fab74afc7dd3e2f08f041be3c4402da0050b1d5d84ef6917f0c7669b9203123400000000";
+  let mut query = test_query();
+  let mac = sign(&mut query, &test_key(), 1_700_000_000, None).unwrap();
+  assert_eq!(HEXLOWER.encode(&query), expected);
+  assert_eq!(mac.len(), 32);
+  assert_eq!(query.len(), test_query().len() + test_key().record_len());
+  let Verification::Verified { key, mac: verified } = verify(&query, &[test_key()], 1_700_000_100, None).unwrap() else {
+    panic!("query did not verify");
+  };
+  assert_eq!(key.name, test_key().name);
+  assert_eq!(verified, mac);
+  assert!(matches!(verify(&query, &[test_key()], 1_700_000_301, None), Ok(Verification::Rejected { error: BADTIME, .. })));
+  assert!(matches!(verify(&test_query(), &[test_key()], 1_700_000_000, None), Ok(Verification::Unsigned)));
+}
+
+#[test]
+fn test_verify_rejects() {
+  let now = 1_700_000_000;
+  let mut query = test_query();
+  sign(&mut query, &test_key(), now, None).unwrap();
+  let other: Key = "other.key.:c2VjcmV0IGtleSBmb3IgdGVzdHM=".parse().unwrap();
+  assert!(matches!(verify(&query, &[other], now, None), Ok(Verification::Rejected { error: BADKEY, key: None, .. })));
+  let sha512: Key = "hmac-sha512:test.key.:c2VjcmV0IGtleSBmb3IgdGVzdHM=".parse().unwrap();
+  assert!(matches!(verify(&query, &[sha512], now, None), Ok(Verification::Rejected { error: BADKEY, .. })));
+  let wrong: Key = "test.key.:d3Jvbmc=".parse().unwrap();
+  assert!(matches!(verify(&query, &[wrong], now, None), Ok(Verification::Rejected { error: BADSIG, .. })));
+  let mut tampered = query.to_vec();
+  tampered[3] |= 0x10;
+  assert!(matches!(verify(&tampered, &[test_key()], now, None), Ok(Verification::Rejected { error: BADSIG, .. })));
+  // Forwarders may change the ID; the TSIG keeps the original.
+  let mut forwarded = query.to_vec();
+  forwarded[..2].copy_from_slice(&[0xab, 0xcd]);
+  assert!(matches!(verify(&forwarded, &[test_key()], now, None), Ok(Verification::Verified { .. })));
+  let mut misplaced = test_query();
+  sign(&mut misplaced, &test_key(), now, None).unwrap();
+  misplaced.add_additional(&"example. 60 IN A 192.0.2.1".parse().unwrap()).unwrap();
+  assert!(matches!(verify(&misplaced, &[test_key()], now, None), Err(DnsError::MisplacedTsig)));
+}
+
+#[test]
+fn test_sign_response() {
+  let now = 1_700_000_000;
+  let key = test_key();
+  let mut query = test_query();
+  let request_mac = sign(&mut query, &key, now, None).unwrap();
+  let mut response = Message::response_to(&query).unwrap().answer("example. 60 IN A 192.0.2.1".parse().unwrap()).build().unwrap();
+  sign(&mut response, &key, now + 1, Some(&request_mac)).unwrap();
+  assert!(matches!(verify(&response, std::slice::from_ref(&key), now + 2, Some(&request_mac)), Ok(Verification::Verified { .. })));
+  assert!(matches!(verify(&response, std::slice::from_ref(&key), now + 2, None), Ok(Verification::Rejected { error: BADSIG, .. })));
+}
+
+#[test]
+fn test_reject() {
+  let now = 1_700_000_000;
+  let key = test_key();
+  let mut query = test_query();
+  let request_mac = sign(&mut query, &key, now, None).unwrap();
+  let Verification::Rejected { error, tsig, key: found } = verify(&query, std::slice::from_ref(&key), now + 1000, None).unwrap() else {
+    panic!("stale query verified");
+  };
+  assert_eq!(error, BADTIME);
+  let mut response = Message::response_to(&query).unwrap().rcode(crate::header::Rcode::NotAuth).build().unwrap();
+  reject(&mut response, error, &tsig, found.as_ref(), now + 1000).unwrap();
+  // Signed with the client's time, so the client can verify it and see ours.
+  assert!(matches!(verify(&response, std::slice::from_ref(&key), now, Some(&request_mac)), Ok(Verification::Verified { .. })));
+  let RData::TSIG { time_signed, other, .. } = response.additionals().unwrap().pop().unwrap().rdata else {
+    panic!("no TSIG in the response");
+  };
+  assert_eq!(time_signed, now);
+  assert_eq!(other, (now + 1000).to_be_bytes()[2..]);
+  let mut response = Message::response_to(&query).unwrap().rcode(crate::header::Rcode::NotAuth).build().unwrap();
+  reject(&mut response, BADSIG, &tsig, found.as_ref(), now).unwrap();
+  let RData::TSIG { mac, error, .. } = response.additionals().unwrap().pop().unwrap().rdata else {
+    panic!("no TSIG in the response");
+  };
+  assert!(mac.is_empty());
+  assert_eq!(error, BADSIG);
+}
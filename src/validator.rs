@@ -0,0 +1,774 @@
+#![allow(dead_code)]
+//! DNSSEC validation (RFC 4035 section 5): signatures are checked against keys
+//! whose chain of DS records leads back to a configured trust anchor.
+use std::{
+  cell::RefCell,
+  collections::HashMap,
+  rc::Rc,
+  time::{SystemTime, UNIX_EPOCH},
+};
+
+use data_encoding::BASE32HEX_NOPAD;
+use ring::signature::{self, UnparsedPublicKey};
+
+use crate::{
+  dnssec::{canonical_rrset, nsec3_hash, DIGEST_SHA1, DIGEST_SHA256, DIGEST_SHA384, ECDSAP256SHA256, ECDSAP384SHA384, ED25519, OPT_OUT, PROTOCOL, RSASHA256, RSASHA512, ZONE_KEY},
+  error::DnsError,
+  header::Rcode,
+  message::{encode_rdata, Message},
+  name::Name,
+  record::{DnsClass, Question, RData, RecordType, ResourceRecord},
+};
+
+/// Where the validator gets the DNSKEY and DS records it needs. Queries should
+/// set DO, and CD so that the upstream passes on what it cannot validate itself.
+pub trait Resolver {
+  fn resolve(&self, question: &Question) -> Result<Message, DnsError>;
+}
+
+/// So that the validator can share a resolver with whatever else uses it.
+impl<R: Resolver + ?Sized> Resolver for Rc<R> {
+  fn resolve(&self, question: &Question) -> Result<Message, DnsError> {
+    (**self).resolve(question)
+  }
+}
+
+/// The outcome for an answer that is not bogus; bogus ones are `DnsError::Bogus`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Security {
+  /// Signed, with a chain of trust back to an anchor.
+  Secure,
+  /// Provably unsigned, or outside every trust anchor.
+  Insecure,
+}
+
+/// The DNSKEYs of a secure zone.
+#[derive(Debug, Clone)]
+struct ZoneKeys {
+  zone: Name,
+  keys: Vec<RData>,
+}
+
+/// The keys of a zone, or `None` for an insecure one, as the validator found them,
+/// good until `expires` in seconds since the epoch.
+#[derive(Debug, Clone)]
+struct CachedKeys {
+  keys: Option<ZoneKeys>,
+  expires: u32,
+}
+
+/// What the NSEC or NSEC3 records of a response say about a name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Proof {
+  /// The name exists, with these types.
+  Exists(Vec<RecordType>),
+  /// The name does not exist. With opt-out, insecure delegations may still be there.
+  Absent { opt_out: bool },
+}
+
+/// Validates answers with keys found through a `Resolver`, trusting the DS or
+/// DNSKEY records it is given as anchors.
+pub struct Validator<R> {
+  resolver: R,
+  trust_anchors: Vec<ResourceRecord>,
+  /// Zone keys found while validating, kept for as long as the TTLs of every
+  /// DS and DNSKEY RRset on the way down from the anchor allow.
+  keys: RefCell<HashMap<Name, CachedKeys>>,
+}
+
+impl<R: Resolver> Validator<R> {
+  /// Anchors other than DS and DNSKEY records are ignored.
+  pub fn new(resolver: R, trust_anchors: Vec<ResourceRecord>) -> Self {
+    let trust_anchors = trust_anchors.into_iter().filter(|anchor| matches!(anchor.rtype(), RecordType::DS | RecordType::DNSKEY)).collect();
+    Self { resolver, trust_anchors, keys: RefCell::default() }
+  }
+
+  /// Checks every RRset in the answer section, following CNAMEs, and for negative
+  /// or wildcard answers the NSEC or NSEC3 proofs in the authority section.
+  pub fn validate(&self, question: &Question, response: &Message) -> Result<Security, DnsError> {
+    // RRSIGs are not signed themselves.
+    if question.qtype == RecordType::RRSIG {
+      return Ok(Security::Insecure);
+    }
+    let answers = response.answers()?;
+    let (mut secure, mut wildcards) = (true, Vec::new());
+    for (rrset, signatures) in rrsets(&answers) {
+      match self.verify_rrset(&rrset, &signatures)? {
+        None => secure = false,
+        Some(labels) if (labels as usize) < rrset[0].name.labels().len() => wildcards.push((rrset[0].name.clone(), labels)),
+        Some(_) => {}
+      }
+    }
+    if !secure {
+      return Ok(Security::Insecure);
+    }
+    let mut name = question.name.clone();
+    for _ in 0..answers.len() {
+      let cname = answers.iter().find_map(|record| match &record.rdata {
+        RData::CNAME(target) if record.name == name && question.qtype != RecordType::CNAME => Some(target),
+        _ => None,
+      });
+      match cname {
+        Some(target) => name = target.clone(),
+        None => break,
+      }
+    }
+    let answered = answers.iter().any(|record| record.name == name && (record.rtype() == question.qtype || question.qtype == RecordType::ANY));
+    let negative = response.rcode() == Rcode::NXDomain || !answered;
+    if !negative && wildcards.is_empty() {
+      return Ok(Security::Secure);
+    }
+    let authorities = response.authorities()?;
+    let denial: Vec<_> = rrsets(&authorities)
+      .into_iter()
+      .filter(|(rrset, _)| matches!(rrset[0].rtype(), RecordType::SOA | RecordType::NSEC | RecordType::NSEC3))
+      .collect();
+    if denial.is_empty() {
+      return self.unsigned(&name, &format!("the denial of {name}"));
+    }
+    let mut proofs = Vec::new();
+    for (rrset, signatures) in denial {
+      if self.verify_rrset(&rrset, &signatures)?.is_none() {
+        return Ok(Security::Insecure);
+      }
+      proofs.extend(rrset.into_iter().filter(|record| record.rtype() != RecordType::SOA));
+    }
+    // A wildcard expansion needs proof that no closer name matched.
+    for (owner, labels) in wildcards {
+      let next_closer = Name::from_labels(&owner.labels()[owner.labels().len() - labels as usize - 1..])?;
+      if !matches!(prove(&proofs, &next_closer), Some(Proof::Absent { .. })) {
+        return Err(DnsError::Bogus(format!("nothing proves {next_closer} is missing for the wildcard answer {owner}")));
+      }
+    }
+    if !negative {
+      return Ok(Security::Secure);
+    }
+    let proven = if response.rcode() == Rcode::NXDomain {
+      closest_encloser(&proofs, &name).is_some_and(|encloser| {
+        let next_closer = Name::from_labels(&name.labels()[name.labels().len() - encloser.labels().len() - 1..]);
+        let wildcard = Name::from_labels(std::iter::once(&b"*"[..]).chain(encloser.labels().iter().map(Vec::as_slice)));
+        [next_closer, wildcard].iter().all(|name| matches!(name.as_ref().map(|name| prove(&proofs, name)), Ok(Some(Proof::Absent { .. }))))
+      })
+    } else {
+      match prove(&proofs, &name) {
+        Some(Proof::Exists(types)) => !types.contains(&question.qtype) && !types.contains(&RecordType::CNAME),
+        Some(Proof::Absent { opt_out }) => opt_out && question.qtype == RecordType::DS,
+        None => false,
+      }
+    };
+    if !proven {
+      return Err(DnsError::Bogus(format!("nothing proves the {:?} answer for {name} {}", response.rcode(), question.qtype)));
+    }
+    Ok(Security::Secure)
+  }
+
+  fn query(&self, name: &Name, rtype: RecordType) -> Result<Message, DnsError> {
+    let response = self.resolver.resolve(&Question::new(name.clone(), rtype, DnsClass::IN))?;
+    match response.rcode() {
+      Rcode::NoError | Rcode::NXDomain => Ok(response),
+      rcode => Err(DnsError::Bogus(format!("{rcode:?} looking up {name} {rtype}"))),
+    }
+  }
+
+  /// Data without signatures is fine in an insecure zone, and bogus in a secure one.
+  fn unsigned(&self, name: &Name, what: &str) -> Result<Security, DnsError> {
+    match self.keys(name)? {
+      None => Ok(Security::Insecure),
+      Some(keys) => Err(DnsError::Bogus(format!("{what} is unsigned in the secure zone {}", keys.zone))),
+    }
+  }
+
+  /// Checks an RRset against its signatures, giving the label count of the one that
+  /// verified, or `None` if the RRset belongs to an insecure zone.
+  fn verify_rrset(&self, rrset: &[ResourceRecord], signatures: &[RData]) -> Result<Option<u8>, DnsError> {
+    let Some(RData::RRSIG { signer_name, .. }) = signatures.first() else {
+      let (owner, rtype) = (&rrset[0].name, rrset[0].rtype());
+      return self.unsigned(owner, &format!("{owner} {rtype}")).map(|_| None);
+    };
+    match self.keys(signer_name)? {
+      Some(keys) => verify_with(rrset, signatures, &keys).map(Some),
+      None => Ok(None),
+    }
+  }
+
+  /// The keys of the secure zone `name` is in, or `None` if it is in an insecure one.
+  fn keys(&self, name: &Name) -> Result<Option<ZoneKeys>, DnsError> {
+    Ok(self.cached_keys(name)?.keys)
+  }
+
+  /// The keys of `name` from the cache, or found again once they have expired.
+  fn cached_keys(&self, name: &Name) -> Result<CachedKeys, DnsError> {
+    if let Some(cached) = self.keys.borrow().get(name).filter(|cached| now() < cached.expires) {
+      return Ok(cached.clone());
+    }
+    let cached = self.find_keys(name)?;
+    self.keys.borrow_mut().insert(name.clone(), cached.clone());
+    Ok(cached)
+  }
+
+  /// Follows the chain of trust from the closest anchor down to `name`, one DS
+  /// lookup at a time. Each DS RRset, or proof of its absence, is checked with
+  /// the keys of the zone above, and the answer expires with the first of them.
+  fn find_keys(&self, name: &Name) -> Result<CachedKeys, DnsError> {
+    let Some(anchor) = self.trust_anchors.iter().map(|anchor| &anchor.name).filter(|anchor| name.is_subdomain_of(anchor)).max_by_key(|anchor| anchor.labels().len()) else {
+      return Ok(CachedKeys { keys: None, expires: u32::MAX });
+    };
+    if anchor == name {
+      let trusted: Vec<_> = self.trust_anchors.iter().filter(|anchor| anchor.name == *name).map(|anchor| anchor.rdata.clone()).collect();
+      return self.zone_keys(name, &trusted, u32::MAX);
+    }
+    let response = self.query(name, RecordType::DS)?;
+    let (answers, authorities) = (response.answers()?, response.authorities()?);
+    let ds: Vec<_> = answers.iter().filter(|record| record.name == *name && record.rtype() == RecordType::DS).map(|record| record.rdata.clone()).collect();
+    let records = if ds.is_empty() { &authorities } else { &answers };
+    // The zone above is the one that signed the answer, or failing that sent its SOA.
+    let parent = records
+      .iter()
+      .find_map(|record| match &record.rdata {
+        RData::RRSIG { signer_name, .. } => Some(signer_name.clone()),
+        _ => None,
+      })
+      .or_else(|| authorities.iter().find(|record| record.rtype() == RecordType::SOA).map(|record| record.name.clone()))
+      .ok_or_else(|| DnsError::Bogus(format!("no zone answered for the DS of {name}")))?;
+    if parent == *name || !name.is_subdomain_of(&parent) || !parent.is_subdomain_of(anchor) {
+      return Err(DnsError::Bogus(format!("{parent} answered for the DS of {name}")));
+    }
+    let CachedKeys { keys: parent_keys, mut expires } = self.cached_keys(&parent)?;
+    let Some(parent_keys) = parent_keys else {
+      return Ok(CachedKeys { keys: None, expires });
+    };
+    let mut proofs = Vec::new();
+    for (rrset, signatures) in rrsets(records) {
+      if matches!(rrset[0].rtype(), RecordType::DS | RecordType::SOA | RecordType::NSEC | RecordType::NSEC3) {
+        verify_with(&rrset, &signatures, &parent_keys)?;
+        expires = expires.min(expiry(&rrset));
+        proofs.extend(rrset.into_iter().filter(|record| matches!(record.rtype(), RecordType::NSEC | RecordType::NSEC3)));
+      }
+    }
+    if !ds.is_empty() {
+      return self.zone_keys(name, &ds, expires);
+    }
+    let keys = match prove(&proofs, name) {
+      Some(Proof::Exists(types)) if types.contains(&RecordType::DS) => return Err(DnsError::Bogus(format!("the DS of {name} was left out"))),
+      // A delegation without DS leads to an unsigned zone.
+      Some(Proof::Exists(types)) if types.contains(&RecordType::NS) => None,
+      Some(Proof::Absent { opt_out: true }) => None,
+      // No zone cut here, so `name` is part of the zone above.
+      Some(_) => Some(parent_keys),
+      None => return Err(DnsError::Bogus(format!("nothing proves {name} has no DS"))),
+    };
+    Ok(CachedKeys { keys, expires })
+  }
+
+  /// The DNSKEYs of `zone`, if one that the `trusted` DS or DNSKEY records vouch
+  /// for signs them. With no supported algorithm among `trusted` the zone counts
+  /// as unsigned (RFC 4035 section 5.2). They expire with the DNSKEY RRset, or
+  /// at `expires` if that is sooner.
+  fn zone_keys(&self, zone: &Name, trusted: &[RData], expires: u32) -> Result<CachedKeys, DnsError> {
+    let trusted: Vec<_> = trusted
+      .iter()
+      .filter(|rdata| match rdata {
+        RData::DS { algorithm, digest_type, .. } => is_supported(*algorithm) && matches!(*digest_type, DIGEST_SHA1 | DIGEST_SHA256 | DIGEST_SHA384),
+        RData::DNSKEY { algorithm, .. } => is_supported(*algorithm),
+        _ => false,
+      })
+      .collect();
+    if trusted.is_empty() {
+      return Ok(CachedKeys { keys: None, expires });
+    }
+    let answers = self.query(zone, RecordType::DNSKEY)?.answers()?;
+    let Some((keys, signatures)) = rrsets(&answers).into_iter().find(|(rrset, _)| rrset[0].name == *zone && rrset[0].rtype() == RecordType::DNSKEY) else {
+      return Err(DnsError::Bogus(format!("{zone} has no DNSKEY records")));
+    };
+    let now = now();
+    for key in &keys {
+      let vouched = trusted.iter().any(|&rdata| match rdata {
+        RData::DS { digest_type, .. } => key.to_ds(*digest_type).as_ref() == Some(rdata),
+        _ => key.rdata == *rdata,
+      });
+      if vouched && signatures.iter().any(|signature| verify_rrsig(&keys, signature, &key.rdata, now).is_ok()) {
+        let expires = expires.min(expiry(&keys));
+        return Ok(CachedKeys { keys: Some(ZoneKeys { zone: zone.clone(), keys: keys.into_iter().map(|key| key.rdata).collect() }), expires });
+      }
+    }
+    Err(DnsError::Bogus(format!("no trusted DNSKEY of {zone} signs its DNSKEY RRset")))
+  }
+}
+
+/// The RRsets in a section, each with the RRSIGs covering it.
+fn rrsets(records: &[ResourceRecord]) -> Vec<(Vec<ResourceRecord>, Vec<RData>)> {
+  let mut rrsets: Vec<(Vec<ResourceRecord>, Vec<RData>)> = Vec::new();
+  for record in records.iter().filter(|record| record.rtype() != RecordType::RRSIG) {
+    match rrsets.iter_mut().find(|(rrset, _)| rrset[0].name == record.name && rrset[0].rtype() == record.rtype() && rrset[0].class == record.class) {
+      Some((rrset, _)) => rrset.push(record.clone()),
+      None => rrsets.push((vec![record.clone()], Vec::new())),
+    }
+  }
+  for (rrset, signatures) in &mut rrsets {
+    let covering = records.iter().filter(|record| {
+      record.name == rrset[0].name && record.class == rrset[0].class && matches!(record.rdata, RData::RRSIG { type_covered, .. } if type_covered == rrset[0].rtype())
+    });
+    signatures.extend(covering.map(|record| record.rdata.clone()));
+  }
+  rrsets
+}
+
+/// Checks `rrset` against the first of `signatures` that one of `keys` verifies,
+/// giving its label count.
+fn verify_with(rrset: &[ResourceRecord], signatures: &[RData], keys: &ZoneKeys) -> Result<u8, DnsError> {
+  let (owner, rtype, zone) = (&rrset[0].name, rrset[0].rtype(), &keys.zone);
+  let mut error = DnsError::Bogus(format!("{owner} {rtype} is unsigned in the secure zone {zone}"));
+  let now = now();
+  for signature in signatures {
+    let RData::RRSIG { labels, key_tag, signer_name, .. } = signature else {
+      continue;
+    };
+    error = DnsError::Bogus(format!("{owner} {rtype} is signed by {signer_name} {key_tag}, not a DNSKEY of {zone}"));
+    if signer_name != zone {
+      continue;
+    }
+    for key in keys.keys.iter().filter(|key| key.key_tag() == Some(*key_tag)) {
+      match verify_rrsig(rrset, signature, key, now) {
+        Ok(()) => return Ok(*labels),
+        Err(e) => error = e,
+      }
+    }
+  }
+  Err(error)
+}
+
+/// When the shortest TTL in `rrset` runs out.
+fn expiry(rrset: &[ResourceRecord]) -> u32 {
+  now().saturating_add(rrset.iter().map(|record| record.ttl).min().unwrap_or(0))
+}
+
+/// Seconds since the epoch, as RRSIG times count them.
+fn now() -> u32 {
+  SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs() as u32)
+}
+
+/// Whether serial number `a` comes before `b` (RFC 1982), as RRSIG times compare.
+fn serial_lt(a: u32, b: u32) -> bool {
+  a != b && b.wrapping_sub(a) < 1 << 31
+}
+
+/// Algorithms whose signatures can be checked.
+pub fn is_supported(algorithm: u8) -> bool {
+  matches!(algorithm, RSASHA256 | RSASHA512 | ECDSAP256SHA256 | ECDSAP384SHA384 | ED25519)
+}
+
+/// The data an RRSIG signs (RFC 4034 section 3.1.8.1): its own rdata up to the
+/// signature, then the RRset in canonical form and order with the original TTL.
+/// Owners with more labels than the RRSIG counts were expanded from a wildcard,
+/// and are signed as that wildcard.
+pub fn signed_data(rrset: &[ResourceRecord], rrsig: &RData) -> Option<Vec<u8>> {
+  let RData::RRSIG { type_covered, algorithm, labels, original_ttl, expiration, inception, key_tag, signer_name, .. } = rrsig.to_canonical() else {
+    return None;
+  };
+  let mut data = encode_rdata(&RData::RRSIG { type_covered, algorithm, labels, original_ttl, expiration, inception, key_tag, signer_name, signature: Vec::new() }).ok()?;
+  for record in canonical_rrset(rrset).ok()? {
+    let owner = record.name.labels();
+    let owner = match owner.len().checked_sub(labels as usize)? {
+      0 => record.name.clone(),
+      extra => Name::from_labels(std::iter::once(&b"*"[..]).chain(owner[extra..].iter().map(Vec::as_slice))).ok()?,
+    };
+    let rdata = encode_rdata(&record.rdata).ok()?;
+    data.extend(owner.to_wire());
+    data.extend(u16::from(record.rtype()).to_be_bytes());
+    data.extend(u16::from(record.class).to_be_bytes());
+    data.extend(original_ttl.to_be_bytes());
+    data.extend(u16::try_from(rdata.len()).ok()?.to_be_bytes());
+    data.extend(rdata);
+  }
+  Some(data)
+}
+
+/// Checks that `dnskey` made `rrsig` over `rrset`, and that the signature is valid
+/// at `now`, in seconds since the epoch.
+pub fn verify_rrsig(rrset: &[ResourceRecord], rrsig: &RData, dnskey: &RData, now: u32) -> Result<(), DnsError> {
+  let bogus = |reason: &str| Err(DnsError::Bogus(reason.to_string()));
+  let (RData::RRSIG { type_covered, algorithm, labels, expiration, inception, key_tag, signer_name, signature, .. }, RData::DNSKEY { flags, protocol, public_key, .. }) = (rrsig, dnskey) else {
+    return bogus("not an RRSIG and a DNSKEY");
+  };
+  let Some(owner) = rrset.first().map(|record| &record.name) else {
+    return bogus("empty RRset");
+  };
+  let what = format!("RRSIG over {owner} {type_covered}");
+  if rrset.iter().any(|record| record.name != *owner || record.rtype() != *type_covered) {
+    return bogus(&format!("{what} covers a different RRset"));
+  }
+  if !owner.is_subdomain_of(signer_name) || *labels as usize > owner.labels().len() {
+    return bogus(&format!("{what} cannot come from {signer_name}"));
+  }
+  if flags & ZONE_KEY == 0 || *protocol != PROTOCOL || dnskey.key_tag() != Some(*key_tag) || dnskey_algorithm(dnskey) != *algorithm {
+    return bogus(&format!("{what} was not made by that DNSKEY"));
+  }
+  if serial_lt(now, *inception) {
+    return bogus(&format!("{what} is not valid yet"));
+  }
+  if serial_lt(*expiration, now) {
+    return bogus(&format!("{what} has expired"));
+  }
+  let Some(data) = signed_data(rrset, rrsig) else {
+    return bogus(&format!("{what} has more labels than its owner"));
+  };
+  let verified = match *algorithm {
+    RSASHA256 | RSASHA512 => {
+      let Some((e, n)) = rsa_public_key(public_key) else {
+        return bogus(&format!("{what}: malformed RSA key"));
+      };
+      // DNSSEC still has 1024 bit zone signing keys about.
+      let parameters =
+        if *algorithm == RSASHA256 { &signature::RSA_PKCS1_1024_8192_SHA256_FOR_LEGACY_USE_ONLY } else { &signature::RSA_PKCS1_1024_8192_SHA512_FOR_LEGACY_USE_ONLY };
+      signature::RsaPublicKeyComponents { n, e }.verify(parameters, &data, signature)
+    }
+    // The key is the bare point, without the uncompressed point prefix.
+    ECDSAP256SHA256 => UnparsedPublicKey::new(&signature::ECDSA_P256_SHA256_FIXED, [&[4], &public_key[..]].concat()).verify(&data, signature),
+    ECDSAP384SHA384 => UnparsedPublicKey::new(&signature::ECDSA_P384_SHA384_FIXED, [&[4], &public_key[..]].concat()).verify(&data, signature),
+    ED25519 => UnparsedPublicKey::new(&signature::ED25519, public_key).verify(&data, signature),
+    algorithm => return bogus(&format!("{what} uses unsupported algorithm {algorithm}")),
+  };
+  verified.or_else(|_| bogus(&format!("{what} does not verify")))
+}
+
+fn dnskey_algorithm(dnskey: &RData) -> u8 {
+  match dnskey {
+    RData::DNSKEY { algorithm, .. } => *algorithm,
+    _ => 0,
+  }
+}
+
+/// The exponent and modulus of an RSA DNSKEY (RFC 3110 section 2).
+fn rsa_public_key(key: &[u8]) -> Option<(&[u8], &[u8])> {
+  let (length, rest) = match *key.first()? {
+    0 => (u16::from_be_bytes([*key.get(1)?, *key.get(2)?]) as usize, &key[3..]),
+    length => (length as usize, &key[1..]),
+  };
+  (length < rest.len()).then(|| rest.split_at(length))
+}
+
+/// Whether `name` falls strictly between `owner` and `next`, in a chain that wraps
+/// around from its last record to its first.
+fn covers<T: Ord + ?Sized>(owner: &T, next: &T, name: &T) -> bool {
+  if owner < next {
+    owner < name && name < next
+  } else {
+    owner < name || name < next
+  }
+}
+
+/// What `proofs`, NSEC and NSEC3 records, say about `name`.
+fn prove(proofs: &[ResourceRecord], name: &Name) -> Option<Proof> {
+  proofs.iter().find_map(|proof| match &proof.rdata {
+    RData::NSEC { types, .. } if proof.name == *name => Some(Proof::Exists(types.clone())),
+    // An empty non-terminal has names below it, but no NSEC of its own.
+    RData::NSEC { next_domain, .. } if covers(&proof.name, next_domain, name) => {
+      Some(if next_domain.is_subdomain_of(name) { Proof::Exists(Vec::new()) } else { Proof::Absent { opt_out: false } })
+    }
+    RData::NSEC3 { hash_algorithm, flags, iterations, salt, next_hashed_owner, types } => {
+      if !name.is_subdomain_of(&proof.name.parent()?) {
+        return None;
+      }
+      let hash = nsec3_hash(name, *hash_algorithm, *iterations, salt)?;
+      let owner = BASE32HEX_NOPAD.decode(&proof.name.labels().first()?.to_ascii_uppercase()).ok()?;
+      if hash == owner {
+        Some(Proof::Exists(types.clone()))
+      } else {
+        covers(&owner, next_hashed_owner, &hash).then_some(Proof::Absent { opt_out: flags & OPT_OUT != 0 })
+      }
+    }
+    _ => None,
+  })
+}
+
+/// The closest ancestor of the missing `name` that exists (RFC 5155 section 7.2.1):
+/// one an NSEC3 record matches, or that holds either end of the NSEC covering `name`.
+fn closest_encloser(proofs: &[ResourceRecord], name: &Name) -> Option<Name> {
+  let mut ancestor = name.parent();
+  while let Some(encloser) = ancestor {
+    let exists = proofs.iter().any(|proof| match &proof.rdata {
+      RData::NSEC { next_domain, .. } => covers(&proof.name, next_domain, name) && (proof.name.is_subdomain_of(&encloser) || next_domain.is_subdomain_of(&encloser)),
+      RData::NSEC3 { .. } => matches!(prove(std::slice::from_ref(proof), &encloser), Some(Proof::Exists(_))),
+      _ => false,
+    });
+    if exists {
+      return Some(encloser);
+    }
+    ancestor = encloser.parent();
+  }
+  None
+}
+
+#[cfg(test)]
+use crate::{builder::MessageBuilder, dnssec::SECURE_ENTRY_POINT, zone::Zone};
+
+/// A zone and the one key that signs everything in it, for building test hierarchies.
+#[cfg(test)]
+struct Signer {
+  key: TestKey,
+  dnskey: ResourceRecord,
+}
+
+#[cfg(test)]
+enum TestKey {
+  Ed25519(signature::Ed25519KeyPair),
+  Ecdsa(signature::EcdsaKeyPair),
+}
+
+#[cfg(test)]
+impl Signer {
+  fn new(zone: &str, algorithm: u8) -> Self {
+    use ring::signature::KeyPair;
+    let rng = ring::rand::SystemRandom::new();
+    let (key, public_key) = match algorithm {
+      ED25519 => {
+        let pkcs8 = signature::Ed25519KeyPair::generate_pkcs8(&rng).unwrap();
+        let key = signature::Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+        let public_key = key.public_key().as_ref().to_vec();
+        (TestKey::Ed25519(key), public_key)
+      }
+      _ => {
+        let signing = if algorithm == ECDSAP256SHA256 { &signature::ECDSA_P256_SHA256_FIXED_SIGNING } else { &signature::ECDSA_P384_SHA384_FIXED_SIGNING };
+        let pkcs8 = signature::EcdsaKeyPair::generate_pkcs8(signing, &rng).unwrap();
+        let key = signature::EcdsaKeyPair::from_pkcs8(signing, pkcs8.as_ref(), &rng).unwrap();
+        let public_key = key.public_key().as_ref()[1..].to_vec();
+        (TestKey::Ecdsa(key), public_key)
+      }
+    };
+    let rdata = RData::DNSKEY { flags: ZONE_KEY | SECURE_ENTRY_POINT, protocol: PROTOCOL, algorithm, public_key };
+    Self { key, dnskey: ResourceRecord::new(zone.parse().unwrap(), DnsClass::IN, 3600, rdata) }
+  }
+
+  fn ds(&self) -> ResourceRecord {
+    ResourceRecord::new(self.dnskey.name.clone(), DnsClass::IN, 3600, self.dnskey.to_ds(DIGEST_SHA256).unwrap())
+  }
+
+  /// `rrset` followed by an RRSIG valid from `inception` to `expiration`.
+  fn sign_between(&self, rrset: &[ResourceRecord], inception: u32, expiration: u32) -> Vec<ResourceRecord> {
+    let owner = &rrset[0].name;
+    let labels = owner.labels().len() - usize::from(owner.labels().first().is_some_and(|label| label == b"*"));
+    let RData::DNSKEY { algorithm, .. } = self.dnskey.rdata else { unreachable!() };
+    let mut rrsig = RData::RRSIG {
+      type_covered: rrset[0].rtype(),
+      algorithm,
+      labels: labels as u8,
+      original_ttl: rrset[0].ttl,
+      expiration,
+      inception,
+      key_tag: self.dnskey.rdata.key_tag().unwrap(),
+      signer_name: self.dnskey.name.clone(),
+      signature: Vec::new(),
+    };
+    let data = signed_data(rrset, &rrsig).unwrap();
+    let RData::RRSIG { signature, .. } = &mut rrsig else { unreachable!() };
+    *signature = match &self.key {
+      TestKey::Ed25519(key) => key.sign(&data).as_ref().to_vec(),
+      TestKey::Ecdsa(key) => key.sign(&ring::rand::SystemRandom::new(), &data).unwrap().as_ref().to_vec(),
+    };
+    let mut signed = rrset.to_vec();
+    signed.push(ResourceRecord::new(owner.clone(), rrset[0].class, rrset[0].ttl, rrsig));
+    signed
+  }
+
+  /// Each RRset of `text`, a zone file, with an RRSIG valid for an hour either side of now.
+  fn sign(&self, text: &str) -> Vec<ResourceRecord> {
+    let records: Zone = text.parse().unwrap();
+    rrsets(records.records()).iter().flat_map(|(rrset, _)| self.sign_between(rrset, now() - 3600, now() + 3600)).collect()
+  }
+}
+
+/// Canned answers to the DS and DNSKEY queries of the validator, counting the queries.
+#[cfg(test)]
+#[derive(Default)]
+struct TestResolver {
+  responses: HashMap<(Name, RecordType), Message>,
+  queries: std::cell::Cell<usize>,
+}
+
+#[cfg(test)]
+impl TestResolver {
+  fn add(&mut self, name: &str, rtype: RecordType, response: Message) {
+    self.responses.insert((name.parse().unwrap(), rtype), response);
+  }
+}
+
+#[cfg(test)]
+impl Resolver for TestResolver {
+  fn resolve(&self, question: &Question) -> Result<Message, DnsError> {
+    self.queries.set(self.queries.get() + 1);
+    let response = self.responses.get(&(question.name.clone(), question.qtype)).ok_or(DnsError::UpstreamTimeout)?;
+    Message::parse(response)
+  }
+}
+
+#[cfg(test)]
+fn test_response(rcode: Rcode, answers: Vec<ResourceRecord>, authorities: Vec<ResourceRecord>) -> Message {
+  MessageBuilder::new(0).rcode(rcode).answers(answers).authorities(authorities).build().unwrap()
+}
+
+/// A root signed with Ed25519 and trusted through its DS, over `example.`, with
+/// ECDSA P-256 and NSEC, and `test.`, with ECDSA P-384 and NSEC3. Below
+/// `example.` are `www.example.`, and the unsigned delegation `insecure.example.`.
+#[cfg(test)]
+fn test_hierarchy() -> (Validator<TestResolver>, Signer, Signer) {
+  let (root, example, test) = (Signer::new(".", ED25519), Signer::new("example.", ECDSAP256SHA256), Signer::new("test.", ECDSAP384SHA384));
+  let mut resolver = TestResolver::default();
+  for signer in [&root, &example, &test] {
+    let name = signer.dnskey.name.to_string();
+    resolver.add(&name, RecordType::DNSKEY, test_response(Rcode::NoError, signer.sign(&signer.dnskey.to_string()), Vec::new()));
+    resolver.add(&name, RecordType::DS, test_response(Rcode::NoError, root.sign(&signer.ds().to_string()), Vec::new()));
+  }
+  let soa = "example. 300 IN SOA ns.example. hostmaster.example. 1 7200 3600 1209600 300\n";
+  let denial = example.sign(&format!("{soa}insecure.example. 300 IN NSEC www.example. NS RRSIG NSEC"));
+  resolver.add("insecure.example", RecordType::DS, test_response(Rcode::NoError, Vec::new(), denial));
+  let denial = example.sign(&format!("{soa}www.example. 300 IN NSEC example. A RRSIG NSEC"));
+  resolver.add("www.example", RecordType::DS, test_response(Rcode::NoError, Vec::new(), denial));
+  let soa: ResourceRecord = "insecure.example. 300 IN SOA ns.insecure.example. hostmaster.insecure.example. 1 7200 3600 1209600 300".parse().unwrap();
+  resolver.add("www.insecure.example", RecordType::DS, test_response(Rcode::NoError, Vec::new(), vec![soa]));
+  let anchor = root.ds();
+  (Validator::new(resolver, vec![anchor]), example, test)
+}
+
+#[cfg(test)]
+fn test_question(name: &str, qtype: RecordType) -> Question {
+  Question::new(name.parse().unwrap(), qtype, DnsClass::IN)
+}
+
+#[test]
+fn test_verify_rrsig() {
+  // The Ed25519 example of RFC 8080 section 6.1.
+  let records: Zone = "example.com. 3600 IN DNSKEY 257 3 15 l02Woi0iS8Aa25FQkUd9RMzZHJpBoRQwAQEX1SxZJA4=
+    example.com. 3600 IN MX 10 mail.example.com.
+    example.com. 3600 IN RRSIG MX 15 2 3600 1440021600 1438207200 3613 example.com. (
+      oL9krJun7xfBOIWcGHi7mag5/hdZrKWw15jPGrHpjQeRAvTdszaPD+QLs3fx8A4M3e23mRZ9VrbpMngwcrqNAg== )"
+    .parse()
+    .unwrap();
+  let [dnskey, mx, rrsig] = records.records() else { panic!() };
+  let rrset = [mx.clone()];
+  verify_rrsig(&rrset, &rrsig.rdata, &dnskey.rdata, 1439000000).unwrap();
+  assert!(matches!(verify_rrsig(&rrset, &rrsig.rdata, &dnskey.rdata, 1440021601), Err(DnsError::Bogus(reason)) if reason.ends_with("has expired")));
+  assert!(verify_rrsig(&rrset, &rrsig.rdata, &dnskey.rdata, 1438207199).is_err());
+  let other = [ResourceRecord { rdata: RData::from_text(RecordType::MX, "20 mail.example.com.").unwrap(), ..mx.clone() }];
+  assert!(verify_rrsig(&other, &rrsig.rdata, &dnskey.rdata, 1439000000).is_err());
+  // The ECDSA P-256 example of RFC 6605 section 6.1.
+  let records: Zone = "example.net. 3600 IN DNSKEY 257 3 13 GojIhhXUN/u4v54ZQqGSnyhWJwaubCvTmeexv7bR6edbkrSqQpF64cYbcB7wNcP+e+MAnLr+Wi9xMWyQLc8NAA==
+    www.example.net. 3600 IN A 192.0.2.1
+    www.example.net. 3600 IN RRSIG A 13 3 3600 20100909100439 20100812100439 55648 example.net. (
+      qx6wLYqmh+l9oCKTN6qIc+bw6ya+KJ8oMz0YP107epXAyGmt+3SNruPFKG7tZoLBLlUzGGus7ZwmwWep666VCw== )"
+    .parse()
+    .unwrap();
+  let [dnskey, a, rrsig] = records.records() else { panic!() };
+  verify_rrsig(std::slice::from_ref(a), &rrsig.rdata, &dnskey.rdata, 1282000000).unwrap();
+  // RSA/SHA-256, signed with openssl over data built by hand.
+  let records: Zone = "example.net. 3600 IN DNSKEY 257 3 8 (
+      AwEAAcs+MIDxyiFX88HBJIIsp4r/gt0K0r2u0SQM+wSAHjVq7uaEgqCEIoPZnLRP00MfnbYGyrisitH20JpXGCyuo/rFYBxMS5BCHl8dJZAJhFZq
+      CQ34KvpX4yiTKvPioG35eDXRAxp4ekxbSbojWYUV0jlXJJ6dZzRw9IzwHxqk7jCd )
+    www.example.net. 3600 IN A 192.0.2.1
+    www.example.net. 3600 IN RRSIG A 8 3 3600 2000000000 1700000000 15206 example.net. (
+      FDcJ407UsidW+W4VdrpbH8SFyLfie43vtMKlampeSClBJg0i02cPdEl7rjC7rYM69qaAlM0paDLd84b3C1tc0hkANiKIenPKcvzL4MXKZjvBa0vs
+      AJLMWpT2w5Q5OOFG+PcQyf5mpxbWg/n0dHNf2Rjb1WdvcUL5LfB5AzrvR1s= )"
+    .parse()
+    .unwrap();
+  let [dnskey, a, rrsig] = records.records() else { panic!() };
+  verify_rrsig(std::slice::from_ref(a), &rrsig.rdata, &dnskey.rdata, 1800000000).unwrap();
+  assert_eq!(rsa_public_key(&[1, 3]), None);
+  assert!(serial_lt(u32::MAX, 1) && !serial_lt(1, u32::MAX));
+}
+
+#[test]
+fn test_validate_chain() {
+  let (validator, example, test) = test_hierarchy();
+  let question = test_question("www.example", RecordType::A);
+  let answers = example.sign("www.example. 300 IN A 192.0.2.1");
+  assert_eq!(validator.validate(&question, &test_response(Rcode::NoError, answers.clone(), Vec::new())).unwrap(), Security::Secure);
+  let mut tampered = answers.clone();
+  tampered[0].rdata = RData::A([192, 0, 2, 66].into());
+  assert!(matches!(validator.validate(&question, &test_response(Rcode::NoError, tampered, Vec::new())), Err(DnsError::Bogus(_))));
+  let stripped = test_response(Rcode::NoError, answers[..1].to_vec(), Vec::new());
+  assert!(matches!(validator.validate(&question, &stripped), Err(DnsError::Bogus(reason)) if reason.contains("unsigned in the secure zone example.")));
+  let expired = example.sign_between(&answers[..1], now() - 7200, now() - 3600);
+  assert!(matches!(validator.validate(&question, &test_response(Rcode::NoError, expired, Vec::new())), Err(DnsError::Bogus(reason)) if reason.ends_with("has expired")));
+  // Keys of one zone cannot sign for another.
+  let question = test_question("www.test", RecordType::AAAA);
+  let forged = example.sign("www.test. 300 IN AAAA 2001:db8::1");
+  assert!(validator.validate(&question, &test_response(Rcode::NoError, forged, Vec::new())).is_err());
+  let answers = test.sign("www.test. 300 IN AAAA 2001:db8::1");
+  assert_eq!(validator.validate(&question, &test_response(Rcode::NoError, answers, Vec::new())).unwrap(), Security::Secure);
+}
+
+#[test]
+fn test_validate_insecure() {
+  let (validator, example, _) = test_hierarchy();
+  let question = test_question("www.insecure.example", RecordType::A);
+  let answers = vec!["www.insecure.example. 300 IN A 192.0.2.1".parse().unwrap()];
+  assert_eq!(validator.validate(&question, &test_response(Rcode::NoError, answers, Vec::new())).unwrap(), Security::Insecure);
+  // Without a trust anchor nothing is secure, signed or not.
+  let validator = Validator::new(validator.resolver, Vec::new());
+  let answers = example.sign("www.example. 300 IN A 192.0.2.1");
+  let response = test_response(Rcode::NoError, answers, Vec::new());
+  assert_eq!(validator.validate(&test_question("www.example", RecordType::A), &response).unwrap(), Security::Insecure);
+}
+
+#[test]
+fn test_validate_denial() {
+  let (validator, example, test) = test_hierarchy();
+  let soa = "example. 300 IN SOA ns.example. hostmaster.example. 1 7200 3600 1209600 300\n";
+  let apex = "example. 300 IN NSEC insecure.example. SOA NS DNSKEY NSEC RRSIG\n";
+  let insecure = "insecure.example. 300 IN NSEC www.example. NS RRSIG NSEC\n";
+  // NXDOMAIN needs the name and the wildcard of its closest encloser proven missing.
+  let question = test_question("nope.example", RecordType::A);
+  let response = test_response(Rcode::NXDomain, Vec::new(), example.sign(&[soa, apex, insecure].concat()));
+  assert_eq!(validator.validate(&question, &response).unwrap(), Security::Secure);
+  let response = test_response(Rcode::NXDomain, Vec::new(), example.sign(&[soa, insecure].concat()));
+  assert!(matches!(validator.validate(&question, &response), Err(DnsError::Bogus(reason)) if reason.starts_with("nothing proves")));
+  // NODATA needs the NSEC of the name itself, without the type.
+  let nodata = test_response(Rcode::NoError, Vec::new(), example.sign(&[soa, "www.example. 300 IN NSEC example. A RRSIG NSEC"].concat()));
+  assert_eq!(validator.validate(&test_question("www.example", RecordType::MX), &nodata).unwrap(), Security::Secure);
+  assert!(validator.validate(&test_question("www.example", RecordType::A), &nodata).is_err());
+  // A wildcard answer needs the next closer name proven missing.
+  let question = test_question("a.b.example", RecordType::A);
+  let mut answers = example.sign("*.example. 300 IN A 192.0.2.2");
+  for answer in &mut answers {
+    answer.name = question.name.clone();
+  }
+  let proof = example.sign("*.example. 300 IN NSEC insecure.example. A RRSIG NSEC");
+  assert_eq!(validator.validate(&question, &test_response(Rcode::NoError, answers.clone(), proof)).unwrap(), Security::Secure);
+  assert!(validator.validate(&question, &test_response(Rcode::NoError, answers, Vec::new())).is_err());
+  // NSEC3 matches names by their hash.
+  let hash = BASE32HEX_NOPAD.encode(&nsec3_hash(&"test".parse().unwrap(), 1, 0, &[]).unwrap()).to_ascii_lowercase();
+  let denial = test.sign(&format!(
+    "test. 300 IN SOA ns.test. hostmaster.test. 1 7200 3600 1209600 300
+    {hash}.test. 300 IN NSEC3 1 0 0 - {hash} SOA NS DNSKEY NSEC3PARAM RRSIG"
+  ));
+  let response = test_response(Rcode::NoError, Vec::new(), denial);
+  assert_eq!(validator.validate(&test_question("test", RecordType::MX), &response).unwrap(), Security::Secure);
+  assert!(validator.validate(&test_question("test", RecordType::SOA), &response).is_err());
+}
+
+#[test]
+fn test_validate_cname() {
+  let (validator, example, _) = test_hierarchy();
+  let question = test_question("alias.example", RecordType::A);
+  let mut answers = example.sign("alias.example. 300 IN CNAME www.example.\nwww.example. 300 IN A 192.0.2.1");
+  // The chain is followed even when the CNAME comes after its RRSIG.
+  answers.swap(0, 1);
+  assert_eq!(answers[0].rtype(), RecordType::RRSIG);
+  assert_eq!(validator.validate(&question, &test_response(Rcode::NoError, answers, Vec::new())).unwrap(), Security::Secure);
+}
+
+#[test]
+fn test_validate_caches_keys() {
+  let (validator, example, _) = test_hierarchy();
+  let question = test_question("www.example", RecordType::A);
+  let response = test_response(Rcode::NoError, example.sign("www.example. 300 IN A 192.0.2.1"), Vec::new());
+  assert_eq!(validator.validate(&question, &response).unwrap(), Security::Secure);
+  let queries = validator.resolver.queries.get();
+  assert!(queries > 0);
+  assert_eq!(validator.validate(&question, &response).unwrap(), Security::Secure);
+  assert_eq!(validator.resolver.queries.get(), queries);
+  // Keys are looked up again once their TTL has run out.
+  assert!(validator.keys.borrow().values().all(|cached| cached.expires <= now() + 3600));
+  validator.keys.borrow_mut().values_mut().for_each(|cached| cached.expires = now());
+  assert_eq!(validator.validate(&question, &response).unwrap(), Security::Secure);
+  assert_eq!(validator.resolver.queries.get(), 2 * queries);
+}
@@ -0,0 +1,209 @@
+#![allow(dead_code)]
+//! Records the server answers for itself, loaded from master files.
+use std::str::FromStr;
+
+use crate::{
+  error::DnsError,
+  header::Rcode,
+  name::Name,
+  record::{DnsClass, Question, RData, RecordType, ResourceRecord},
+};
+
+/// Local zone data. Names under the owner of an SOA record belong to that zone, and
+/// are answered authoritatively, with NXDOMAIN for names it does not hold.
+#[derive(Debug, Clone, Default)]
+pub struct Zone {
+  records: Vec<ResourceRecord>,
+}
+
+/// The records and rcode for one question.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Answer {
+  pub rcode: Rcode,
+  pub answers: Vec<ResourceRecord>,
+  pub authorities: Vec<ResourceRecord>,
+  pub additionals: Vec<ResourceRecord>,
+}
+
+impl Zone {
+  pub fn new() -> Self {
+    Self::default()
+  }
+  pub fn records(&self) -> &[ResourceRecord] {
+    &self.records
+  }
+  pub fn is_empty(&self) -> bool {
+    self.records.is_empty()
+  }
+
+  /// The SOA record of the closest zone holding `name`.
+  fn soa(&self, name: &Name) -> Option<&ResourceRecord> {
+    self
+      .records
+      .iter()
+      .filter(|record| record.rtype() == RecordType::SOA && name.is_subdomain_of(&record.name))
+      .max_by_key(|record| record.name.labels().len())
+  }
+
+  fn matching<'a>(&'a self, name: &'a Name, rtype: RecordType, class: DnsClass) -> impl Iterator<Item = &'a ResourceRecord> + 'a {
+    self.records.iter().filter(move |record| {
+      record.name == *name && (record.rtype() == rtype || rtype == RecordType::ANY) && (record.class == class || class == DnsClass::ANY)
+    })
+  }
+
+  /// `None` unless `question` falls in one of the zones or names a record held here.
+  /// A CNAME is returned for any type it does not itself hold, but is not followed.
+  pub fn lookup(&self, question: &Question) -> Option<Answer> {
+    let name = &question.name;
+    let soa = self.soa(name);
+    if soa.is_none() && !self.records.iter().any(|record| record.name == *name) {
+      return None;
+    }
+    let mut answer = Answer::default();
+    answer.answers.extend(self.matching(name, question.qtype, question.qclass).cloned());
+    if answer.answers.is_empty() && question.qtype != RecordType::CNAME {
+      answer.answers.extend(self.matching(name, RecordType::CNAME, question.qclass).cloned());
+    }
+    if answer.answers.is_empty() {
+      if !self.records.iter().any(|record| record.name.is_subdomain_of(name)) {
+        answer.rcode = Rcode::NXDomain;
+      }
+      answer.authorities.extend(soa.cloned());
+    }
+    answer.additionals = self.additionals(&answer.answers);
+    Some(answer)
+  }
+
+  /// Addresses of the in-zone targets of SVCB and HTTPS answers (RFC 9460 section
+  /// 4.2), so clients can connect without another round trip.
+  fn additionals(&self, answers: &[ResourceRecord]) -> Vec<ResourceRecord> {
+    let mut additionals = Vec::new();
+    for answer in answers {
+      let (RData::SVCB { priority, target, .. } | RData::HTTPS { priority, target, .. }) = &answer.rdata else {
+        continue;
+      };
+      // In ServiceMode, `.` stands for the owner name itself.
+      let target = if target.is_root() && *priority != 0 { &answer.name } else { target };
+      for rtype in [RecordType::A, RecordType::AAAA] {
+        for record in self.matching(target, rtype, answer.class) {
+          if !additionals.contains(record) {
+            additionals.push(record.clone());
+          }
+        }
+      }
+    }
+    additionals
+  }
+}
+
+impl Extend<ResourceRecord> for Zone {
+  fn extend<T: IntoIterator<Item = ResourceRecord>>(&mut self, records: T) {
+    self.records.extend(records);
+  }
+}
+
+impl FromIterator<ResourceRecord> for Zone {
+  fn from_iter<T: IntoIterator<Item = ResourceRecord>>(records: T) -> Self {
+    Self { records: records.into_iter().collect() }
+  }
+}
+
+/// How many parentheses `line` leaves open, ignoring those in strings and comments.
+fn open_parentheses(line: &str) -> isize {
+  let (mut open, mut quoted, mut escaped) = (0, false, false);
+  for c in line.chars() {
+    match c {
+      _ if escaped => escaped = false,
+      '\\' => escaped = true,
+      '"' => quoted = !quoted,
+      _ if quoted => {}
+      ';' => break,
+      '(' => open += 1,
+      ')' => open -= 1,
+      _ => {}
+    }
+  }
+  open
+}
+
+/// A master file with one record to a line, in the format `ResourceRecord` parses,
+/// where parentheses let a record continue over several lines. Every owner name is
+/// written out in full; directives such as `$ORIGIN` are not supported.
+impl FromStr for Zone {
+  type Err = DnsError;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let mut zone = Zone::new();
+    let mut record = String::new();
+    let (mut open, mut first_line) = (0, 0);
+    for (i, line) in s.lines().enumerate() {
+      if open == 0 {
+        first_line = i + 1;
+        let text = line.trim_start();
+        if text.is_empty() || text.starts_with(';') {
+          continue;
+        }
+        if text.starts_with('$') {
+          return Err(DnsError::Syntax(format!("line {first_line}: directives are not supported")));
+        }
+      }
+      record.push_str(line);
+      record.push('\n');
+      open += open_parentheses(line);
+      if open <= 0 {
+        let parsed = record.parse().map_err(|e| DnsError::Syntax(format!("line {first_line}: {e}")))?;
+        zone.records.push(parsed);
+        record.clear();
+        open = 0;
+      }
+    }
+    if open > 0 {
+      return Err(DnsError::Syntax(format!("line {first_line}: unbalanced parentheses")));
+    }
+    Ok(zone)
+  }
+}
+
+#[cfg(test)]
+const EXAMPLE: &str = "
+; A zone with HTTPS records.
+example.com. 3600 IN SOA ns1.example.com. hostmaster.example.com. (
+  2024010101 ; serial
+  7200 3600 1209600 300 )
+example.com. 3600 IN NS ns1.example.com.
+example.com. 300 IN HTTPS 1 . alpn=h2,h3 ipv4hint=192.0.2.1
+example.com. 300 IN A 192.0.2.1
+www.example.com. 300 IN CNAME example.com.
+_8443._https.api.example.com. 300 IN HTTPS 1 svc.example.com. port=8443 mandatory=port
+svc.example.com. 300 IN AAAA 2001:db8::1
+";
+
+#[test]
+fn test_zone_from_str() {
+  let zone: Zone = EXAMPLE.parse().unwrap();
+  assert_eq!(zone.records().len(), 7);
+  assert_eq!(zone.records()[0].rdata, RData::from_text(RecordType::SOA, "ns1.example.com. hostmaster.example.com. 2024010101 7200 3600 1209600 300").unwrap());
+  assert!(matches!("$ORIGIN example.com.".parse::<Zone>(), Err(DnsError::Syntax(_))));
+  let error = "example.com. 60 IN A 192.0.2.1\nexample.com. 60 IN A (\n192.0.2.1".parse::<Zone>().unwrap_err();
+  assert_eq!(error.to_string(), "syntax error: line 2: unbalanced parentheses");
+}
+
+#[test]
+fn test_zone_lookup() {
+  let zone: Zone = EXAMPLE.parse().unwrap();
+  let question = |name: &str, qtype| Question::new(name.parse().unwrap(), qtype, DnsClass::IN);
+  let answer = zone.lookup(&question("example.com", RecordType::HTTPS)).unwrap();
+  assert_eq!(answer.rcode, Rcode::NoError);
+  assert_eq!(answer.answers.len(), 1);
+  assert_eq!(answer.additionals, zone.lookup(&question("example.com", RecordType::A)).unwrap().answers);
+  let answer = zone.lookup(&question("_8443._https.api.example.com", RecordType::HTTPS)).unwrap();
+  assert_eq!(answer.additionals[0].rtype(), RecordType::AAAA);
+  let answer = zone.lookup(&question("www.example.com", RecordType::HTTPS)).unwrap();
+  assert_eq!(answer.answers[0].rtype(), RecordType::CNAME);
+  let answer = zone.lookup(&question("example.com", RecordType::MX)).unwrap();
+  assert_eq!((answer.rcode, answer.answers.len()), (Rcode::NoError, 0));
+  assert_eq!(answer.authorities[0].rtype(), RecordType::SOA);
+  // An empty non-terminal exists, so it has no data rather than no name.
+  assert_eq!(zone.lookup(&question("_https.api.example.com", RecordType::A)).unwrap().rcode, Rcode::NoError);
+  assert_eq!(zone.lookup(&question("nope.example.com", RecordType::A)).unwrap().rcode, Rcode::NXDomain);
+  assert!(zone.lookup(&question("example.net", RecordType::A)).is_none());
+}